@@ -0,0 +1,122 @@
+//! Redirect throughput through the whole router, against an in-memory database
+
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{Request, StatusCode},
+    Router,
+};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use tokio::{runtime::Runtime, task::JoinSet};
+use tower::ServiceExt;
+use tracing_subscriber::reload;
+use url_shortener::{
+    config::{Config, LiveConfig},
+    db,
+    usage::{self, UsageQueue, UsageTally},
+    AppState,
+};
+
+/// Requests sent at once in each iteration
+const CONCURRENCY: u64 = 64;
+
+async fn app(name: &str, flush_interval: u64) -> Router {
+    let mut config = Config {
+        // a shared cache lets the write and read pools see the same in-memory database
+        database_url: format!("file:{}?mode=memory&cache=shared", name),
+        ..Config::default()
+    };
+    config.usage_counts.flush_interval = flush_interval;
+
+    let pool = db::write_pool(&config.database_url, &config.pool);
+    db::run_migrations(&pool).await.unwrap();
+    let read_pool = db::read_pool(&config.database_url, &config.pool);
+
+    // nothing reloads the config here, so the log filter is never installed
+    let (_, log_filter) = reload::Layer::new(config.env_filter());
+    let live_config = Arc::new(LiveConfig::new(config.clone(), log_filter));
+
+    let usage = Arc::new(UsageTally::new(config.usage_counts.shards));
+    let (usage_queue, usage_events) = UsageQueue::new(&config.usage_counts);
+    tokio::spawn(usage::flush_every(
+        usage_events,
+        usage.clone(),
+        pool.clone(),
+        config.usage_counts.clone(),
+        None,
+        live_config.clone(),
+    ));
+
+    let state = AppState {
+        pool,
+        read_pool,
+        config: live_config,
+        scan_guard: Arc::default(),
+        bans: Arc::default(),
+        redirect_limiter: Arc::default(),
+        cache: None,
+        usage,
+        usage_queue,
+        notifier: None,
+        quotas: Arc::default(),
+        unfurls: Arc::default(),
+        previews: Arc::default(),
+    };
+    url_shortener::router(state, &config)
+}
+
+fn request(method: &str, uri: &str, body: &'static str) -> Request<Body> {
+    let mut req = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(body))
+        .unwrap();
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 4000))));
+    req
+}
+
+async fn shorten(app: &Router) {
+    let body = r#"{"url": "https://example.com/", "slug": "bench"}"#;
+    let res = app
+        .clone()
+        .oneshot(request("POST", "/api/v1/urls", body))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+fn redirect(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    // the pools need a runtime to close their connections when they are dropped
+    let _guard = rt.enter();
+
+    let mut group = c.benchmark_group("redirect");
+    group.throughput(Throughput::Elements(CONCURRENCY));
+    for (name, flush_interval) in [("batched counts", 1000), ("count every click", 0)] {
+        let app = rt.block_on(async {
+            let app = app(&name.replace(' ', "-"), flush_interval).await;
+            shorten(&app).await;
+            app
+        });
+
+        group.bench_function(name, |b| {
+            b.to_async(&rt).iter(|| async {
+                let mut requests = JoinSet::new();
+                for _ in 0..CONCURRENCY {
+                    requests.spawn(app.clone().oneshot(request("GET", "/bench", "")));
+                }
+                while let Some(res) = requests.join_next().await {
+                    assert_eq!(res.unwrap().unwrap().status(), StatusCode::SEE_OTHER);
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, redirect);
+criterion_main!(benches);
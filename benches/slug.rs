@@ -0,0 +1,37 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use url_shortener::{config::Config, profanity, slug};
+
+fn generation(c: &mut Criterion) {
+    let config = Config::default();
+    let url = slug::normalize_url("https://example.com/some/long/path?with=a&query=string");
+
+    let mut group = c.benchmark_group("generate");
+    group.bench_function("nanoid", |b| b.iter(|| slug::gen_slug(false)));
+    group.bench_function("nanoid lowercase", |b| b.iter(|| slug::gen_slug(true)));
+    group.bench_function("hash", |b| {
+        b.iter(|| slug::hashed(black_box(&url), false).next())
+    });
+    group.bench_function("profanity check", |b| {
+        let slug = slug::gen_slug(false);
+        b.iter(|| profanity::is_profane(black_box(&slug), &config))
+    });
+    group.finish();
+}
+
+fn validation(c: &mut Criterion) {
+    let config = Config::default();
+
+    let mut group = c.benchmark_group("validate");
+    for slug in ["aB3-x_9QzK", "my-promo", "🦀rust", "API"] {
+        group.bench_function(slug, |b| {
+            b.iter(|| {
+                let slug = slug::normalize(black_box(slug), &config);
+                slug::validate(&slug, &config).is_ok()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, generation, validation);
+criterion_main!(benches);
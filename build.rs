@@ -0,0 +1,4 @@
+// `embed_migrations!` reads the migrations at compile time, so rebuild whenever they change
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
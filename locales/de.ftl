@@ -0,0 +1,71 @@
+## Fehler, nach ihrem `code` benannt.  Der Wert ist der Titel, `.detail` erklärt ihn.
+
+slug_occupied = Kürzel vergeben
+    .detail = Dieses Kürzel wird bereits verwendet.
+slug_reserved = Kürzel reserviert
+    .detail = Dieses Kürzel ist vom Server reserviert.
+invalid_slug = Ungültiges Kürzel
+    .detail = Dieses Kürzel enthält Zeichen, die nicht erlaubt sind.
+slug_too_many_tries = Kein freies Kürzel
+    .detail = Es wurde kein freies zufälliges Kürzel gefunden, bitte später erneut versuchen.
+database_error = Datenbankfehler
+    .detail = Bei der Datenbank ist ein Fehler aufgetreten.
+invalid_json = Ungültiges JSON
+    .detail = Das JSON konnte nicht gelesen werden: { $error }
+invalid_url = Ungültige URL
+    .detail = Die URL ist nicht gültig: { $error }
+invalid_cache_control = Ungültiges Cache-Control
+    .detail = Der Wert von cache_control ist kein gültiger Header-Wert.
+invalid_email = Ungültige E-Mail-Adresse
+    .detail = owner_email ist keine gültige E-Mail-Adresse.
+invalid_cursor = Ungültiger Cursor
+    .detail = Der Cursor für die Seitenaufteilung ist nicht gültig.
+unauthorized = Nicht berechtigt
+    .detail = Ein gültiges Admin-Token wird benötigt.
+feature_disabled = Funktion deaktiviert
+    .detail = { $feature } sind auf diesem Server nicht aktiviert.
+not_found = Nicht gefunden
+    .detail = Dieser Kurzlink existiert nicht.
+expired = Abgelaufen
+    .detail = Dieser Link ist abgelaufen.
+rate_limited = Zu viele Anfragen
+    .detail = Zu viele Anfragen, bitte später erneut versuchen.
+banned = Gesperrt
+    .detail = Deine Adresse wurde vorübergehend gesperrt.
+blocked_destination = Ziel gesperrt
+    .detail = Links zu dieser Seite sind nicht erlaubt.
+overloaded = Überlastet
+    .detail = Der Server ist ausgelastet, bitte später erneut versuchen.
+link_disabled = Link deaktiviert
+    .detail = Dieser Link wurde deaktiviert.
+stale_claim = Veraltete Übernahme
+    .detail = Dieses Übernahme-Token ist abgelaufen, oder der Link hat seitdem den Besitzer gewechselt.
+quota_exceeded = Kontingent aufgebraucht
+    .detail = Dein Kontingent ({ $quota }) ist aufgebraucht.
+likely_spam = Vermutlich Spam
+    .detail = Diese URL sieht nach Spam aus.
+scheme_not_allowed = Schema nicht erlaubt
+    .detail = Links mit diesem URL-Schema sind nicht erlaubt.
+invalid_campaign = Ungültige Kampagne
+    .detail = Kampagnennamen müssen 1 bis 100 Zeichen lang sein und dürfen keine Steuerzeichen enthalten.
+campaign_exists = Kampagne existiert
+    .detail = Eine Kampagne mit diesem Namen existiert bereits.
+invalid_template = Ungültige Vorlage
+    .detail = Platzhalter müssen lückenlos ab {"{"}1{"}"} nummeriert sein und dürfen nicht im Host stehen.
+preview_unavailable = Vorschau nicht verfügbar
+    .detail = Von diesem Link konnte keine Vorschau erstellt werden, bitte später erneut versuchen.
+invalid_page = Ungültige Seite
+    .detail = { $reason }
+read_only = Nur lesbar
+    .detail = Der Server ist wegen Wartungsarbeiten nur lesbar, bitte später erneut versuchen.
+invalid_description = Ungültige Beschreibung
+    .detail = Beschreibungen dürfen höchstens 1000 Zeichen lang sein.
+invalid_message = Ungültige Nachricht
+    .detail = unavailable_message darf höchstens 1000 Zeichen lang sein.
+unsupported_format = Nicht unterstütztes Format
+    .detail = Nur das Format json wird unterstützt.
+
+## Die Seite für Links zu anderen Apps, etwa `mailto:`
+
+open-title = Link öffnen
+open-text = Dieser Link öffnet eine { $scheme }-Adresse, wodurch eine andere App starten kann:
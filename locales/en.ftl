@@ -0,0 +1,72 @@
+## Errors, named by their `code`.  The value is the title and `.detail` explains it.
+
+slug_occupied = Slug occupied
+    .detail = This slug is already in use.
+slug_reserved = Slug reserved
+    .detail = This slug is reserved by the server.
+invalid_slug = Invalid slug
+    .detail = This slug contains characters that are not allowed.
+slug_too_many_tries = No free slug
+    .detail = Unable to find a random slug to use, try again later.
+database_error = Database error
+    .detail = There was an error with the database.
+invalid_json = Invalid json
+    .detail = Error parsing json: { $error }
+invalid_url = Invalid url
+    .detail = The url is not valid: { $error }
+invalid_cache_control = Invalid Cache-Control
+    .detail = The cache_control value is not a valid header value.
+invalid_email = Invalid email
+    .detail = The owner_email is not a valid email address.
+invalid_cursor = Invalid cursor
+    .detail = The pagination cursor is not valid.
+unauthorized = Unauthorized
+    .detail = A valid admin token is required.
+feature_disabled = Feature disabled
+    .detail = { $feature } are not enabled on this server.
+not_found = Not found
+    .detail = Shortened URL not found.
+expired = Expired
+    .detail = This link has expired.
+rate_limited = Too many requests
+    .detail = Too many requests, try again later.
+banned = Banned
+    .detail = Your address has been temporarily banned.
+blocked_destination = Blocked destination
+    .detail = Links to this site are not allowed.
+overloaded = Overloaded
+    .detail = The server is too busy, try again later.
+link_disabled = Link disabled
+    .detail = This link has been disabled.
+stale_claim = Stale claim
+    .detail = This claim token has expired, or the link has changed owner since it was made.
+quota_exceeded = Quota exceeded
+    .detail = Your { $quota } quota has been used up.
+likely_spam = Likely spam
+    .detail = This url looks like spam.
+scheme_not_allowed = Scheme not allowed
+    .detail = Links with this url scheme are not allowed.
+invalid_campaign = Invalid campaign
+    .detail = Campaign names must be 1 to 100 characters, without control characters.
+campaign_exists = Campaign exists
+    .detail = A campaign with this name already exists.
+invalid_template = Invalid template
+    .detail = Url placeholders must go from {"{"}1{"}"} up without gaps, and can't be in the host.
+preview_unavailable = Preview unavailable
+    .detail = A preview of this link could not be taken, try again later.
+invalid_page = Invalid page
+    .detail = { $reason }
+read_only = Read-only
+    .detail = The server is in read-only mode for maintenance, try again later.
+invalid_description = Invalid description
+    .detail = Descriptions can be at most 1000 characters.
+invalid_message = Invalid message
+    .detail = The unavailable_message can be at most 1000 characters.
+unsupported_format = Unsupported format
+    .detail = Only the json format is supported.
+
+## The page for links to other apps, such as `mailto:`
+
+open-title = Open link
+# $scheme is already in bold
+open-text = This link opens a { $scheme } address, which may start another app:
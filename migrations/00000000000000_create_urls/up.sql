@@ -1,6 +1,5 @@
-DROP TABLE IF EXISTS urls;
-
-CREATE TABLE urls (
+-- `IF NOT EXISTS` so that databases created from the old `schema.sql` are picked up as is
+CREATE TABLE IF NOT EXISTS urls (
     slug TEXT PRIMARY KEY NOT NULL UNIQUE,
     url TEXT NOT NULL,
     author_ip TEXT NOT NULL,
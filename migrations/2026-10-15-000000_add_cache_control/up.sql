@@ -0,0 +1 @@
+ALTER TABLE urls ADD COLUMN cache_control TEXT;
@@ -0,0 +1,5 @@
+-- Hands out ids for the sequential slug strategies.  AUTOINCREMENT means an id is never handed
+-- out twice, even though rows are deleted as soon as they're inserted.
+CREATE TABLE slug_sequence (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL
+);
@@ -0,0 +1,2 @@
+DROP INDEX urls_author_ip;
+DROP INDEX urls_slug_nocase;
@@ -0,0 +1,4 @@
+-- `slug` is already unique as the primary key, but that index can't be used for the
+-- `COLLATE NOCASE` comparisons made when slugs are case-insensitive
+CREATE INDEX urls_slug_nocase ON urls (slug COLLATE NOCASE);
+CREATE INDEX urls_author_ip ON urls (author_ip);
@@ -0,0 +1,2 @@
+DROP TABLE reports;
+ALTER TABLE urls DROP COLUMN disabled;
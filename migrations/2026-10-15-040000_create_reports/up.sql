@@ -0,0 +1,13 @@
+ALTER TABLE urls ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT 0;
+
+CREATE TABLE reports (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    slug TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    reporter_ip TEXT NOT NULL,
+    -- seconds since the unix epoch
+    created_at BIGINT NOT NULL
+);
+
+-- each visitor can only report a link once
+CREATE UNIQUE INDEX reports_slug_reporter ON reports (slug, reporter_ip);
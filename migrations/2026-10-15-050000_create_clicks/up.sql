@@ -0,0 +1,14 @@
+CREATE TABLE clicks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    slug TEXT NOT NULL,
+    -- seconds since the unix epoch
+    clicked_at BIGINT NOT NULL,
+    -- only the host of the `Referer`
+    referrer TEXT,
+    user_agent TEXT,
+    ip TEXT,
+    -- ISO 3166 code from the configured header, if there is one
+    country TEXT
+);
+
+CREATE INDEX clicks_slug_clicked_at ON clicks (slug, clicked_at);
@@ -0,0 +1,2 @@
+DROP TABLE notification_preferences;
+ALTER TABLE urls DROP COLUMN owner_email;
@@ -0,0 +1,9 @@
+-- who is emailed about the link, if anyone
+ALTER TABLE urls ADD COLUMN owner_email TEXT;
+
+-- which emails each owner wants, anyone without a row gets all of them
+CREATE TABLE notification_preferences (
+    email TEXT PRIMARY KEY NOT NULL,
+    milestones BOOLEAN NOT NULL DEFAULT 1,
+    moderation BOOLEAN NOT NULL DEFAULT 1
+);
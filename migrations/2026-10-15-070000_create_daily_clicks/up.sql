@@ -0,0 +1,8 @@
+-- clicks older than the retention period, rolled up into a count per day
+CREATE TABLE daily_clicks (
+    slug TEXT NOT NULL,
+    -- `YYYY-MM-DD`, UTC
+    day TEXT NOT NULL,
+    clicks BIGINT NOT NULL,
+    PRIMARY KEY (slug, day)
+);
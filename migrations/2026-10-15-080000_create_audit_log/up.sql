@@ -0,0 +1,13 @@
+CREATE TABLE audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    -- seconds since the unix epoch
+    created_at BIGINT NOT NULL,
+    slug TEXT NOT NULL,
+    -- such as `transfer`, see `AuditAction`
+    action TEXT NOT NULL,
+    -- who made the change: `admin`, or an owner's email
+    actor TEXT NOT NULL,
+    detail TEXT
+);
+
+CREATE INDEX audit_log_slug ON audit_log (slug);
@@ -0,0 +1,3 @@
+DROP INDEX urls_api_key;
+
+ALTER TABLE urls DROP COLUMN api_key;
@@ -0,0 +1,4 @@
+-- the name of the API key a link was created with, which its quotas are counted against
+ALTER TABLE urls ADD COLUMN api_key TEXT;
+
+CREATE INDEX urls_api_key ON urls (api_key);
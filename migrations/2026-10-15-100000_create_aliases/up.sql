@@ -0,0 +1,8 @@
+-- extra slugs that lead to the same url as `slug`
+CREATE TABLE aliases (
+    alias TEXT PRIMARY KEY NOT NULL,
+    slug TEXT NOT NULL
+);
+
+CREATE INDEX aliases_slug ON aliases (slug);
+CREATE INDEX aliases_alias_nocase ON aliases (alias COLLATE NOCASE);
@@ -0,0 +1,5 @@
+DROP INDEX urls_campaign;
+
+ALTER TABLE urls DROP COLUMN campaign;
+
+DROP TABLE campaigns;
@@ -0,0 +1,10 @@
+CREATE TABLE campaigns (
+    name TEXT PRIMARY KEY NOT NULL,
+    -- in seconds since the unix epoch
+    created_at BIGINT NOT NULL
+);
+
+-- the campaign a link is grouped into, if any
+ALTER TABLE urls ADD COLUMN campaign TEXT;
+
+CREATE INDEX urls_campaign ON urls (campaign);
@@ -0,0 +1,3 @@
+DROP TABLE page_links;
+
+DROP TABLE pages;
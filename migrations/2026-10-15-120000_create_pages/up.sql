@@ -0,0 +1,21 @@
+-- landing pages listing several links, shown at their slug instead of redirecting
+CREATE TABLE pages (
+    slug TEXT PRIMARY KEY NOT NULL,
+    title TEXT NOT NULL,
+    description TEXT,
+    -- in seconds since the unix epoch
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX pages_slug_nocase ON pages (slug COLLATE NOCASE);
+
+CREATE TABLE page_links (
+    id INTEGER PRIMARY KEY NOT NULL,
+    page TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    url TEXT NOT NULL,
+    clicks INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX page_links_page ON page_links (page, position);
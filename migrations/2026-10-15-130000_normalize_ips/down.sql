@@ -0,0 +1 @@
+-- the mapped form isn't needed by anything, so it isn't restored
@@ -0,0 +1,10 @@
+-- IPv4 clients of a dual-stack listener were stored as IPv4-mapped IPv6 addresses
+-- (`::ffff:192.0.2.1`), they are now stored as plain IPv4 (`192.0.2.1`)
+UPDATE urls SET author_ip = substr(author_ip, 8)
+WHERE author_ip LIKE '::ffff:%.%' AND substr(author_ip, 8) NOT LIKE '%:%';
+
+UPDATE reports SET reporter_ip = substr(reporter_ip, 8)
+WHERE reporter_ip LIKE '::ffff:%.%' AND substr(reporter_ip, 8) NOT LIKE '%:%';
+
+UPDATE clicks SET ip = substr(ip, 8)
+WHERE ip LIKE '::ffff:%.%' AND substr(ip, 8) NOT LIKE '%:%';
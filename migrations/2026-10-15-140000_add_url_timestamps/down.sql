@@ -0,0 +1,5 @@
+DROP INDEX urls_created_at;
+
+ALTER TABLE urls DROP COLUMN updated_at;
+
+ALTER TABLE urls DROP COLUMN created_at;
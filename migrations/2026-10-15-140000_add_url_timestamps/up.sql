@@ -0,0 +1,10 @@
+-- in seconds since the unix epoch, `updated_at` is when its settings, owner or campaign last
+-- changed.  When links were made before this isn't known, so they get the time of the migration.
+ALTER TABLE urls ADD COLUMN created_at BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE urls ADD COLUMN updated_at BIGINT NOT NULL DEFAULT 0;
+
+UPDATE urls SET
+    created_at = CAST(strftime('%s', 'now') AS INTEGER),
+    updated_at = CAST(strftime('%s', 'now') AS INTEGER);
+
+CREATE INDEX urls_created_at ON urls (created_at);
@@ -0,0 +1,2 @@
+-- free text so that people remember what a link was for, it is never shown to visitors
+ALTER TABLE urls ADD COLUMN description TEXT;
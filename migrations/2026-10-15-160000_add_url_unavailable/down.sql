@@ -0,0 +1,3 @@
+ALTER TABLE urls DROP COLUMN unavailable_url;
+
+ALTER TABLE urls DROP COLUMN unavailable_message;
@@ -0,0 +1,3 @@
+-- shown (or redirected to) instead of the usual error when the link is disabled or over its limit
+ALTER TABLE urls ADD COLUMN unavailable_message TEXT;
+ALTER TABLE urls ADD COLUMN unavailable_url TEXT;
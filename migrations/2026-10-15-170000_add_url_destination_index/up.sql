@@ -0,0 +1,2 @@
+-- the bookmarklet looks for an existing link to the page before making one
+CREATE INDEX urls_url ON urls (url);
@@ -0,0 +1,20 @@
+//! A small web UI over the admin API.  The page itself holds no data, it asks for the admin
+//! token and sends it with every API request, so it is protected the same way the API is.
+
+use axum::{
+    http::header,
+    response::{Html, IntoResponse},
+};
+
+const DASHBOARD: &str = include_str!("admin.html");
+
+pub async fn dashboard() -> impl IntoResponse {
+    (
+        // the token is kept in session storage, so the page shouldn't be cached or framed
+        [
+            (header::CACHE_CONTROL, "no-store"),
+            (header::X_FRAME_OPTIONS, "DENY"),
+        ],
+        Html(DASHBOARD),
+    )
+}
@@ -0,0 +1,93 @@
+//! Extra slugs for a link, so that `/launch` and `/june-launch` lead to the same place.  An
+//! alias has no state of its own: clicks, limits and stats all belong to the link it points at.
+
+use diesel::{
+    dsl::sql,
+    expression::BoxableExpression,
+    prelude::*,
+    sql_types::{Bool, Text},
+    sqlite::Sqlite,
+};
+use diesel_async::RunQueryDsl;
+
+use crate::{
+    cache::Cache,
+    db::Connection,
+    models::Url,
+    schema::{aliases, urls},
+    slug,
+};
+
+/// A filter matching `alias`, like [`slug::eq`] does for urls
+pub fn eq(
+    alias: String,
+    case_insensitive: bool,
+) -> Box<dyn BoxableExpression<aliases::table, Sqlite, SqlType = Bool>> {
+    if case_insensitive {
+        Box::new(
+            sql::<Bool>("aliases.alias = ")
+                .bind::<Text, _>(alias)
+                .sql(" COLLATE NOCASE"),
+        )
+    } else {
+        Box::new(aliases::alias.eq(alias))
+    }
+}
+
+/// Whether `alias` is already an alias
+pub async fn exists(
+    conn: &mut Connection,
+    alias: String,
+    case_insensitive: bool,
+) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(
+        aliases::table.filter(eq(alias, case_insensitive)),
+    ))
+    .get_result(conn)
+    .await
+}
+
+/// Find the url for a slug or one of its aliases
+pub async fn resolve(
+    conn: &mut Connection,
+    slug: String,
+    case_insensitive: bool,
+) -> QueryResult<Option<Url>> {
+    let url = urls::table
+        .filter(slug::eq(slug.clone(), case_insensitive))
+        .first::<Url>(conn)
+        .await
+        .optional()?;
+    if url.is_some() {
+        return Ok(url);
+    }
+
+    let Some(target) = aliases::table
+        .filter(eq(slug, case_insensitive))
+        .select(aliases::slug)
+        .first::<String>(conn)
+        .await
+        .optional()?
+    else {
+        return Ok(None);
+    };
+    urls::table.find(target).first::<Url>(conn).await.optional()
+}
+
+/// Every alias of `slug`
+pub async fn of(conn: &mut Connection, slug: &str) -> QueryResult<Vec<String>> {
+    aliases::table
+        .filter(aliases::slug.eq(slug))
+        .select(aliases::alias)
+        .order(aliases::alias.asc())
+        .load(conn)
+        .await
+}
+
+/// Drop a link and its aliases from the cache, since each alias is cached under its own slug
+pub async fn invalidate(cache: &Cache, slug: &str, aliases: &[String]) {
+    cache.invalidate(slug).await;
+    for alias in aliases {
+        cache.invalidate(alias).await;
+    }
+}
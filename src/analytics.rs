@@ -0,0 +1,317 @@
+//! Per-click records behind each link's stats: when it was followed, from where and by whom.
+//! Clicks are written along with the usage counts (see [`crate::usage`]), so they are batched
+//! the same way.  Clicks older than the retention period are rolled up into a count per day.
+
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+use axum::{
+    http::{header, HeaderMap},
+    response::{Html, IntoResponse},
+};
+use diesel::{
+    prelude::*,
+    sql_query,
+    sql_types::{BigInt, Text},
+};
+use diesel_async::{scoped_futures::ScopedFutureExt, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, warn};
+
+use crate::{
+    config::LiveConfig, db, error::UrlErr, etag::Version, models::NewClick, schema::clicks,
+    signed::now,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AnalyticsConfig {
+    /// Record every click, rather than only counting them
+    pub enabled: bool,
+    /// Request header holding the visitor's country, such as `CF-IPCountry` behind Cloudflare.
+    /// Only set this behind a proxy that always overwrites it.
+    pub country_header: Option<String>,
+    /// Let anyone see a link's stats, rather than only admins
+    pub public_stats: bool,
+    /// What is recorded about visitors who send `DNT: 1` or `Sec-GPC: 1`
+    pub opt_out: OptOutPolicy,
+    /// Days that each click is kept for, after which only the count for its day is.  `0` keeps
+    /// them forever.
+    pub retention_days: u64,
+}
+
+/// How clicks from visitors asking not to be tracked are recorded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OptOutPolicy {
+    /// Record the click with only its time, so it still shows up in the daily counts
+    #[default]
+    Anonymous,
+    /// Don't record the click at all, it is only added to the link's usage count
+    Skip,
+    /// Record everything, as for any other visitor
+    Ignore,
+}
+
+impl Default for AnalyticsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            country_header: None,
+            public_stats: true,
+            opt_out: OptOutPolicy::default(),
+            retention_days: 90,
+        }
+    }
+}
+
+/// The longest user agent that is kept, in characters
+const MAX_USER_AGENT: usize = 512;
+
+/// What is recorded about whoever followed a link
+#[derive(Debug, Clone)]
+pub struct Visitor {
+    pub ip: IpAddr,
+    pub referrer: Option<String>,
+    pub user_agent: Option<String>,
+    pub country: Option<String>,
+    /// Whether they sent `DNT: 1` or `Sec-GPC: 1`
+    pub opted_out: bool,
+}
+
+impl Visitor {
+    pub fn new(ip: IpAddr, headers: &HeaderMap, config: &AnalyticsConfig) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .filter(|v| !v.is_empty())
+        };
+
+        Self {
+            ip,
+            // the rest of the url can carry tokens and the like, so only the site is kept
+            referrer: header(header::REFERER.as_str())
+                .and_then(|r| url::Url::parse(r).ok())
+                .and_then(|r| r.host_str().map(str::to_lowercase)),
+            user_agent: header(header::USER_AGENT.as_str())
+                .map(|ua| ua.chars().take(MAX_USER_AGENT).collect()),
+            country: config
+                .country_header
+                .as_deref()
+                .and_then(header)
+                .map(str::to_uppercase)
+                // Cloudflare sends `XX` and `T1` for unknown countries and Tor
+                .filter(|c| c.len() == 2 && c != "XX" && c != "T1"),
+            opted_out: header("dnt") == Some("1") || header("sec-gpc") == Some("1"),
+        }
+    }
+
+    /// The click to record for this visitor, if any
+    pub fn click(self, slug: &str, config: &AnalyticsConfig) -> Option<NewClick> {
+        if !config.enabled {
+            return None;
+        }
+        let anonymous = match (self.opted_out, config.opt_out) {
+            (true, OptOutPolicy::Skip) => return None,
+            (true, OptOutPolicy::Anonymous) => true,
+            _ => false,
+        };
+        let keep = |field: Option<String>| field.filter(|_| !anonymous);
+
+        Some(NewClick {
+            slug: slug.to_string(),
+            clicked_at: now() as i64,
+            referrer: keep(self.referrer),
+            user_agent: keep(self.user_agent),
+            ip: keep(Some(self.ip.to_string())),
+            // the country comes from their ip, so it goes too
+            country: keep(self.country),
+        })
+    }
+}
+
+const STATS_PAGE: &str = include_str!("stats.html");
+
+/// A page charting a link's stats, served at `/:slug/stats` so it can be shared.  It loads
+/// everything from the stats endpoint, which decides who may see them.
+pub async fn stats_page() -> impl IntoResponse {
+    Html(STATS_PAGE)
+}
+
+/// How many of the top referrers and countries are listed
+const TOP: i64 = 10;
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct Bucket {
+    /// The day (`YYYY-MM-DD`, UTC), referrer or country
+    #[diesel(sql_type = Text)]
+    pub key: String,
+    #[diesel(sql_type = BigInt)]
+    pub clicks: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    /// Clicks per day, oldest first.  Days without clicks are left out.
+    pub daily: Vec<Bucket>,
+    /// The sites that sent the most clicks, only counting the clicks that haven't been rolled up
+    pub referrers: Vec<Bucket>,
+    pub countries: Vec<Bucket>,
+}
+
+/// Stats for the clicks on `slug` since `since` (in seconds since the unix epoch)
+pub async fn stats(conn: &mut db::Connection, slug: &str, since: i64) -> QueryResult<Stats> {
+    let buckets = |key: &str, extra: &str| {
+        sql_query(format!(
+            "SELECT {0} AS key, COUNT(*) AS clicks FROM clicks \
+             WHERE slug = ? AND clicked_at >= ? AND {0} IS NOT NULL \
+             GROUP BY key {1}",
+            key, extra
+        ))
+        .bind::<Text, _>(slug.to_string())
+        .bind::<BigInt, _>(since)
+    };
+
+    let top = format!("ORDER BY clicks DESC LIMIT {}", TOP);
+    let daily = sql_query(
+        "SELECT key, SUM(clicks) AS clicks FROM ( \
+             SELECT date(clicked_at, 'unixepoch') AS key, COUNT(*) AS clicks FROM clicks \
+             WHERE slug = ? AND clicked_at >= ? GROUP BY key \
+             UNION ALL \
+             SELECT day AS key, clicks FROM daily_clicks \
+             WHERE slug = ? AND day >= date(?, 'unixepoch') \
+         ) GROUP BY key ORDER BY key",
+    )
+    .bind::<Text, _>(slug.to_string())
+    .bind::<BigInt, _>(since)
+    .bind::<Text, _>(slug.to_string())
+    .bind::<BigInt, _>(since);
+
+    Ok(Stats {
+        daily: daily.load(conn).await?,
+        referrers: buckets("referrer", &top).load(conn).await?,
+        countries: buckets("country", &top).load(conn).await?,
+    })
+}
+
+/// What [`stats`] for `slug` since `since` depends on, for its ETag
+pub async fn version(conn: &mut db::Connection, slug: &str, since: i64) -> QueryResult<String> {
+    let version = sql_query(
+        "SELECT COUNT(*) || ':' || IFNULL(MAX(id), 0) || ':' || \
+         (SELECT IFNULL(SUM(clicks), 0) FROM daily_clicks WHERE slug = ?) AS version \
+         FROM clicks WHERE slug = ? AND clicked_at >= ?",
+    )
+    .bind::<Text, _>(slug.to_string())
+    .bind::<Text, _>(slug.to_string())
+    .bind::<BigInt, _>(since)
+    .get_result::<Version>(conn)
+    .await?;
+    // the first day counted moves along with `since`
+    Ok(format!("{}:{}", version.version, since / DAY as i64))
+}
+
+/// What the stats of `campaign` since `since` depend on, for their ETag
+pub async fn campaign_version(
+    conn: &mut db::Connection,
+    campaign: &str,
+    since: i64,
+) -> QueryResult<String> {
+    let version = sql_query(
+        "SELECT COUNT(*) || ':' || IFNULL(MAX(id), 0) || ':' || \
+         (SELECT COUNT(*) || ':' || IFNULL(MAX(updated_at), 0) || ':' || \
+          IFNULL(SUM(updated_at), 0) || ':' || IFNULL(SUM(usage_count), 0) \
+          FROM urls WHERE campaign = ?) || ':' || \
+         (SELECT IFNULL(SUM(clicks), 0) FROM daily_clicks \
+          WHERE slug IN (SELECT slug FROM urls WHERE campaign = ?)) AS version \
+         FROM clicks WHERE slug IN (SELECT slug FROM urls WHERE campaign = ?) \
+         AND clicked_at >= ?",
+    )
+    .bind::<Text, _>(campaign.to_string())
+    .bind::<Text, _>(campaign.to_string())
+    .bind::<Text, _>(campaign.to_string())
+    .bind::<BigInt, _>(since)
+    .get_result::<Version>(conn)
+    .await?;
+    Ok(format!("{}:{}", version.version, since / DAY as i64))
+}
+
+/// Clicks per day since `since` on every link in `campaign`, added together
+pub async fn campaign_daily(
+    conn: &mut db::Connection,
+    campaign: &str,
+    since: i64,
+) -> QueryResult<Vec<Bucket>> {
+    sql_query(
+        "SELECT key, SUM(clicks) AS clicks FROM ( \
+             SELECT date(clicked_at, 'unixepoch') AS key, COUNT(*) AS clicks FROM clicks \
+             WHERE slug IN (SELECT slug FROM urls WHERE campaign = ?) AND clicked_at >= ? \
+             GROUP BY key \
+             UNION ALL \
+             SELECT day AS key, clicks FROM daily_clicks \
+             WHERE slug IN (SELECT slug FROM urls WHERE campaign = ?) \
+             AND day >= date(?, 'unixepoch') \
+         ) GROUP BY key ORDER BY key",
+    )
+    .bind::<Text, _>(campaign.to_string())
+    .bind::<BigInt, _>(since)
+    .bind::<Text, _>(campaign.to_string())
+    .bind::<BigInt, _>(since)
+    .load(conn)
+    .await
+}
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// How often old clicks are looked for
+const ROLL_UP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Roll up the clicks before `before` (in seconds since the unix epoch) into `daily_clicks`,
+/// returning how many were removed
+pub async fn roll_up(conn: &mut db::Connection, before: i64) -> QueryResult<usize> {
+    conn.immediate_transaction(|conn| {
+        async move {
+            sql_query(
+                "INSERT INTO daily_clicks (slug, day, clicks) \
+                 SELECT slug, date(clicked_at, 'unixepoch'), COUNT(*) FROM clicks \
+                 WHERE clicked_at < ? GROUP BY 1, 2 \
+                 ON CONFLICT (slug, day) DO UPDATE SET clicks = clicks + excluded.clicks",
+            )
+            .bind::<BigInt, _>(before)
+            .execute(conn)
+            .await?;
+            diesel::delete(clicks::table.filter(clicks::clicked_at.lt(before)))
+                .execute(conn)
+                .await
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+/// Roll up clicks once they are older than `retention_days`, forever
+pub async fn roll_up_every(pool: db::Pool, config: Arc<LiveConfig>) {
+    let mut ticker = tokio::time::interval(ROLL_UP_INTERVAL);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+        let retention_days = config.get().analytics.retention_days;
+        if retention_days == 0 || config.is_read_only() {
+            continue;
+        }
+        // only whole days, so that a day is never split between the two tables for long
+        let before =
+            ((now().saturating_sub(retention_days.saturating_mul(DAY))) / DAY * DAY) as i64;
+
+        let removed = async {
+            let mut conn = db::get(&pool).await?;
+            Ok::<_, UrlErr>(roll_up(&mut conn, before).await?)
+        };
+        match removed.await {
+            Ok(0) => {}
+            Ok(removed) => debug!("Rolled up {} old clicks", removed),
+            Err(err) => warn!("Unable to roll up old clicks: {}", err.detail()),
+        }
+    }
+}
@@ -0,0 +1,12 @@
+//! The management API, every version is nested under its own prefix so that a new version
+//! can be added next to the old ones without breaking existing clients.
+
+use axum::Router;
+
+use crate::{AppState, LimitedBody};
+
+pub mod v1;
+
+pub fn router() -> Router<AppState, LimitedBody> {
+    Router::new().nest("/v1", v1::router())
+}
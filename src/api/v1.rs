@@ -0,0 +1,1084 @@
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::ErrorResponse,
+    routing::{delete, get, patch, post, put},
+    Json, Router, TypedHeader,
+};
+use diesel::{
+    dsl::sql,
+    prelude::*,
+    sql_query,
+    sql_types::{BigInt, Nullable},
+};
+use diesel_async::{scoped_futures::ScopedFutureExt, RunQueryDsl};
+use headers::{ContentType, IfNoneMatch};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{
+    alias,
+    analytics::{self, Bucket},
+    audit::{self, AuditAction},
+    auth::Admin,
+    ban::{Ban, BanReason, Bans},
+    cache::Cache,
+    campaign, check_description, check_destination, check_quota,
+    claim::Claim,
+    clamp_limit,
+    config::LiveConfig,
+    create_url,
+    db::{self, ReadPool},
+    destination,
+    error::UrlErr,
+    etag::{self, Conditional, Version},
+    integrations,
+    ip::{self, ClientIp},
+    mail::{Notification, Notifier},
+    maintenance,
+    models::{
+        Alias, AuditEntry, Click, NewReport, NotificationPreferences, PublicUrl, Report, Url,
+    },
+    page,
+    pagination::{Page, PageParams},
+    quota::{Client, QuotaStatus, Quotas},
+    quota_status,
+    schema::{aliases, audit_log, clicks, daily_clicks, notification_preferences, reports, urls},
+    signed, slug, suggest, unavailable,
+    usage::{UsageAdjustment, UsageQueue, UsageTally},
+    AppState, LimitedBody, ShortReq,
+};
+
+pub fn router() -> Router<AppState, LimitedBody> {
+    Router::new()
+        .route("/urls", post(create).get(list))
+        .route("/urls/:slug", patch(update).delete(remove))
+        .route("/urls/:slug/stats", get(stats))
+        .route("/urls/:slug/clicks", get(list_clicks))
+        .route("/urls/:slug/usage", post(adjust_usage))
+        .route("/urls/:slug/aliases", get(list_aliases).post(add_alias))
+        .route("/urls/:slug/aliases/:alias", delete(remove_alias))
+        .route("/urls/:slug/claim", post(start_claim))
+        .route("/claim", post(redeem_claim))
+        .route("/audit", get(list_audit))
+        .route("/report/:slug", post(report))
+        .route("/reports", get(list_reports))
+        .route("/reports/:slug", delete(clear_reports))
+        .route("/check/:slug", get(check))
+        .route("/suggest", get(suggest))
+        .route("/quota", get(quota))
+        .route("/maintenance", get(maintenance::get).put(maintenance::set))
+        .route("/signed", post(sign))
+        .route("/notifications", get(preferences).put(set_preferences))
+        .route("/bans", get(list_bans))
+        .route("/bans/:ip", put(ban).delete(lift_ban))
+        .nest("/campaigns", campaign::router())
+        .nest("/pages", page::router())
+        .nest("/integrations", integrations::router())
+}
+
+/// Shorten a url, the body is either json ([`ShortReq`]) or just the url
+pub async fn create(
+    State(state): State<AppState>,
+    content_type: Option<TypedHeader<ContentType>>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+    body: String,
+) -> Result<(HeaderMap, Json<PublicUrl>), ErrorResponse> {
+    let mut req = match content_type {
+        Some(TypedHeader(ct)) if ct == ContentType::json() => {
+            serde_json::from_str::<ShortReq>(&body).map_err(UrlErr::JsonError)?
+        }
+        _ => ShortReq::from_url(body),
+    };
+
+    let config = state.config.get();
+    let client = Client::identify(&headers, ip, &config.quotas)?;
+    let status = check_quota(&state, &client, ip, &config).await?;
+
+    check_destination(&mut req, ip, &state.bans, &config)?;
+    if let Some(slug) = &mut req.slug {
+        *slug = slug::normalize(slug, &config);
+        slug::validate(slug, &config)?;
+    }
+    if let Some(cc) = &req.cache_control {
+        HeaderValue::from_str(cc).map_err(|_| UrlErr::InvalidCacheControl)?;
+    }
+    if let Some(email) = &req.owner_email {
+        email
+            .parse::<lettre::Address>()
+            .map_err(|_| UrlErr::InvalidEmail)?;
+    }
+
+    let author_ip = ip.to_string();
+    req.api_key = client.key_name().map(String::from);
+
+    let (entry, is_new) = create_url(req, author_ip, config.clone(), state.pool).await?;
+    let mut links = status.links.used as i64;
+    // handing back an existing link doesn't use up anything
+    if is_new {
+        state.quotas.record_creation(client.clone());
+        links += 1;
+    }
+    let status = state.quotas.status(&client, links, &config.quotas);
+    Ok((status.headers(), Json(entry.into())))
+}
+
+/// Where the caller stands against their quotas
+pub async fn quota(
+    State(read_pool): State<ReadPool>,
+    State(config): State<Arc<LiveConfig>>,
+    State(quotas): State<Arc<Quotas>>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+) -> Result<(HeaderMap, Json<QuotaStatus>), UrlErr> {
+    let config = config.get();
+    let client = Client::identify(&headers, ip, &config.quotas)?;
+    let status = quota_status(&client, read_pool, &quotas, &config).await?;
+    Ok((status.headers(), Json(status)))
+}
+
+/// Narrows [`list`] down to links made in a time range
+#[derive(Debug, Deserialize)]
+pub struct CreatedFilter {
+    /// Only links made after this, in seconds since the unix epoch
+    created_after: Option<i64>,
+    /// Only links made before this, in seconds since the unix epoch
+    created_before: Option<i64>,
+}
+
+/// List every url, oldest first
+pub async fn list(
+    _: Admin,
+    State(ReadPool(pool)): State<ReadPool>,
+    State(usage): State<Arc<UsageTally>>,
+    Query(params): Query<PageParams>,
+    Query(filter): Query<CreatedFilter>,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+) -> Result<Conditional<Page<Url>>, UrlErr> {
+    let limit = params.limit();
+    let after = params.after()?.unwrap_or(0);
+
+    // the page changes when a row in it does (which updates `updated_at`), or its count
+    let mut conn = db::get(&pool).await?;
+    let version = sql_query(
+        "SELECT COUNT(*) || ':' || IFNULL(MAX(updated_at), 0) || ':' || \
+         IFNULL(SUM(updated_at), 0) || ':' || IFNULL(SUM(usage_count), 0) AS version \
+         FROM (SELECT updated_at, usage_count FROM urls WHERE rowid > ? \
+         AND (? IS NULL OR created_at > ?) AND (? IS NULL OR created_at < ?) \
+         ORDER BY rowid LIMIT ?)",
+    )
+    .bind::<BigInt, _>(after)
+    .bind::<Nullable<BigInt>, _>(filter.created_after)
+    .bind::<Nullable<BigInt>, _>(filter.created_after)
+    .bind::<Nullable<BigInt>, _>(filter.created_before)
+    .bind::<Nullable<BigInt>, _>(filter.created_before)
+    .bind::<BigInt, _>(limit + 1)
+    .get_result::<Version>(&mut conn)
+    .await?;
+    let etag = etag::weak(&format!("{}:{}", version.version, usage.version()));
+    if let Some(not_modified) = Conditional::not_modified(&etag, if_none_match.as_ref()) {
+        return Ok(not_modified);
+    }
+
+    // new rows always get a larger rowid than the existing ones, so they can't shift pages
+    let rowid = sql::<BigInt>("urls.rowid");
+    let mut query = urls::table
+        .select((rowid.clone(), Url::as_select()))
+        .filter(rowid.clone().gt(after))
+        .order(rowid.asc())
+        .limit(limit + 1)
+        .into_boxed();
+    if let Some(created_after) = filter.created_after {
+        query = query.filter(urls::created_at.gt(created_after));
+    }
+    if let Some(created_before) = filter.created_before {
+        query = query.filter(urls::created_at.lt(created_before));
+    }
+    let rows = query.load::<(i64, Url)>(&mut conn).await?;
+    drop(conn);
+
+    let page = Page::new(rows, limit, |(rowid, _)| *rowid).map(|(_, mut url)| {
+        url.usage_count += usage.pending(&url.slug);
+        url
+    });
+    Ok(Conditional::new(etag, page))
+}
+
+/// Changes to a url, fields that are left out are kept as they are
+#[derive(Debug, Deserialize)]
+pub struct UpdateReq {
+    /// `null` removes the link's own limit, so the configured one applies again
+    #[serde(default, deserialize_with = "present")]
+    redirect_limit: Option<Option<u32>>,
+    /// Disabled links answer with a `410` instead of redirecting
+    disabled: Option<bool>,
+    /// `null` removes the description
+    #[serde(default, deserialize_with = "present")]
+    description: Option<Option<String>>,
+    /// `null` goes back to the usual error when the link is disabled or over its limit
+    #[serde(default, deserialize_with = "present")]
+    unavailable_message: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    unavailable_url: Option<Option<String>>,
+}
+
+/// Tell a field that was sent as `null` apart from one that was left out
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Change a url's settings
+pub async fn update(
+    _: Admin,
+    State(pool): State<db::Pool>,
+    State(config): State<Arc<LiveConfig>>,
+    State(cache): State<Option<Arc<Cache>>>,
+    State(notifier): State<Option<Arc<Notifier>>>,
+    Path(slug): Path<String>,
+    Json(req): Json<UpdateReq>,
+) -> Result<Json<Url>, UrlErr> {
+    let config = config.get();
+    let slug = slug::normalize(&slug, &config);
+    let case_insensitive = config.case_insensitive_slugs;
+    if let Some(Some(description)) = &req.description {
+        check_description(description)?;
+    }
+    unavailable::validate(
+        req.unavailable_message.as_ref().and_then(Option::as_deref),
+        req.unavailable_url.as_ref().and_then(Option::as_deref),
+        &config,
+    )?;
+
+    let mut conn = db::get(&pool).await?;
+    // every field changes together, or not at all
+    let (was_disabled, aliases, url) = conn
+        .immediate_transaction(|conn| {
+            async move {
+                let url = urls::table
+                    .filter(slug::eq(slug, case_insensitive))
+                    .first::<Url>(conn)
+                    .await
+                    .optional()?
+                    .ok_or(UrlErr::NotFound)?;
+
+                let now = signed::now() as i64;
+                if let Some(limit) = req.redirect_limit {
+                    diesel::update(urls::table.find(&url.slug))
+                        .set((
+                            urls::redirect_limit.eq(limit.map(clamp_limit)),
+                            urls::updated_at.eq(now),
+                        ))
+                        .execute(conn)
+                        .await?;
+                }
+                if let Some(disabled) = req.disabled {
+                    diesel::update(urls::table.find(&url.slug))
+                        .set((urls::disabled.eq(disabled), urls::updated_at.eq(now)))
+                        .execute(conn)
+                        .await?;
+                }
+                if let Some(description) = req.description {
+                    diesel::update(urls::table.find(&url.slug))
+                        .set((urls::description.eq(description), urls::updated_at.eq(now)))
+                        .execute(conn)
+                        .await?;
+                }
+                if let Some(message) = req.unavailable_message {
+                    diesel::update(urls::table.find(&url.slug))
+                        .set((
+                            urls::unavailable_message.eq(message),
+                            urls::updated_at.eq(now),
+                        ))
+                        .execute(conn)
+                        .await?;
+                }
+                if let Some(unavailable_url) = req.unavailable_url {
+                    diesel::update(urls::table.find(&url.slug))
+                        .set((
+                            urls::unavailable_url.eq(unavailable_url),
+                            urls::updated_at.eq(now),
+                        ))
+                        .execute(conn)
+                        .await?;
+                }
+
+                let was_disabled = url.disabled;
+                let aliases = alias::of(conn, &url.slug).await?;
+                let url = urls::table.find(url.slug).first::<Url>(conn).await?;
+                Ok::<_, UrlErr>((was_disabled, aliases, url))
+            }
+            .scope_boxed()
+        })
+        .await?;
+    drop(conn);
+
+    if let Some(cache) = cache {
+        alias::invalidate(&cache, &slug::normalize(&url.slug, &config), &aliases).await;
+    }
+    if let (Some(notifier), Some(owner), false, true) =
+        (notifier, &url.owner_email, was_disabled, url.disabled)
+    {
+        let notification = Notification::Disabled {
+            slug: url.slug.clone(),
+            url: url.url.clone(),
+        };
+        notifier.send(owner.clone(), notification);
+    }
+    Ok(Json(url))
+}
+
+/// Reset or lower a url's usage count, after bots or scrapers inflated it.  The clicks recorded
+/// for its stats are kept, and the change is recorded in the audit log.
+pub async fn adjust_usage(
+    _: Admin,
+    State(config): State<Arc<LiveConfig>>,
+    State(usage_queue): State<UsageQueue>,
+    Path(slug): Path<String>,
+    Json(adjustment): Json<UsageAdjustment>,
+) -> Result<Json<Url>, UrlErr> {
+    let config = config.get();
+    let slug = slug::normalize(&slug, &config);
+    let case_insensitive = config.case_insensitive_slugs;
+
+    // made by the usage writer, so it can't be undone by clicks it is still holding
+    let url = usage_queue
+        .adjust(slug, case_insensitive, adjustment)
+        .await?;
+    Ok(Json(url))
+}
+
+/// Delete a url, along with any reports and aliases of it
+pub async fn remove(
+    _: Admin,
+    State(pool): State<db::Pool>,
+    State(config): State<Arc<LiveConfig>>,
+    State(cache): State<Option<Arc<Cache>>>,
+    Path(slug): Path<String>,
+) -> Result<StatusCode, UrlErr> {
+    let config = config.get();
+    let slug = slug::normalize(&slug, &config);
+    let case_insensitive = config.case_insensitive_slugs;
+
+    let mut conn = db::get(&pool).await?;
+    let (deleted, aliases) = conn
+        .immediate_transaction(|conn| {
+            async move {
+                let Some(slug) = urls::table
+                    .filter(slug::eq(slug, case_insensitive))
+                    .select(urls::slug)
+                    .first::<String>(conn)
+                    .await
+                    .optional()?
+                else {
+                    return Ok(None);
+                };
+
+                diesel::delete(urls::table.find(&slug))
+                    .execute(conn)
+                    .await?;
+                diesel::delete(reports::table.filter(reports::slug.eq(&slug)))
+                    .execute(conn)
+                    .await?;
+                diesel::delete(clicks::table.filter(clicks::slug.eq(&slug)))
+                    .execute(conn)
+                    .await?;
+                diesel::delete(daily_clicks::table.filter(daily_clicks::slug.eq(&slug)))
+                    .execute(conn)
+                    .await?;
+                let aliases = alias::of(conn, &slug).await?;
+                diesel::delete(aliases::table.filter(aliases::slug.eq(&slug)))
+                    .execute(conn)
+                    .await?;
+                Ok::<_, UrlErr>(Some((slug, aliases)))
+            }
+            .scope_boxed()
+        })
+        .await?
+        .ok_or(UrlErr::NotFound)?;
+    drop(conn);
+
+    if let Some(cache) = cache {
+        alias::invalidate(&cache, &slug::normalize(&deleted, &config), &aliases).await;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AliasReq {
+    alias: String,
+}
+
+/// Every alias of a url
+pub async fn list_aliases(
+    _: Admin,
+    State(ReadPool(pool)): State<ReadPool>,
+    State(config): State<Arc<LiveConfig>>,
+    Path(slug): Path<String>,
+) -> Result<Json<Vec<String>>, UrlErr> {
+    let config = config.get();
+    let slug = slug::normalize(&slug, &config);
+    let case_insensitive = config.case_insensitive_slugs;
+
+    let mut conn = db::get(&pool).await?;
+    let slug = urls::table
+        .filter(slug::eq(slug, case_insensitive))
+        .select(urls::slug)
+        .first::<String>(&mut conn)
+        .await
+        .optional()?
+        .ok_or(UrlErr::NotFound)?;
+    let aliases = alias::of(&mut conn, &slug).await?;
+    Ok(Json(aliases))
+}
+
+/// Give a url another slug, which redirects to it and counts towards its usage
+pub async fn add_alias(
+    _: Admin,
+    State(pool): State<db::Pool>,
+    State(config): State<Arc<LiveConfig>>,
+    Path(slug): Path<String>,
+    Json(req): Json<AliasReq>,
+) -> Result<(StatusCode, Json<Alias>), UrlErr> {
+    let config = config.get();
+    let slug = slug::normalize(&slug, &config);
+    let new = slug::normalize(&req.alias, &config);
+    slug::validate(&new, &config)?;
+    let case_insensitive = config.case_insensitive_slugs;
+
+    let mut conn = db::get(&pool).await?;
+    let alias = conn
+        .immediate_transaction(|conn| {
+            async move {
+                let slug = urls::table
+                    .filter(slug::eq(slug, case_insensitive))
+                    .select(urls::slug)
+                    .first::<String>(conn)
+                    .await
+                    .optional()?
+                    .ok_or(UrlErr::NotFound)?;
+                if slug::exists(conn, new.clone(), case_insensitive).await? {
+                    return Err(UrlErr::SlugOccupied);
+                }
+
+                let alias = Alias { alias: new, slug };
+                diesel::insert_into(aliases::table)
+                    .values(&alias)
+                    .execute(conn)
+                    .await?;
+                Ok(alias)
+            }
+            .scope_boxed()
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(alias)))
+}
+
+/// Take an alias off a url, leaving the url and its other slugs as they are
+pub async fn remove_alias(
+    _: Admin,
+    State(pool): State<db::Pool>,
+    State(config): State<Arc<LiveConfig>>,
+    State(cache): State<Option<Arc<Cache>>>,
+    Path((slug, alias)): Path<(String, String)>,
+) -> Result<StatusCode, UrlErr> {
+    let config = config.get();
+    let slug = slug::normalize(&slug, &config);
+    let alias = slug::normalize(&alias, &config);
+    let case_insensitive = config.case_insensitive_slugs;
+
+    let mut conn = db::get(&pool).await?;
+    let slug = urls::table
+        .filter(slug::eq(slug, case_insensitive))
+        .select(urls::slug)
+        .first::<String>(&mut conn)
+        .await
+        .optional()?
+        .ok_or(UrlErr::NotFound)?;
+    let deleted = diesel::delete(
+        aliases::table
+            .filter(alias::eq(alias.clone(), case_insensitive))
+            .filter(aliases::slug.eq(slug)),
+    )
+    .execute(&mut conn)
+    .await?;
+    drop(conn);
+    if deleted == 0 {
+        return Err(UrlErr::NotFound);
+    }
+
+    if let Some(cache) = cache {
+        cache.invalidate(&alias).await;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The most days of stats that can be asked for at once
+const MAX_STATS_DAYS: u64 = 366;
+
+#[derive(Debug, Deserialize)]
+pub struct StatsParams {
+    /// How many days back to go, 30 by default
+    days: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatsRes {
+    slug: String,
+    url: String,
+    /// Only shown to admins, since it may be a private note
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    /// Every click the link has had, including those from before it had stats
+    total_clicks: i64,
+    days: u64,
+    daily: Vec<Bucket>,
+    referrers: Vec<Bucket>,
+    countries: Vec<Bucket>,
+}
+
+/// A link's clicks over time, top referrers and countries
+pub async fn stats(
+    admin: Option<Admin>,
+    State(ReadPool(pool)): State<ReadPool>,
+    State(config): State<Arc<LiveConfig>>,
+    State(usage): State<Arc<UsageTally>>,
+    Path(slug): Path<String>,
+    Query(params): Query<StatsParams>,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+) -> Result<Conditional<StatsRes>, UrlErr> {
+    let config = config.get();
+    if !config.analytics.public_stats && admin.is_none() {
+        return Err(UrlErr::Unauthorized);
+    }
+
+    let slug = slug::normalize(&slug, &config);
+    let case_insensitive = config.case_insensitive_slugs;
+    let days = params.days.unwrap_or(30).clamp(1, MAX_STATS_DAYS);
+    let since = (signed::now() - days * 24 * 60 * 60) as i64;
+
+    let mut conn = db::get(&pool).await?;
+    let url = alias::resolve(&mut conn, slug, case_insensitive)
+        .await?
+        .ok_or(UrlErr::NotFound)?;
+    let pending = usage.pending(&url.slug);
+    let version = analytics::version(&mut conn, &url.slug, since).await?;
+    let etag = etag::weak(&format!(
+        "{}:{}:{}:{}:{}:{}",
+        url.slug,
+        url.updated_at,
+        url.usage_count,
+        pending,
+        admin.is_some(),
+        version
+    ));
+    if let Some(not_modified) = Conditional::not_modified(&etag, if_none_match.as_ref()) {
+        return Ok(not_modified);
+    }
+    let stats = analytics::stats(&mut conn, &url.slug, since).await?;
+    drop(conn);
+
+    let res = StatsRes {
+        total_clicks: i64::from(url.usage_count) + i64::from(pending),
+        slug: url.slug,
+        url: url.url,
+        description: url.description.filter(|_| admin.is_some()),
+        days,
+        daily: stats.daily,
+        referrers: stats.referrers,
+        countries: stats.countries,
+    };
+    Ok(Conditional::new(etag, res))
+}
+
+/// Every click recorded for a link (and its aliases) that hasn't been rolled up yet, oldest
+/// first.  Only for admins, since they include the visitors' addresses.
+pub async fn list_clicks(
+    _: Admin,
+    State(ReadPool(pool)): State<ReadPool>,
+    State(config): State<Arc<LiveConfig>>,
+    Path(slug): Path<String>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Page<Click>>, UrlErr> {
+    let config = config.get();
+    let slug = slug::normalize(&slug, &config);
+    let case_insensitive = config.case_insensitive_slugs;
+    let limit = params.limit();
+    let after = params.after()?.unwrap_or(0);
+
+    let mut conn = db::get(&pool).await?;
+    let url = alias::resolve(&mut conn, slug, case_insensitive)
+        .await?
+        .ok_or(UrlErr::NotFound)?;
+    let rows = clicks::table
+        .select(Click::as_select())
+        .filter(clicks::slug.eq(&url.slug))
+        .filter(clicks::id.gt(after))
+        .order(clicks::id.asc())
+        .limit(limit + 1)
+        .load::<Click>(&mut conn)
+        .await?;
+
+    Ok(Json(Page::new(rows, limit, |click| click.id)))
+}
+
+/// The longest reason that is kept with a report, in characters
+const MAX_REPORT_REASON: usize = 1000;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ReportReq {
+    #[serde(default)]
+    reason: String,
+}
+
+/// Flag a link as malicious, for an admin to review.  Reporting the same link twice from one
+/// address only counts once.
+pub async fn report(
+    State(pool): State<db::Pool>,
+    State(config): State<Arc<LiveConfig>>,
+    ClientIp(ip): ClientIp,
+    Path(slug): Path<String>,
+    req: Option<Json<ReportReq>>,
+) -> Result<StatusCode, UrlErr> {
+    let config = config.get();
+    let slug = slug::normalize(&slug, &config);
+    let case_insensitive = config.case_insensitive_slugs;
+    let Json(req) = req.unwrap_or_default();
+    let reason = req
+        .reason
+        .chars()
+        .take(MAX_REPORT_REASON)
+        .collect::<String>();
+    let reporter_ip = ip.to_string();
+
+    let mut conn = db::get(&pool).await?;
+    let slug = alias::resolve(&mut conn, slug, case_insensitive)
+        .await?
+        .ok_or(UrlErr::NotFound)?
+        .slug;
+
+    diesel::insert_into(reports::table)
+        .values(NewReport {
+            slug: &slug,
+            reason: &reason,
+            reporter_ip: &reporter_ip,
+            created_at: signed::now() as i64,
+        })
+        .on_conflict_do_nothing()
+        .execute(&mut conn)
+        .await?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Every open report, oldest first
+pub async fn list_reports(
+    _: Admin,
+    State(ReadPool(pool)): State<ReadPool>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Page<Report>>, UrlErr> {
+    let limit = params.limit();
+    let after = params.after()?.unwrap_or(0);
+
+    let mut conn = db::get(&pool).await?;
+    let rows = reports::table
+        .filter(reports::id.gt(after))
+        .order(reports::id.asc())
+        .limit(limit + 1)
+        .load::<Report>(&mut conn)
+        .await?;
+
+    Ok(Json(Page::new(rows, limit, |report| report.id)))
+}
+
+/// Close every report for a link, whether or not it was disabled
+pub async fn clear_reports(
+    _: Admin,
+    State(pool): State<db::Pool>,
+    State(config): State<Arc<LiveConfig>>,
+    Path(slug): Path<String>,
+) -> Result<StatusCode, UrlErr> {
+    let slug = slug::normalize(&slug, &config.get());
+    let mut conn = db::get(&pool).await?;
+    let cleared = diesel::delete(reports::table.filter(reports::slug.eq(slug)))
+        .execute(&mut conn)
+        .await?;
+
+    if cleared == 0 {
+        return Err(UrlErr::NotFound);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Availability {
+    Free,
+    Taken,
+    Reserved,
+    Invalid,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckRes {
+    /// The slug as it would be stored
+    slug: String,
+    status: Availability,
+}
+
+/// Whether a custom slug could be used right now
+pub async fn check(
+    State(ReadPool(pool)): State<ReadPool>,
+    State(config): State<Arc<LiveConfig>>,
+    Path(slug): Path<String>,
+) -> Result<Json<CheckRes>, UrlErr> {
+    let config = config.get();
+    let slug = slug::normalize(&slug, &config);
+
+    let status = match slug::validate(&slug, &config) {
+        Err(UrlErr::SlugReserved) => Availability::Reserved,
+        Err(_) => Availability::Invalid,
+        Ok(()) => {
+            let case_insensitive = config.case_insensitive_slugs;
+            let mut conn = db::get(&pool).await?;
+            let taken = slug::exists(&mut conn, slug.clone(), case_insensitive).await?;
+
+            if taken {
+                Availability::Taken
+            } else {
+                Availability::Free
+            }
+        }
+    };
+
+    Ok(Json(CheckRes { slug, status }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SuggestParams {
+    url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SuggestRes {
+    slugs: Vec<String>,
+}
+
+/// How many suggestions to return at most
+const SUGGESTIONS: usize = 5;
+
+/// Suggest some free, readable slugs for a url
+pub async fn suggest(
+    State(ReadPool(pool)): State<ReadPool>,
+    State(config): State<Arc<LiveConfig>>,
+    Query(params): Query<SuggestParams>,
+) -> Result<Json<SuggestRes>, UrlErr> {
+    let url = url::Url::parse(&params.url).map_err(UrlErr::InvalidUrl)?;
+    let config = config.get();
+
+    let candidates = suggest::candidates(&url)
+        .into_iter()
+        .map(|s| slug::normalize(&s, &config))
+        .filter(|s| slug::validate(s, &config).is_ok())
+        .collect::<Vec<_>>();
+
+    let case_insensitive = config.case_insensitive_slugs;
+    let mut conn = db::get(&pool).await?;
+    let mut slugs = Vec::new();
+    for candidate in candidates {
+        if !slug::exists(&mut conn, candidate.clone(), case_insensitive).await? {
+            slugs.push(candidate);
+        }
+        if slugs.len() == SUGGESTIONS {
+            break;
+        }
+    }
+
+    Ok(Json(SuggestRes { slugs }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SignReq {
+    url: String,
+    /// How long (in seconds) the link should work for
+    ttl: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SignRes {
+    slug: String,
+    url: String,
+    /// When the link stops working, in seconds since the unix epoch
+    expires_at: u64,
+}
+
+/// Mint a temporary link that isn't stored anywhere
+pub async fn sign(
+    _: Admin,
+    State(config): State<Arc<LiveConfig>>,
+    Json(req): Json<SignReq>,
+) -> Result<Json<SignRes>, UrlErr> {
+    let config = config.get();
+    let key = config
+        .signing_key
+        .as_deref()
+        .ok_or(UrlErr::FeatureDisabled("Signed links"))?;
+
+    let url = url::Url::parse(&req.url).map_err(UrlErr::InvalidUrl)?;
+    destination::check_scheme(&url, &config)?;
+
+    let expires_at = signed::now() + req.ttl.min(config.signed_link_max_ttl);
+    let slug = signed::mint(&req.url, expires_at, key);
+
+    Ok(Json(SignRes {
+        slug,
+        url: req.url,
+        expires_at,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenParams {
+    /// From the link at the bottom of a notification email
+    token: String,
+}
+
+/// The address a preferences token was made for
+fn token_email(params: &TokenParams, config: &LiveConfig) -> Result<String, UrlErr> {
+    let config = config.get();
+    let key = config
+        .signing_key
+        .as_deref()
+        .ok_or(UrlErr::FeatureDisabled("Notification preferences"))?;
+    let email = signed::check_token(&params.token, key)?;
+    // other kinds of token (such as claims) are signed the same way
+    email
+        .parse::<lettre::Address>()
+        .map_err(|_| UrlErr::Unauthorized)?;
+    Ok(email)
+}
+
+/// Which notification emails an owner gets
+pub async fn preferences(
+    State(ReadPool(pool)): State<ReadPool>,
+    State(config): State<Arc<LiveConfig>>,
+    Query(params): Query<TokenParams>,
+) -> Result<Json<NotificationPreferences>, UrlErr> {
+    let email = token_email(&params, &config)?;
+    let mut conn = db::get(&pool).await?;
+    let preferences = notification_preferences::table
+        .find(&email)
+        .first::<NotificationPreferences>(&mut conn)
+        .await
+        .optional()?
+        .unwrap_or_else(|| NotificationPreferences::new(email));
+    Ok(Json(preferences))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreferencesReq {
+    milestones: bool,
+    moderation: bool,
+}
+
+pub async fn set_preferences(
+    State(pool): State<db::Pool>,
+    State(config): State<Arc<LiveConfig>>,
+    Query(params): Query<TokenParams>,
+    Json(req): Json<PreferencesReq>,
+) -> Result<Json<NotificationPreferences>, UrlErr> {
+    let preferences = NotificationPreferences {
+        email: token_email(&params, &config)?,
+        milestones: req.milestones,
+        moderation: req.moderation,
+    };
+    let mut conn = db::get(&pool).await?;
+    diesel::insert_into(notification_preferences::table)
+        .values(&preferences)
+        .on_conflict(notification_preferences::email)
+        .do_update()
+        .set(&preferences)
+        .execute(&mut conn)
+        .await?;
+    Ok(Json(preferences))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ClaimParams {
+    /// The owner's notification preferences token, for owners handing on their own link
+    token: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClaimRes {
+    token: String,
+    /// In seconds since the unix epoch
+    expires_at: u64,
+}
+
+/// Make a token that hands a link to whoever redeems it.  Admins can do this for any link, and
+/// owners for their own.
+pub async fn start_claim(
+    admin: Option<Admin>,
+    State(ReadPool(pool)): State<ReadPool>,
+    State(config): State<Arc<LiveConfig>>,
+    Path(slug): Path<String>,
+    Query(params): Query<ClaimParams>,
+) -> Result<Json<ClaimRes>, UrlErr> {
+    let config = config.get();
+    let key = config
+        .signing_key
+        .as_deref()
+        .ok_or(UrlErr::FeatureDisabled("Link claims"))?;
+    let owner = match (admin, &params.token) {
+        (Some(_), _) => None,
+        (None, Some(token)) => Some(signed::check_token(token, key)?),
+        (None, None) => return Err(UrlErr::Unauthorized),
+    };
+
+    let slug = slug::normalize(&slug, &config);
+    let case_insensitive = config.case_insensitive_slugs;
+    let mut conn = db::get(&pool).await?;
+    let url = urls::table
+        .filter(slug::eq(slug, case_insensitive))
+        .first::<Url>(&mut conn)
+        .await
+        .optional()?
+        .ok_or(UrlErr::NotFound)?;
+    if owner.is_some() && owner != url.owner_email {
+        return Err(UrlErr::Unauthorized);
+    }
+
+    let claim = Claim::new(url.slug, url.owner_email);
+    Ok(Json(ClaimRes {
+        token: claim.token(key),
+        expires_at: claim.expires_at,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RedeemReq {
+    token: String,
+    /// The new owner
+    email: String,
+}
+
+/// Become the owner of a link with a token from [`start_claim`]
+pub async fn redeem_claim(
+    State(pool): State<db::Pool>,
+    State(config): State<Arc<LiveConfig>>,
+    Json(req): Json<RedeemReq>,
+) -> Result<Json<PublicUrl>, UrlErr> {
+    let config = config.get();
+    let key = config
+        .signing_key
+        .as_deref()
+        .ok_or(UrlErr::FeatureDisabled("Link claims"))?;
+    let claim = Claim::verify(&req.token, key)?;
+    req.email
+        .parse::<lettre::Address>()
+        .map_err(|_| UrlErr::InvalidEmail)?;
+
+    let mut conn = db::get(&pool).await?;
+    let url = conn
+        .immediate_transaction(|conn| {
+            async move {
+                // only if nobody else has redeemed a token for this link in the meantime
+                let updated = diesel::update(
+                    urls::table
+                        .find(&claim.slug)
+                        .filter(urls::owner_email.is(&claim.owner)),
+                )
+                .set((
+                    urls::owner_email.eq(&req.email),
+                    urls::updated_at.eq(signed::now() as i64),
+                ))
+                .execute(conn)
+                .await?;
+                if updated == 0 {
+                    return Err(UrlErr::StaleClaim);
+                }
+
+                let (action, detail) = match &claim.owner {
+                    Some(old) => (AuditAction::Transfer, format!("from {}", old)),
+                    None => (AuditAction::Claim, "with a claim token".to_string()),
+                };
+                audit::record(conn, &claim.slug, action, &req.email, Some(&detail)).await?;
+                Ok(urls::table.find(&claim.slug).first::<Url>(conn).await?)
+            }
+            .scope_boxed()
+        })
+        .await?;
+    Ok(Json(url.into()))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditParams {
+    /// Only list the entries for this link
+    slug: Option<String>,
+    #[serde(flatten)]
+    page: PageParams,
+}
+
+/// The audit log, oldest first
+pub async fn list_audit(
+    _: Admin,
+    State(ReadPool(pool)): State<ReadPool>,
+    Query(params): Query<AuditParams>,
+) -> Result<Json<Page<AuditEntry>>, UrlErr> {
+    let limit = params.page.limit();
+    let after = params.page.after()?.unwrap_or(0);
+
+    let mut query = audit_log::table
+        .select(AuditEntry::as_select())
+        .filter(audit_log::id.gt(after))
+        .order(audit_log::id.asc())
+        .limit(limit + 1)
+        .into_boxed();
+    if let Some(slug) = params.slug {
+        query = query.filter(audit_log::slug.eq(slug));
+    }
+    let mut conn = db::get(&pool).await?;
+    let rows = query.load::<AuditEntry>(&mut conn).await?;
+
+    Ok(Json(Page::new(rows, limit, |entry| entry.id)))
+}
+
+pub async fn list_bans(_: Admin, State(bans): State<Arc<Bans>>) -> Json<Vec<Ban>> {
+    Json(bans.list())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BanReq {
+    /// How long (in seconds) from now the ban should last
+    duration: u64,
+}
+
+/// Ban an ip, or change when an existing ban ends
+pub async fn ban(
+    _: Admin,
+    State(bans): State<Arc<Bans>>,
+    Path(ip): Path<IpAddr>,
+    Json(req): Json<BanReq>,
+) -> Json<Ban> {
+    let ip = ip::canonical(ip);
+    let reason = bans.get(ip).map_or(BanReason::Manual, |ban| ban.reason);
+    Json(bans.ban(ip, reason, Duration::from_secs(req.duration)))
+}
+
+pub async fn lift_ban(
+    _: Admin,
+    State(bans): State<Arc<Bans>>,
+    Path(ip): Path<IpAddr>,
+) -> Result<StatusCode, UrlErr> {
+    if bans.lift(ip::canonical(ip)) {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(UrlErr::NotFound)
+    }
+}
@@ -0,0 +1,51 @@
+//! A record of changes to who owns or controls a link (and to its usage count), listed for admins at
+//! `GET /api/v1/audit`.
+
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+
+use crate::{db::Connection, models::NewAuditEntry, schema::audit_log, signed::now};
+
+/// Who an entry is recorded against when an admin made the change
+pub const ADMIN: &str = "admin";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    /// A link without an owner was claimed
+    Claim,
+    /// A link was handed from one owner to another
+    Transfer,
+    /// A link's usage count was reset or lowered by an admin
+    AdjustUsage,
+}
+
+impl AuditAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditAction::Claim => "claim",
+            AuditAction::Transfer => "transfer",
+            AuditAction::AdjustUsage => "adjust_usage",
+        }
+    }
+}
+
+/// Add an entry to the log, usually in the same transaction as the change itself
+pub async fn record(
+    conn: &mut Connection,
+    slug: &str,
+    action: AuditAction,
+    actor: &str,
+    detail: Option<&str>,
+) -> QueryResult<()> {
+    diesel::insert_into(audit_log::table)
+        .values(NewAuditEntry {
+            created_at: now() as i64,
+            slug,
+            action: action.as_str(),
+            actor,
+            detail,
+        })
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts},
+    http::{header, request::Parts},
+};
+
+use crate::{config::LiveConfig, error::UrlErr};
+
+/// Only extracts if the request has `Authorization: Bearer <admin_token>`, and didn't come in
+/// through the public listener while there is a separate admin one
+#[derive(Debug, Clone, Copy)]
+pub struct Admin;
+
+/// Marks requests to the public listener when the admin routes are served on `admin_bind`
+#[derive(Debug, Clone, Copy)]
+pub struct PublicListener;
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Admin
+where
+    Arc<LiveConfig>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = UrlErr;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if parts.extensions.get::<PublicListener>().is_some() {
+            return Err(UrlErr::Unauthorized);
+        }
+        let config = Arc::<LiveConfig>::from_ref(state).get();
+        let Some(expected) = &config.admin_token else {
+            return Err(UrlErr::Unauthorized);
+        };
+
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or(UrlErr::Unauthorized)?;
+
+        if constant_time_eq(token.as_bytes(), expected.as_bytes()) {
+            Ok(Self)
+        } else {
+            Err(UrlErr::Unauthorized)
+        }
+    }
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
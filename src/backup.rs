@@ -0,0 +1,202 @@
+//! Periodic snapshots of the database, kept in a local directory or uploaded to an
+//! S3-compatible bucket.
+
+use std::{path::Path, path::PathBuf, time::Duration};
+
+use diesel::{prelude::*, sql_query, sql_types::Text};
+use rusty_s3::{Bucket, Credentials, S3Action, UrlStyle};
+use serde::Deserialize;
+use tokio::time::MissedTickBehavior;
+use tracing::{error, info};
+
+use crate::signed::now;
+
+/// How long the signed upload url stays valid, which only needs to cover the upload itself
+const UPLOAD_URL_TTL: Duration = Duration::from_secs(60 * 60);
+
+const FILE_PREFIX: &str = "url-shortener-";
+const FILE_SUFFIX: &str = ".sqlite";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    /// Seconds between backups, which are disabled if this is not set
+    pub interval: Option<u64>,
+    /// Where backups are written, they are only kept here if `s3` is not set
+    pub dir: PathBuf,
+    /// How many local backups to keep, `0` keeps all of them
+    pub keep: usize,
+    /// Upload backups to an S3-compatible bucket instead of keeping them in `dir`
+    pub s3: Option<S3Config>,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            interval: None,
+            dir: "backups".into(),
+            keep: 7,
+            s3: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct S3Config {
+    /// e.g. `https://s3.us-east-1.amazonaws.com`
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Put in front of every object's name
+    pub prefix: String,
+    /// Use `<endpoint>/<bucket>/<object>` urls instead of `<bucket>.<endpoint>/<object>`, which
+    /// most stores other than AWS need
+    pub path_style: bool,
+}
+
+impl Default for S3Config {
+    fn default() -> Self {
+        Self {
+            endpoint: String::new(),
+            bucket: String::new(),
+            region: "us-east-1".into(),
+            access_key_id: String::new(),
+            secret_access_key: String::new(),
+            prefix: String::new(),
+            path_style: true,
+        }
+    }
+}
+
+/// Back up the database every `interval`, forever
+pub async fn run(database_url: String, config: BackupConfig) {
+    let Some(interval) = config.interval else {
+        return;
+    };
+
+    let client = reqwest::Client::new();
+    let mut ticker = tokio::time::interval(Duration::from_secs(interval));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // the first tick completes straight away, don't back up on every restart
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+        match backup(&database_url, &config, &client).await {
+            Ok(location) => info!("Backed up the database to {}", location),
+            Err(err) => error!("Unable to back up the database: {}", err),
+        }
+    }
+}
+
+/// Take a backup, returning where it ended up
+async fn backup(
+    database_url: &str,
+    config: &BackupConfig,
+    client: &reqwest::Client,
+) -> Result<String, String> {
+    tokio::fs::create_dir_all(&config.dir)
+        .await
+        .map_err(|e| e.to_string())?;
+    let name = format!("{}{}{}", FILE_PREFIX, now(), FILE_SUFFIX);
+    let path = config.dir.join(&name);
+    snapshot(database_url, &path).await?;
+
+    match &config.s3 {
+        Some(s3) => {
+            let uploaded = upload(client, s3, &name, &path).await;
+            let _ = tokio::fs::remove_file(&path).await;
+            uploaded
+        }
+        None => {
+            prune(&config.dir, config.keep).await?;
+            Ok(path.display().to_string())
+        }
+    }
+}
+
+/// Write a consistent copy of the database to `path`.  This uses its own connection, the read
+/// pool's connections are read-only and the writer shouldn't be held up for the whole copy.
+async fn snapshot(database_url: &str, path: &Path) -> Result<(), String> {
+    let database_url = database_url.to_string();
+    let path = path
+        .to_str()
+        .ok_or("The backup path isn't valid utf-8")?
+        .to_string();
+    tokio::task::spawn_blocking(move || {
+        let mut conn = SqliteConnection::establish(&database_url).map_err(|e| e.to_string())?;
+        sql_query("VACUUM INTO ?")
+            .bind::<Text, _>(path)
+            .execute(&mut conn)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+async fn upload(
+    client: &reqwest::Client,
+    s3: &S3Config,
+    name: &str,
+    path: &Path,
+) -> Result<String, String> {
+    let endpoint = s3
+        .endpoint
+        .parse()
+        .map_err(|e| format!("Invalid S3 endpoint: {}", e))?;
+    let style = if s3.path_style {
+        UrlStyle::Path
+    } else {
+        UrlStyle::VirtualHost
+    };
+    let bucket = Bucket::new(endpoint, style, s3.bucket.clone(), s3.region.clone())
+        .map_err(|e| format!("Invalid S3 bucket: {}", e))?;
+    let credentials = Credentials::new(&s3.access_key_id, &s3.secret_access_key);
+
+    let key = format!("{}{}", s3.prefix, name);
+    let url = bucket
+        .put_object(Some(&credentials), &key)
+        .sign(UPLOAD_URL_TTL);
+    let body = tokio::fs::read(path).await.map_err(|e| e.to_string())?;
+    let res = client
+        .put(url)
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !res.status().is_success() {
+        return Err(format!("The bucket answered with {}", res.status()));
+    }
+
+    Ok(format!("s3://{}/{}", s3.bucket, key))
+}
+
+/// Delete all but the newest `keep` backups in `dir`
+async fn prune(dir: &Path, keep: usize) -> Result<(), String> {
+    if keep == 0 {
+        return Ok(());
+    }
+
+    let mut entries = tokio::fs::read_dir(dir).await.map_err(|e| e.to_string())?;
+    let mut backups = Vec::new();
+    while let Some(entry) = entries.next_entry().await.map_err(|e| e.to_string())? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(FILE_PREFIX) && name.ends_with(FILE_SUFFIX) {
+            backups.push(name);
+        }
+    }
+
+    // the names only differ by timestamp, which all have the same number of digits
+    backups.sort();
+    let old = backups.len().saturating_sub(keep);
+    for name in &backups[..old] {
+        tokio::fs::remove_file(dir.join(name))
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
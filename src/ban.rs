@@ -0,0 +1,194 @@
+//! Temporary bans for clients that go over the abuse thresholds, checked before any route runs.
+
+use std::{collections::HashMap, net::IpAddr, sync::Arc, sync::Mutex, time::Duration};
+
+use axum::{
+    extract::State,
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    auth::Admin, config::LiveConfig, error::UrlErr, ip::ClientIp, rate::WindowCounter, signed::now,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BanConfig {
+    pub enabled: bool,
+    /// How long (in seconds) an automatic ban lasts
+    pub duration: u64,
+    /// How many urls a client may create within `creation_window`
+    pub max_creations: u32,
+    /// In seconds
+    pub creation_window: u64,
+    /// Ban clients that trip the scan guard
+    pub ban_scanners: bool,
+    /// How many times a client may try to shorten a blocked url within `blocked_window`
+    pub max_blocked_attempts: u32,
+    /// In seconds
+    pub blocked_window: u64,
+    /// How many honeypot paths a client may ask for within `honeypot_window`, `0` never bans
+    pub max_honeypot_hits: u32,
+    /// In seconds
+    pub honeypot_window: u64,
+}
+
+impl Default for BanConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            duration: 60 * 60,
+            max_creations: 30,
+            creation_window: 60,
+            ban_scanners: true,
+            max_blocked_attempts: 3,
+            blocked_window: 60 * 60,
+            max_honeypot_hits: 3,
+            honeypot_window: 60 * 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BanReason {
+    CreationFlood,
+    Scanning,
+    BlockedDestination,
+    Honeypot,
+    /// Banned by an admin
+    Manual,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Ban {
+    pub ip: IpAddr,
+    pub reason: BanReason,
+    /// When the ban is lifted, in seconds since the unix epoch
+    pub expires_at: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Bans {
+    bans: Mutex<HashMap<IpAddr, Ban>>,
+    creations: WindowCounter<IpAddr>,
+    blocked_attempts: WindowCounter<IpAddr>,
+    honeypot_hits: WindowCounter<IpAddr>,
+}
+
+impl Bans {
+    /// The ban on `ip`, if it has one that hasn't expired
+    pub fn get(&self, ip: IpAddr) -> Option<Ban> {
+        let mut bans = self.bans.lock().unwrap();
+        match bans.get(&ip) {
+            Some(ban) if ban.expires_at > now() => Some(ban.clone()),
+            Some(_) => {
+                bans.remove(&ip);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Every ban that hasn't expired, soonest to expire first
+    pub fn list(&self) -> Vec<Ban> {
+        let mut bans = self.bans.lock().unwrap();
+        let now = now();
+        bans.retain(|_, ban| ban.expires_at > now);
+
+        let mut list: Vec<_> = bans.values().cloned().collect();
+        list.sort_by_key(|ban| ban.expires_at);
+        list
+    }
+
+    /// Ban `ip` for `duration` from now, replacing any ban it already has
+    pub fn ban(&self, ip: IpAddr, reason: BanReason, duration: Duration) -> Ban {
+        let ban = Ban {
+            ip,
+            reason,
+            expires_at: now() + duration.as_secs(),
+        };
+        self.bans.lock().unwrap().insert(ip, ban.clone());
+        ban
+    }
+
+    /// Lift the ban on `ip`, returning whether it had one.  Its strikes are forgotten too, so
+    /// that it isn't banned again straight away.
+    pub fn lift(&self, ip: IpAddr) -> bool {
+        self.creations.reset(&ip);
+        self.blocked_attempts.reset(&ip);
+        self.honeypot_hits.reset(&ip);
+        self.get(ip).is_some() && self.bans.lock().unwrap().remove(&ip).is_some()
+    }
+
+    fn auto_ban(&self, ip: IpAddr, reason: BanReason, config: &BanConfig) {
+        warn!("Banning {} for {}s: {:?}", ip, config.duration, reason);
+        self.ban(ip, reason, Duration::from_secs(config.duration));
+    }
+
+    /// Record an attempt to create a url, failing if `ip` is now banned
+    pub fn record_creation(&self, ip: IpAddr, config: &BanConfig) -> Result<(), UrlErr> {
+        if !config.enabled {
+            return Ok(());
+        }
+
+        let window = Duration::from_secs(config.creation_window);
+        if self.creations.hit(ip, window) > config.max_creations {
+            self.auto_ban(ip, BanReason::CreationFlood, config);
+            return Err(UrlErr::Banned);
+        }
+        Ok(())
+    }
+
+    /// Record an attempt to shorten a url on the blocklist
+    pub fn record_blocked(&self, ip: IpAddr, config: &BanConfig) {
+        if !config.enabled {
+            return;
+        }
+
+        let window = Duration::from_secs(config.blocked_window);
+        if self.blocked_attempts.hit(ip, window) >= config.max_blocked_attempts {
+            self.auto_ban(ip, BanReason::BlockedDestination, config);
+        }
+    }
+
+    /// Record a request for one of the honeypot's paths
+    pub fn record_honeypot(&self, ip: IpAddr, config: &BanConfig) {
+        if !config.enabled || config.max_honeypot_hits == 0 {
+            return;
+        }
+
+        let window = Duration::from_secs(config.honeypot_window);
+        if self.honeypot_hits.hit(ip, window) >= config.max_honeypot_hits {
+            self.auto_ban(ip, BanReason::Honeypot, config);
+        }
+    }
+
+    /// Called when `ip` trips the scan guard
+    pub fn record_scanning(&self, ip: IpAddr, config: &BanConfig) {
+        if config.enabled && config.ban_scanners {
+            self.auto_ban(ip, BanReason::Scanning, config);
+        }
+    }
+}
+
+/// Refuse every request from a banned client, except for admins so that they can't lock
+/// themselves out
+pub async fn check<B>(
+    State(bans): State<Arc<Bans>>,
+    State(config): State<Arc<LiveConfig>>,
+    admin: Option<Admin>,
+    ClientIp(ip): ClientIp,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if config.get().bans.enabled && admin.is_none() && bans.get(ip).is_some() {
+        return UrlErr::Banned.into_response();
+    }
+
+    next.run(req).await
+}
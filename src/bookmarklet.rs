@@ -0,0 +1,93 @@
+//! `GET /new?url=...`, so that a bookmarklet can shorten the page it is clicked on:
+//!
+//! ```text
+//! javascript:location='https://sho.rt/new?token=TOKEN&url='+encodeURIComponent(location.href)
+//! ```
+//!
+//! It answers with a small page showing the link and a button to copy it.
+
+use axum::{
+    extract::{Query, State},
+    http::header,
+    response::{Html, IntoResponse},
+};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use serde::Deserialize;
+
+use crate::{
+    auth::constant_time_eq, check_destination, check_quota, config::Config, create_url, db,
+    error::UrlErr, html::escape, ip::ClientIp, models::Url, quota::Client, schema::urls, slug,
+    AppState, ShortReq,
+};
+
+const PAGE: &str = include_str!("bookmarklet.html");
+
+#[derive(Debug, Deserialize)]
+pub struct NewParams {
+    token: String,
+    url: String,
+}
+
+pub async fn new(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    Query(params): Query<NewParams>,
+) -> Result<impl IntoResponse, UrlErr> {
+    let config = state.config.get();
+    let expected = config
+        .bookmarklet_token
+        .as_deref()
+        .ok_or(UrlErr::FeatureDisabled("The bookmarklet"))?;
+    if !constant_time_eq(params.token.as_bytes(), expected.as_bytes()) {
+        return Err(UrlErr::Unauthorized);
+    }
+
+    let mut req = ShortReq::from_url(params.url.clone());
+    check_destination(&mut req, ip, &state.bans, &config)?;
+
+    // clicking the bookmarklet twice on the same page shouldn't make two links
+    let candidates = [params.url.clone(), slug::normalize_url(&params.url)];
+    let mut conn = db::get(&state.read_pool.0).await?;
+    let existing = urls::table
+        .filter(urls::url.eq_any(candidates))
+        .filter(urls::disabled.eq(false))
+        .first::<Url>(&mut conn)
+        .await
+        .optional()?;
+    drop(conn);
+    let entry = match existing {
+        Some(entry) => entry,
+        None => {
+            // only a new link counts, the bookmarklet has no API key to send
+            let client = Client::Ip(ip);
+            check_quota(&state, &client, ip, &config)
+                .await
+                .map_err(|(_, err)| err)?;
+            let (entry, is_new) =
+                create_url(req, ip.to_string(), config.clone(), state.pool.clone()).await?;
+            if is_new {
+                state.quotas.record_creation(client);
+            }
+            entry
+        }
+    };
+
+    Ok((
+        // the token is in this page's url, so it mustn't be cached, framed or sent on
+        [
+            (header::CACHE_CONTROL, "no-store"),
+            (header::X_FRAME_OPTIONS, "DENY"),
+            (header::REFERRER_POLICY, "no-referrer"),
+        ],
+        Html(render(&entry, &config)),
+    ))
+}
+
+fn render(entry: &Url, config: &Config) -> String {
+    PAGE.replace(
+        "{short}",
+        &escape(&format!("{}/{}", config.base_url(), entry.slug)),
+    )
+    .replace("{url}", &escape(&entry.url))
+}
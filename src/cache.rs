@@ -0,0 +1,208 @@
+//! An optional Redis (or Valkey) cache of where slugs redirect to, shared between replicas.
+//!
+//! Each replica also keeps a short-lived copy of the entries it has used in memory.  Changing a
+//! url publishes its slug on an invalidation channel, so that every replica drops its copy.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures_util::StreamExt;
+use redis::{aio::ConnectionManager, AsyncCommands, Client, ErrorKind, RedisError, RedisResult};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::models::Url;
+
+/// How long to wait for Redis at startup, the connection manager would otherwise retry forever
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait before resubscribing when the invalidation channel drops
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// e.g. `redis://127.0.0.1/`, the cache is disabled if this is not set
+    pub redis_url: Option<String>,
+    /// How long (in seconds) entries are kept in Redis
+    pub ttl: u64,
+    /// How long (in seconds) each replica keeps entries in memory
+    pub local_ttl: u64,
+    /// The most entries each replica keeps in memory
+    pub local_capacity: usize,
+    /// Put in front of every key and the invalidation channel, so that several shorteners can
+    /// share a Redis
+    pub key_prefix: String,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            redis_url: None,
+            ttl: 60 * 60,
+            local_ttl: 60,
+            local_capacity: 10_000,
+            key_prefix: "url-shortener:".into(),
+        }
+    }
+}
+
+/// Everything needed to redirect, without going to the database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Target {
+    /// The slug as it is stored, which may not be exactly what was asked for
+    pub slug: String,
+    pub url: String,
+    pub cache_control: Option<String>,
+    // entries cached before this was added won't have it
+    #[serde(default)]
+    pub redirect_limit: Option<i32>,
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default)]
+    pub unavailable_message: Option<String>,
+    #[serde(default)]
+    pub unavailable_url: Option<String>,
+}
+
+impl From<Url> for Target {
+    fn from(url: Url) -> Self {
+        Self {
+            slug: url.slug,
+            url: url.url,
+            cache_control: url.cache_control,
+            redirect_limit: url.redirect_limit,
+            disabled: url.disabled,
+            unavailable_message: url.unavailable_message,
+            unavailable_url: url.unavailable_url,
+        }
+    }
+}
+
+type Local = Mutex<HashMap<String, (Instant, Target)>>;
+
+pub struct Cache {
+    redis: ConnectionManager,
+    local: Arc<Local>,
+    config: CacheConfig,
+}
+
+impl Cache {
+    /// Connect to Redis and start listening for invalidations, if a `redis_url` is configured
+    pub async fn connect(config: &CacheConfig) -> RedisResult<Option<Self>> {
+        let Some(redis_url) = &config.redis_url else {
+            return Ok(None);
+        };
+
+        let client = Client::open(redis_url.as_str())?;
+        let redis = tokio::time::timeout(CONNECT_TIMEOUT, ConnectionManager::new(client.clone()))
+            .await
+            .map_err(|_| RedisError::from((ErrorKind::IoError, "Timed out connecting")))??;
+        let local = Arc::default();
+        tokio::spawn(listen(client, channel(config), Arc::clone(&local)));
+        info!("Caching slugs in Redis");
+
+        Ok(Some(Self {
+            redis,
+            local,
+            config: config.clone(),
+        }))
+    }
+
+    fn key(&self, slug: &str) -> String {
+        format!("{}slug:{}", self.config.key_prefix, slug)
+    }
+
+    /// Look up a normalized slug, errors are logged and treated as a miss
+    pub async fn get(&self, slug: &str) -> Option<Target> {
+        let local_ttl = Duration::from_secs(self.config.local_ttl);
+        if let Some((since, target)) = self.local.lock().unwrap().get(slug) {
+            if since.elapsed() < local_ttl {
+                return Some(target.clone());
+            }
+        }
+
+        let found: Option<String> = match self.redis.clone().get(self.key(slug)).await {
+            Ok(found) => found,
+            Err(err) => {
+                warn!("Unable to read {} from the cache: {}", slug, err);
+                return None;
+            }
+        };
+        let target: Target = serde_json::from_str(&found?).ok()?;
+        self.remember(slug, &target);
+        Some(target)
+    }
+
+    pub async fn set(&self, slug: &str, target: &Target) {
+        self.remember(slug, target);
+
+        let value = serde_json::to_string(target).expect("targets can always be serialized");
+        let result: RedisResult<()> = self
+            .redis
+            .clone()
+            .set_ex(self.key(slug), value, self.config.ttl)
+            .await;
+        if let Err(err) = result {
+            warn!("Unable to write {} to the cache: {}", slug, err);
+        }
+    }
+
+    /// Drop a normalized slug from Redis and from every replica's memory, this must be called
+    /// whenever a url is changed or deleted
+    pub async fn invalidate(&self, slug: &str) {
+        self.local.lock().unwrap().remove(slug);
+
+        let mut redis = self.redis.clone();
+        let result: RedisResult<()> = async {
+            redis.del::<_, ()>(self.key(slug)).await?;
+            redis.publish(channel(&self.config), slug).await
+        }
+        .await;
+        if let Err(err) = result {
+            warn!("Unable to invalidate {} in the cache: {}", slug, err);
+        }
+    }
+
+    fn remember(&self, slug: &str, target: &Target) {
+        let mut local = self.local.lock().unwrap();
+        if local.len() >= self.config.local_capacity {
+            local.clear();
+        }
+        local.insert(slug.to_string(), (Instant::now(), target.clone()));
+    }
+}
+
+fn channel(config: &CacheConfig) -> String {
+    format!("{}invalidate", config.key_prefix)
+}
+
+/// Drop local entries when any replica invalidates them, resubscribing if the connection drops
+async fn listen(client: Client, channel: String, local: Arc<Local>) {
+    loop {
+        let result: RedisResult<()> = async {
+            let mut pubsub = client.get_async_pubsub().await?;
+            pubsub.subscribe(&channel).await?;
+            // anything could have changed while we weren't listening
+            local.lock().unwrap().clear();
+
+            let mut messages = pubsub.on_message();
+            while let Some(msg) = messages.next().await {
+                if let Ok(slug) = msg.get_payload::<String>() {
+                    local.lock().unwrap().remove(&slug);
+                }
+            }
+            Ok(())
+        }
+        .await;
+
+        match result {
+            Ok(()) => warn!("Lost the cache invalidation channel, resubscribing"),
+            Err(err) => warn!("Unable to subscribe to cache invalidations: {}", err),
+        }
+        tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+    }
+}
@@ -0,0 +1,277 @@
+//! Named groups of links, such as every link in a newsletter, so that their stats can be seen
+//! together.  A link is in at most one campaign.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{delete, get, put},
+    Json, Router, TypedHeader,
+};
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, RunQueryDsl};
+use headers::IfNoneMatch;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    alias,
+    analytics::{self, Bucket},
+    auth::Admin,
+    config::LiveConfig,
+    db::{self, Connection, ReadPool},
+    error::UrlErr,
+    etag::{self, Conditional},
+    models::Campaign,
+    schema::{campaigns, urls},
+    signed::now,
+    slug,
+    usage::UsageTally,
+    AppState, LimitedBody,
+};
+
+/// The longest campaign name, in characters
+const MAX_NAME: usize = 100;
+
+/// The most days of stats that can be asked for at once
+const MAX_STATS_DAYS: u64 = 366;
+
+pub fn router() -> Router<AppState, LimitedBody> {
+    Router::new()
+        .route("/", get(list).post(create))
+        .route("/:name", delete(remove))
+        .route("/:name/stats", get(stats))
+        .route("/:name/links/:slug", put(assign).delete(unassign))
+}
+
+fn validate_name(name: &str) -> Result<(), UrlErr> {
+    let len = name.chars().count();
+    if len == 0 || len > MAX_NAME || name.chars().any(char::is_control) {
+        return Err(UrlErr::InvalidCampaign);
+    }
+    Ok(())
+}
+
+/// Whether a campaign called `name` exists
+async fn exists(conn: &mut Connection, name: &str) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(campaigns::table.find(name)))
+        .get_result(conn)
+        .await
+}
+
+/// Every campaign, by name
+pub async fn list(
+    _: Admin,
+    State(ReadPool(pool)): State<ReadPool>,
+) -> Result<Json<Vec<Campaign>>, UrlErr> {
+    let mut conn = db::get(&pool).await?;
+    let campaigns = campaigns::table
+        .order(campaigns::name.asc())
+        .load::<Campaign>(&mut conn)
+        .await?;
+    Ok(Json(campaigns))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateReq {
+    name: String,
+}
+
+pub async fn create(
+    _: Admin,
+    State(pool): State<db::Pool>,
+    Json(req): Json<CreateReq>,
+) -> Result<(StatusCode, Json<Campaign>), UrlErr> {
+    let name = req.name.trim().to_string();
+    validate_name(&name)?;
+
+    let campaign = Campaign {
+        name,
+        created_at: now() as i64,
+    };
+    let mut conn = db::get(&pool).await?;
+    let count = diesel::insert_into(campaigns::table)
+        .values(&campaign)
+        .on_conflict_do_nothing()
+        .execute(&mut conn)
+        .await?;
+    if count == 0 {
+        return Err(UrlErr::CampaignExists);
+    }
+    Ok((StatusCode::CREATED, Json(campaign)))
+}
+
+/// Delete a campaign, which leaves its links as they are but no longer grouped
+pub async fn remove(
+    _: Admin,
+    State(pool): State<db::Pool>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, UrlErr> {
+    let mut conn = db::get(&pool).await?;
+    conn.immediate_transaction(|conn| {
+        async move {
+            if diesel::delete(campaigns::table.find(&name))
+                .execute(conn)
+                .await?
+                == 0
+            {
+                return Err(UrlErr::NotFound);
+            }
+            diesel::update(urls::table.filter(urls::campaign.eq(&name)))
+                .set((
+                    urls::campaign.eq(None::<String>),
+                    urls::updated_at.eq(now() as i64),
+                ))
+                .execute(conn)
+                .await?;
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Put a link (found by its slug or an alias) into a campaign, moving it out of any other
+pub async fn assign(
+    _: Admin,
+    State(pool): State<db::Pool>,
+    State(config): State<Arc<LiveConfig>>,
+    Path((name, slug)): Path<(String, String)>,
+) -> Result<StatusCode, UrlErr> {
+    let config = config.get();
+    let slug = slug::normalize(&slug, &config);
+    let case_insensitive = config.case_insensitive_slugs;
+
+    let mut conn = db::get(&pool).await?;
+    conn.immediate_transaction(|conn| {
+        async move {
+            if !exists(conn, &name).await? {
+                return Err(UrlErr::NotFound);
+            }
+            let url = alias::resolve(conn, slug, case_insensitive)
+                .await?
+                .ok_or(UrlErr::NotFound)?;
+            diesel::update(urls::table.find(url.slug))
+                .set((urls::campaign.eq(name), urls::updated_at.eq(now() as i64)))
+                .execute(conn)
+                .await?;
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Take a link out of a campaign
+pub async fn unassign(
+    _: Admin,
+    State(pool): State<db::Pool>,
+    State(config): State<Arc<LiveConfig>>,
+    Path((name, slug)): Path<(String, String)>,
+) -> Result<StatusCode, UrlErr> {
+    let config = config.get();
+    let slug = slug::normalize(&slug, &config);
+    let case_insensitive = config.case_insensitive_slugs;
+
+    let mut conn = db::get(&pool).await?;
+    let url = alias::resolve(&mut conn, slug, case_insensitive)
+        .await?
+        .ok_or(UrlErr::NotFound)?;
+    let count = diesel::update(urls::table.find(url.slug).filter(urls::campaign.eq(name)))
+        .set((
+            urls::campaign.eq(None::<String>),
+            urls::updated_at.eq(now() as i64),
+        ))
+        .execute(&mut conn)
+        .await?;
+    if count == 0 {
+        return Err(UrlErr::NotFound);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatsParams {
+    /// How many days back to go, 30 by default
+    days: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LinkClicks {
+    slug: String,
+    url: String,
+    clicks: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatsRes {
+    name: String,
+    /// Every click any of its links has had, including those from before they joined it
+    total_clicks: i64,
+    /// Each link's clicks, most clicked first
+    links: Vec<LinkClicks>,
+    days: u64,
+    /// Clicks per day on all of its links together
+    daily: Vec<Bucket>,
+}
+
+/// The clicks on a campaign's links, together and one by one
+pub async fn stats(
+    admin: Option<Admin>,
+    State(ReadPool(pool)): State<ReadPool>,
+    State(config): State<Arc<LiveConfig>>,
+    State(usage): State<Arc<UsageTally>>,
+    Path(name): Path<String>,
+    Query(params): Query<StatsParams>,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+) -> Result<Conditional<StatsRes>, UrlErr> {
+    let config = config.get();
+    if !config.analytics.public_stats && admin.is_none() {
+        return Err(UrlErr::Unauthorized);
+    }
+
+    let days = params.days.unwrap_or(30).clamp(1, MAX_STATS_DAYS);
+    let since = (now() - days * 24 * 60 * 60) as i64;
+
+    let mut conn = db::get(&pool).await?;
+    if !exists(&mut conn, &name).await? {
+        return Err(UrlErr::NotFound);
+    }
+    let version = analytics::campaign_version(&mut conn, &name, since).await?;
+    // pending clicks could be on any of its links
+    let etag = etag::weak(&format!("{}:{}:{}", name, version, usage.version()));
+    if let Some(not_modified) = Conditional::not_modified(&etag, if_none_match.as_ref()) {
+        return Ok(not_modified);
+    }
+    let links = urls::table
+        .filter(urls::campaign.eq(&name))
+        .select((urls::slug, urls::url, urls::usage_count))
+        .load::<(String, String, i32)>(&mut conn)
+        .await?;
+    let daily = analytics::campaign_daily(&mut conn, &name, since).await?;
+    drop(conn);
+
+    let mut links = links
+        .into_iter()
+        .map(|(slug, url, count)| {
+            let pending = usage.pending(&slug);
+            LinkClicks {
+                clicks: i64::from(count) + i64::from(pending),
+                slug,
+                url,
+            }
+        })
+        .collect::<Vec<_>>();
+    links.sort_by(|a, b| b.clicks.cmp(&a.clicks).then_with(|| a.slug.cmp(&b.slug)));
+
+    let res = StatsRes {
+        name,
+        total_clicks: links.iter().map(|l| l.clicks).sum(),
+        links,
+        days,
+        daily,
+    };
+    Ok(Conditional::new(etag, res))
+}
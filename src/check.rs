@@ -0,0 +1,207 @@
+//! `url-shortener --check`, which tries everything the server needs at startup and reports on
+//! each, so that a deployment can be stopped before traffic is sent to a server that won't work.
+//! The database is left as it was: migrations are only tried in a transaction that is rolled back.
+
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use diesel::{
+    connection::SimpleConnection, migration::MigrationSource, prelude::*, sqlite::Sqlite,
+};
+use diesel_migrations::MigrationHarness;
+
+use crate::{
+    config::{self, Config},
+    db::MIGRATIONS,
+    locale,
+    models::Url,
+    schema::urls,
+};
+
+struct Step {
+    name: &'static str,
+    /// What was found, or why it failed
+    result: Result<String, String>,
+}
+
+/// Run every check, printing a line for each, and return whether they all passed
+pub fn run() -> bool {
+    let started = Instant::now();
+    let steps = checks();
+    for step in &steps {
+        let (status, detail) = match &step.result {
+            Ok(detail) => ("ok", detail),
+            Err(err) => ("FAILED", err),
+        };
+        println!("{:<7} {:<16} {}", status, step.name, detail);
+    }
+
+    let failed = steps.iter().filter(|s| s.result.is_err()).count();
+    if failed == 0 {
+        println!(
+            "\nEverything passed in {} ms",
+            started.elapsed().as_millis()
+        );
+    } else {
+        println!("\n{} of {} checks failed", failed, steps.len());
+    }
+    failed == 0
+}
+
+fn checks() -> Vec<Step> {
+    let mut steps = Vec::new();
+    let mut step = |name, result| steps.push(Step { name, result });
+
+    let path = config::path();
+    let config = match Config::load() {
+        Ok(config) if Path::new(&path).exists() => {
+            step("config", Ok(path));
+            config
+        }
+        Ok(config) => {
+            step(
+                "config",
+                Ok(format!("{} doesn't exist, using the defaults", path)),
+            );
+            config
+        }
+        Err(err) => {
+            // everything else depends on it
+            step("config", Err(format!("{}: {}", path, err)));
+            return steps;
+        }
+    };
+
+    match database_file(&config.database_url) {
+        Some(file) => {
+            let dir = match file.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
+            step("data directory", writable(&dir));
+        }
+        None => step("data directory", Ok("in memory, nothing to check".into())),
+    }
+
+    let mut conn = match SqliteConnection::establish(&config.database_url) {
+        Ok(conn) => {
+            step("database", Ok(config.database_url.clone()));
+            conn
+        }
+        Err(err) => {
+            step("database", Err(err.to_string()));
+            return steps;
+        }
+    };
+    // a running server may hold the write lock for a moment
+    let _ = conn.batch_execute("PRAGMA busy_timeout = 5000;");
+
+    match migrations(&mut conn) {
+        Ok((migrations, schema)) => {
+            step("migrations", Ok(migrations));
+            step("schema", schema);
+        }
+        Err(err) => step("migrations", Err(err)),
+    }
+
+    step(
+        "translations",
+        locale::check(&config.locale).map(|count| format!("{} languages", count)),
+    );
+    if config.backup.interval.is_some() && config.backup.s3.is_none() {
+        step("backups", creatable(&config.backup.dir));
+    }
+    if let Some(tls) = &config.tls {
+        step("tls", readable(&[&tls.cert_path, &tls.key_path]));
+    }
+
+    steps
+}
+
+/// The file behind `database_url`, unless the database is in memory
+fn database_file(database_url: &str) -> Option<PathBuf> {
+    let path = database_url
+        .strip_prefix("sqlite://")
+        .or_else(|| database_url.strip_prefix("file:"))
+        .unwrap_or(database_url);
+    let (path, query) = path.split_once('?').unwrap_or((path, ""));
+    if path.is_empty() || path == ":memory:" || query.split('&').any(|p| p == "mode=memory") {
+        return None;
+    }
+    Some(PathBuf::from(path))
+}
+
+/// SQLite writes its journal next to the database, so the whole directory has to be writable
+fn writable(dir: &Path) -> Result<String, String> {
+    let probe = dir.join(format!(".url-shortener-check-{}", std::process::id()));
+    std::fs::write(&probe, b"").map_err(|e| format!("{} isn't writable: {}", dir.display(), e))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(format!("{} is writable", dir.display()))
+}
+
+/// Like [`writable`], for directories that are made when they are first needed
+fn creatable(dir: &Path) -> Result<String, String> {
+    let mut existing = dir;
+    while !existing.exists() {
+        match existing.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => existing = parent,
+            _ => return writable(Path::new(".")),
+        }
+    }
+    writable(existing)
+}
+
+fn readable(paths: &[&PathBuf]) -> Result<String, String> {
+    for path in paths {
+        std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    Ok("the certificate and key can be read".into())
+}
+
+/// Check that the database hasn't been migrated by a newer build, and that any pending
+/// migrations apply, then check the schema as it would be after them
+fn migrations(conn: &mut SqliteConnection) -> Result<(String, Result<String, String>), String> {
+    let known = MigrationSource::<Sqlite>::migrations(&MIGRATIONS)
+        .map_err(|e| e.to_string())?
+        .iter()
+        .map(|m| m.name().version().to_string())
+        .collect::<Vec<_>>();
+    let unknown = conn
+        .applied_migrations()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|v| v.to_string())
+        .filter(|v| !known.contains(v))
+        .collect::<Vec<_>>();
+    if !unknown.is_empty() {
+        return Err(format!(
+            "the database has migrations this build doesn't know about ({}), it was run by a \
+             newer version",
+            unknown.join(", ")
+        ));
+    }
+
+    let mut checked = None;
+    let tried = conn.transaction::<(), Box<dyn Error + Send + Sync>, _>(|conn| {
+        let pending = conn.run_pending_migrations(MIGRATIONS)?.len();
+        let schema = urls::table
+            .select(Url::as_select())
+            .limit(1)
+            .load::<Url>(conn)
+            .map(|_| "matches this build".to_string())
+            .map_err(|e| e.to_string());
+        checked = Some((pending, schema));
+        // nothing is kept, the server runs them itself when it starts
+        Err(diesel::result::Error::RollbackTransaction.into())
+    });
+    let Some((pending, schema)) = checked else {
+        return Err(tried.err().map(|e| e.to_string()).unwrap_or_default());
+    };
+    match pending {
+        0 => Ok(("up to date".into(), schema)),
+        _ => Ok((format!("{} pending, they apply cleanly", pending), schema)),
+    }
+}
@@ -0,0 +1,156 @@
+//! Tokens that hand a link to a new owner.  One is made by an admin or the link's current owner
+//! and given to whoever should get the link, who redeems it with their email address.
+//!
+//! Each token names the owner at the time it was made, so it stops working once the link has
+//! changed hands.
+
+use crate::{error::UrlErr, signed};
+
+/// How long a claim token can be redeemed for, in seconds
+pub const CLAIM_TTL: u64 = 7 * 24 * 60 * 60;
+
+/// Starts the value of every claim token, emails can't contain a newline so these can't be
+/// mistaken for a notification preferences token
+const PREFIX: &str = "claim\n";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Claim {
+    pub slug: String,
+    /// The owner when the token was made, `None` for a link without one
+    pub owner: Option<String>,
+    /// In seconds since the unix epoch
+    pub expires_at: u64,
+}
+
+impl Claim {
+    pub fn new(slug: String, owner: Option<String>) -> Self {
+        Self {
+            slug,
+            owner,
+            expires_at: signed::now() + CLAIM_TTL,
+        }
+    }
+
+    pub fn token(&self, key: &str) -> String {
+        let value = format!(
+            "{}{}\n{}\n{}",
+            PREFIX,
+            self.slug,
+            self.owner.as_deref().unwrap_or_default(),
+            self.expires_at
+        );
+        signed::token(&value, key)
+    }
+
+    /// Check a token made by [`Claim::token`], which must not have expired
+    pub fn verify(token: &str, key: &str) -> Result<Self, UrlErr> {
+        let value = signed::check_token(token, key)?;
+        let mut parts = value
+            .strip_prefix(PREFIX)
+            .ok_or(UrlErr::Unauthorized)?
+            .split('\n');
+        let (Some(slug), Some(owner), Some(expires_at), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(UrlErr::Unauthorized);
+        };
+
+        let expires_at = expires_at.parse().map_err(|_| UrlErr::Unauthorized)?;
+        if expires_at <= signed::now() {
+            return Err(UrlErr::StaleClaim);
+        }
+        Ok(Self {
+            slug: slug.to_string(),
+            owner: (!owner.is_empty()).then(|| owner.to_string()),
+            expires_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "test key";
+
+    fn expires_at() -> u64 {
+        signed::now() + 60
+    }
+
+    #[test]
+    fn round_trip() {
+        let claim = Claim::new("abc".into(), Some("owner@example.com".into()));
+        assert_eq!(Claim::verify(&claim.token(KEY), KEY).unwrap(), claim);
+
+        let claim = Claim::new("abc".into(), None);
+        assert_eq!(Claim::verify(&claim.token(KEY), KEY).unwrap(), claim);
+    }
+
+    #[test]
+    fn wrong_key() {
+        let token = Claim::new("abc".into(), None).token(KEY);
+        assert!(matches!(
+            Claim::verify(&token, "other key"),
+            Err(UrlErr::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn wrong_prefix() {
+        // a notification preferences token is signed with the same key
+        let token = signed::token("owner@example.com", KEY);
+        assert!(matches!(
+            Claim::verify(&token, KEY),
+            Err(UrlErr::Unauthorized)
+        ));
+
+        let value = format!("other\nabc\n\n{}", expires_at());
+        let token = signed::token(&value, KEY);
+        assert!(matches!(
+            Claim::verify(&token, KEY),
+            Err(UrlErr::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn extra_fields() {
+        let value = format!("{}abc\nowner@example.com\n{}\nmore", PREFIX, expires_at());
+        let token = signed::token(&value, KEY);
+        assert!(matches!(
+            Claim::verify(&token, KEY),
+            Err(UrlErr::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn missing_fields() {
+        let value = format!("{}abc\n{}", PREFIX, expires_at());
+        let token = signed::token(&value, KEY);
+        assert!(matches!(
+            Claim::verify(&token, KEY),
+            Err(UrlErr::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn bad_expiry() {
+        let value = format!("{}abc\n\nsoon", PREFIX);
+        let token = signed::token(&value, KEY);
+        assert!(matches!(
+            Claim::verify(&token, KEY),
+            Err(UrlErr::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn expired() {
+        let claim = Claim {
+            expires_at: signed::now() - 1,
+            ..Claim::new("abc".into(), None)
+        };
+        assert!(matches!(
+            Claim::verify(&claim.token(KEY), KEY),
+            Err(UrlErr::StaleClaim)
+        ));
+    }
+}
@@ -0,0 +1,434 @@
+use std::{
+    fmt,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
+};
+
+use axum::http::{HeaderName, HeaderValue, Method};
+use serde::Deserialize;
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer};
+use tracing::{info, warn};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+use crate::{
+    analytics::AnalyticsConfig,
+    backup::BackupConfig,
+    ban::BanConfig,
+    cache::CacheConfig,
+    db::PoolConfig,
+    honeypot::HoneypotConfig,
+    integrations::IntegrationsConfig,
+    ip::ClientIpConfig,
+    locale::LocaleConfig,
+    logging::LogOutput,
+    mail::MailConfig,
+    preview::PreviewConfig,
+    quota::QuotaConfig,
+    redirect_limit::RedirectLimitConfig,
+    scan::ScanGuardConfig,
+    slug::{SlugCharset, SlugStrategy},
+    spam::SpamConfig,
+    unfurl::UnfurlConfig,
+    usage::UsageConfig,
+};
+
+/// The environment variable that can be used to point at a different config file
+pub const CONFIG_PATH_VAR: &str = "URL_SHORTENER_CONFIG";
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// Where the config is read from, see [`Config::load`]
+pub fn path() -> String {
+    std::env::var(CONFIG_PATH_VAR).unwrap_or_else(|_| DEFAULT_CONFIG_PATH.into())
+}
+
+#[derive(Debug)]
+pub enum ConfigErr {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    /// Parsed, but a value can't be used
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigErr::Io(err) => write!(f, "Unable to read config file: {}", err),
+            ConfigErr::Parse(err) => write!(f, "Unable to parse config file: {}", err),
+            ConfigErr::Invalid(msg) => write!(f, "Invalid config: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ConfigErr {}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub database_url: String,
+    /// Sizes and timeouts for the database connection pools, only read at startup
+    pub pool: PoolConfig,
+    /// Batching usage count updates, only read at startup
+    pub usage_counts: UsageConfig,
+    /// Recording clicks for each link's stats
+    pub analytics: AnalyticsConfig,
+    /// Emailing link owners
+    pub mail: MailConfig,
+    /// Slack and Telegram bots that shorten links
+    pub integrations: IntegrationsConfig,
+    pub bind: SocketAddr,
+    /// Another address to serve on, opened IPv6-only so that IPv4 and IPv6 can be served on the
+    /// same port by separate listeners (`bind = "0.0.0.0:3000"` and `bind_v6 = "[::]:3000"`).
+    /// Only read at startup.
+    pub bind_v6: Option<SocketAddr>,
+    /// Serve the admin api, the dashboard, metrics and health checks on this address (such as
+    /// `127.0.0.1:3001`) instead of `bind`, only read at startup
+    pub admin_bind: Option<SocketAddr>,
+    pub cors: CorsConfig,
+    pub client_ip: ClientIpConfig,
+    pub limits: LimitsConfig,
+    /// Blocking clients that ask for lots of slugs that don't exist
+    pub scan_guard: ScanGuardConfig,
+    /// Temporarily banning clients that abuse the server
+    pub bans: BanConfig,
+    /// Answering requests for paths that only scanners ask for
+    pub honeypot: HoneypotConfig,
+    /// Limits on how many links each client may have and create
+    pub quotas: QuotaConfig,
+    /// Refusing or holding urls that look like spam
+    pub spam: SpamConfig,
+    /// Capping how often each link may be followed
+    pub redirect_limit: RedirectLimitConfig,
+    /// Caching slugs in Redis, only read at startup
+    pub cache: CacheConfig,
+    /// Periodic backups of the database, only read at startup
+    pub backup: BackupConfig,
+    /// Screenshots of where links go
+    pub preview: PreviewConfig,
+    /// Translations of error messages and pages, only read at startup
+    pub locale: LocaleConfig,
+    /// Link previews for chat apps' unfurl bots
+    pub unfurl: UnfurlConfig,
+    /// Compress responses with gzip or brotli when the client supports it
+    pub compression: bool,
+    /// Accept HTTP/2, over TLS via ALPN or as h2c with prior knowledge on a plain listener
+    pub http2: bool,
+    /// Serve over TLS instead of plain HTTP
+    pub tls: Option<TlsConfig>,
+    /// The `tracing` filter directives, `RUST_LOG` takes precedence if it is set
+    pub log_filter: String,
+    /// Where logs are written, only read at startup
+    pub log_output: LogOutput,
+    /// The bearer token for the admin endpoints, which are disabled if this is not set
+    pub admin_token: Option<String>,
+    /// The `token` for `GET /new`, which is disabled if this is not set.  It is kept separate
+    /// from `admin_token` since it ends up in bookmarks and browser history.
+    pub bookmarklet_token: Option<String>,
+    /// Refuse every change (with a 503) while redirects keep working, for maintenance.  Admins
+    /// can also turn this on while the server runs, see [`LiveConfig::set_read_only`].
+    pub read_only: bool,
+    /// The `Cache-Control` header sent with redirects, unless the url sets its own
+    pub redirect_cache_control: Option<String>,
+    /// Where visitors are sent when a slug doesn't exist, instead of getting a 404.  `{slug}` is
+    /// replaced with the slug they asked for, url-encoded.
+    pub fallback_url: Option<String>,
+    /// Slugs that may not be used, on top of [`crate::slug::RESERVED_SLUGS`]
+    pub reserved_slugs: Vec<String>,
+    /// Treat `Promo` and `promo` as the same slug, new slugs are stored in lower case
+    pub case_insensitive_slugs: bool,
+    /// Which characters may be used in custom slugs
+    pub slug_charset: SlugCharset,
+    /// Re-roll random slugs that contain a word from the profanity list
+    pub profanity_filter: bool,
+    /// Words to filter on top of the bundled list
+    pub profanity_words: Vec<String>,
+    /// How slugs are generated when the user doesn't pick one
+    pub slug_strategy: SlugStrategy,
+    /// Salt for [`SlugStrategy::Hashids`], changing it changes every slug generated after
+    pub hashids_salt: String,
+    /// The shortest slug that [`SlugStrategy::Hashids`] will generate
+    pub hashids_min_length: usize,
+    /// Secret used to sign temporary links, which are disabled if this is not set
+    pub signing_key: Option<String>,
+    /// The longest (in seconds) that a signed link may last
+    pub signed_link_max_ttl: u64,
+    /// Where the shortener is publicly reachable, for full links in emails and chat replies
+    pub base_url: Option<String>,
+    /// Domains (and their subdomains) that may not be shortened
+    pub blocked_domains: Vec<String>,
+    /// Url schemes that may be shortened, schemes in [`crate::destination::UNSAFE_SCHEMES`]
+    /// never are
+    pub allowed_schemes: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            database_url: "sqlite://db/db.sqlite".into(),
+            pool: PoolConfig::default(),
+            usage_counts: UsageConfig::default(),
+            analytics: AnalyticsConfig::default(),
+            mail: MailConfig::default(),
+            quotas: QuotaConfig::default(),
+            spam: SpamConfig::default(),
+            integrations: IntegrationsConfig::default(),
+            bind: ([0, 0, 0, 0], 3000).into(),
+            bind_v6: None,
+            admin_bind: None,
+            cors: CorsConfig::default(),
+            client_ip: ClientIpConfig::default(),
+            limits: LimitsConfig::default(),
+            scan_guard: ScanGuardConfig::default(),
+            bans: BanConfig::default(),
+            honeypot: HoneypotConfig::default(),
+            redirect_limit: RedirectLimitConfig::default(),
+            cache: CacheConfig::default(),
+            backup: BackupConfig::default(),
+            preview: PreviewConfig::default(),
+            locale: LocaleConfig::default(),
+            unfurl: UnfurlConfig::default(),
+            compression: true,
+            http2: true,
+            tls: None,
+            log_filter: "url_shortener=debug,tower_http=debug".into(),
+            log_output: LogOutput::default(),
+            admin_token: None,
+            bookmarklet_token: None,
+            read_only: false,
+            redirect_cache_control: None,
+            fallback_url: None,
+            reserved_slugs: Vec::new(),
+            case_insensitive_slugs: false,
+            slug_charset: SlugCharset::default(),
+            profanity_filter: true,
+            profanity_words: Vec::new(),
+            slug_strategy: SlugStrategy::default(),
+            hashids_salt: String::new(),
+            hashids_min_length: 4,
+            signing_key: None,
+            signed_link_max_ttl: 7 * 24 * 60 * 60,
+            base_url: None,
+            blocked_domains: Vec::new(),
+            allowed_schemes: vec!["http".into(), "https".into()],
+        }
+    }
+}
+
+impl Config {
+    /// Load the config from the path in [`CONFIG_PATH_VAR`], falling back to
+    /// [`DEFAULT_CONFIG_PATH`].  If the file does not exist, the defaults are used.
+    pub fn load() -> Result<Self, ConfigErr> {
+        Self::load_from(path())
+    }
+
+    pub fn load_from(path: impl AsRef<Path>) -> Result<Self, ConfigErr> {
+        match std::fs::read_to_string(path) {
+            Ok(s) => toml::from_str::<Self>(&s)
+                .map_err(ConfigErr::Parse)?
+                .validate(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(ConfigErr::Io(err)),
+        }
+    }
+
+    fn validate(self) -> Result<Self, ConfigErr> {
+        // links are only ever stored in SQLite, Redis can only sit in front of it
+        if self.database_url.starts_with("redis://") || self.database_url.starts_with("rediss://") {
+            return Err(ConfigErr::Invalid(
+                "database_url must be SQLite, Redis is only supported as a cache (`redis_url` under `[cache]`)"
+                    .into(),
+            ));
+        }
+        Ok(self)
+    }
+
+    /// Build the log filter, preferring `RUST_LOG` over [`Config::log_filter`]
+    pub fn env_filter(&self) -> EnvFilter {
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&self.log_filter))
+    }
+
+    /// [`Config::base_url`] without a trailing `/`, or an empty string if it isn't set so that
+    /// links are relative
+    pub fn base_url(&self) -> &str {
+        self.base_url.as_deref().unwrap_or("").trim_end_matches('/')
+    }
+}
+
+/// The config that the server is currently using, which can be swapped out while it runs.
+///
+/// Only settings that are read per request (and the log filter) take effect on reload, the
+/// listener, database and middleware settings need a restart.
+#[derive(Debug)]
+pub struct LiveConfig {
+    config: RwLock<Arc<Config>>,
+    log_filter: reload::Handle<EnvFilter, Registry>,
+    /// Read-only mode turned on by an admin, which lasts until they turn it off or the server
+    /// restarts
+    read_only: AtomicBool,
+}
+
+impl LiveConfig {
+    pub fn new(config: Config, log_filter: reload::Handle<EnvFilter, Registry>) -> Self {
+        Self {
+            config: RwLock::new(Arc::new(config)),
+            log_filter,
+            read_only: AtomicBool::new(false),
+        }
+    }
+
+    /// Get the current config
+    pub fn get(&self) -> Arc<Config> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Whether changes are refused, because of the config or an admin
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed) || self.get().read_only
+    }
+
+    /// Turn read-only mode on or off while the server runs.  Turning it off has no effect while
+    /// the config has `read_only` set.
+    pub fn set_read_only(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::Relaxed);
+        info!(
+            "Read-only mode turned {}",
+            if read_only { "on" } else { "off" }
+        );
+    }
+
+    /// Re-read the config file and swap it in
+    pub fn reload(&self) -> Result<(), ConfigErr> {
+        let config = Config::load()?;
+
+        if let Err(err) = self.log_filter.reload(config.env_filter()) {
+            warn!("Unable to reload log filter: {}", err);
+        }
+
+        *self.config.write().unwrap() = Arc::new(config);
+        info!("Reloaded config");
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TlsConfig {
+    /// PEM encoded certificate chain
+    pub cert_path: PathBuf,
+    /// PEM encoded private key
+    pub key_path: PathBuf,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+    /// The largest request body (in bytes) that will be accepted
+    pub max_body_size: usize,
+    /// How long (in seconds) a request may take, including reading the body, before it is
+    /// cancelled
+    pub request_timeout: u64,
+    /// How long (in seconds) a client has to send its request headers
+    pub header_read_timeout: u64,
+    /// How many requests may be handled at once, any more get a 503.  `0` removes the limit.
+    pub max_concurrent_requests: usize,
+    /// The `Retry-After` (in seconds) sent with those 503s
+    pub retry_after: u64,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_body_size: 16 * 1024,
+            request_timeout: 30,
+            header_read_timeout: 10,
+            max_concurrent_requests: 512,
+            retry_after: 1,
+        }
+    }
+}
+
+impl LimitsConfig {
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_secs(self.request_timeout)
+    }
+
+    pub fn header_read_timeout(&self) -> Duration {
+        Duration::from_secs(self.header_read_timeout)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Origins that may call the API, `"*"` allows any origin.  CORS is disabled if this is
+    /// empty.
+    pub allowed_origins: Vec<String>,
+    /// Methods that may be used, defaults to `GET` and `POST` if empty.  `"*"` allows any
+    /// method.
+    pub allowed_methods: Vec<String>,
+    /// Headers that may be sent, defaults to `Content-Type` if empty.  `"*"` allows any
+    /// header.
+    pub allowed_headers: Vec<String>,
+    /// How long (in seconds) the browser may cache the preflight response
+    pub max_age: Option<u64>,
+}
+
+impl CorsConfig {
+    /// Build the [`CorsLayer`] for this config, or `None` if CORS is not enabled.
+    ///
+    /// Invalid entries are logged and skipped.
+    pub fn layer(&self) -> Option<CorsLayer> {
+        if self.allowed_origins.is_empty() {
+            return None;
+        }
+
+        let origins = if self.allowed_origins.iter().any(|o| o == "*") {
+            AllowOrigin::any()
+        } else {
+            AllowOrigin::list(parse_all::<HeaderValue>(&self.allowed_origins, "origin"))
+        };
+
+        let methods = if self.allowed_methods.is_empty() {
+            AllowMethods::list([Method::GET, Method::POST])
+        } else if self.allowed_methods.iter().any(|m| m == "*") {
+            AllowMethods::any()
+        } else {
+            AllowMethods::list(parse_all::<Method>(&self.allowed_methods, "method"))
+        };
+
+        let headers = if self.allowed_headers.is_empty() {
+            AllowHeaders::list([axum::http::header::CONTENT_TYPE])
+        } else if self.allowed_headers.iter().any(|h| h == "*") {
+            AllowHeaders::any()
+        } else {
+            AllowHeaders::list(parse_all::<HeaderName>(&self.allowed_headers, "header"))
+        };
+
+        let mut layer = CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(methods)
+            .allow_headers(headers);
+
+        if let Some(max_age) = self.max_age {
+            layer = layer.max_age(Duration::from_secs(max_age));
+        }
+
+        Some(layer)
+    }
+}
+
+fn parse_all<T: std::str::FromStr>(values: &[String], kind: &str) -> Vec<T> {
+    values
+        .iter()
+        .filter_map(|v| match v.parse() {
+            Ok(v) => Some(v),
+            Err(_) => {
+                warn!("Ignoring invalid CORS {} in config: {:?}", kind, v);
+                None
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,150 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use deadpool::{managed::PoolError, Runtime};
+use diesel::SqliteConnection;
+use diesel_async::{
+    pooled_connection::{
+        deadpool::{Hook, HookError, Object},
+        AsyncDieselConnectionManager,
+    },
+    sync_connection_wrapper::SyncConnectionWrapper,
+    SimpleAsyncConnection,
+};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use serde::Deserialize;
+use tracing::error;
+
+use crate::error::UrlErr;
+
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
+
+/// Run on every new connection, waiting for the writer rather than failing straight away with
+/// `SQLITE_BUSY`
+const CONNECTION_PRAGMAS: &str = "PRAGMA busy_timeout = 5000;";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PoolConfig {
+    /// How many connections are kept for reads, writes always go through a single connection
+    pub read_size: usize,
+    /// How long (in seconds) a request waits for a free connection before failing
+    pub wait_timeout: u64,
+    /// How long (in seconds) a connection is kept before it is replaced, forever if not set
+    pub max_lifetime: Option<u64>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            read_size: 4,
+            wait_timeout: 5,
+            max_lifetime: None,
+        }
+    }
+}
+
+/// How long requests have spent waiting for connections, across both pools
+#[derive(Debug, Default)]
+pub struct WaitStats {
+    pub count: AtomicU64,
+    pub total_micros: AtomicU64,
+    /// Waits that gave up after `wait_timeout`
+    pub timeouts: AtomicU64,
+}
+
+pub static WAIT_STATS: WaitStats = WaitStats {
+    count: AtomicU64::new(0),
+    total_micros: AtomicU64::new(0),
+    timeouts: AtomicU64::new(0),
+};
+
+/// A database connection.  SQLite has no async driver, so each query is run on tokio's blocking
+/// thread pool rather than on the thread running the handler.
+pub type Connection = SyncConnectionWrapper<SqliteConnection>;
+
+pub type Pool = diesel_async::pooled_connection::deadpool::Pool<Connection>;
+
+/// Take a connection from `pool`, waiting up to `wait_timeout` for one to be free
+pub async fn get(pool: &Pool) -> Result<Object<Connection>, UrlErr> {
+    let started = Instant::now();
+    let conn = pool.get().await;
+    WAIT_STATS.count.fetch_add(1, Ordering::Relaxed);
+    WAIT_STATS
+        .total_micros
+        .fetch_add(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+
+    conn.map_err(|err| {
+        if let PoolError::Timeout(_) = err {
+            WAIT_STATS.timeouts.fetch_add(1, Ordering::Relaxed);
+        }
+        error!("Unable to get a database connection: {}", err);
+        UrlErr::DBError
+    })
+}
+
+/// Pooled connections that may only read, so that redirects don't queue up behind writes
+#[derive(Clone)]
+pub struct ReadPool(pub Pool);
+
+/// Build the pool for writes.  SQLite only allows one writer at a time, so it only ever has one
+/// connection, and puts the database in WAL mode so that readers aren't blocked by it.
+pub fn write_pool(database_url: &str, config: &PoolConfig) -> Pool {
+    build_pool(
+        database_url,
+        config,
+        1,
+        "PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;",
+    )
+}
+
+pub fn read_pool(database_url: &str, config: &PoolConfig) -> ReadPool {
+    ReadPool(build_pool(
+        database_url,
+        config,
+        config.read_size,
+        "PRAGMA query_only = ON;",
+    ))
+}
+
+fn build_pool(database_url: &str, config: &PoolConfig, size: usize, pragmas: &'static str) -> Pool {
+    let manager = AsyncDieselConnectionManager::new(database_url);
+    let max_lifetime = config.max_lifetime.map(Duration::from_secs);
+    Pool::builder(manager)
+        .max_size(size)
+        .runtime(Runtime::Tokio1)
+        .wait_timeout(Some(Duration::from_secs(config.wait_timeout)))
+        .pre_recycle(Hook::sync_fn(move |_, metrics| match max_lifetime {
+            // dropping the connection makes the pool open a new one
+            Some(max) if metrics.age() > max => Err(HookError::message("too old")),
+            _ => Ok(()),
+        }))
+        .post_create(Hook::async_fn(move |conn: &mut Connection, _| {
+            Box::pin(async move {
+                conn.batch_execute(CONNECTION_PRAGMAS)
+                    .await
+                    .map_err(|e| HookError::message(e.to_string()))?;
+                conn.batch_execute(pragmas)
+                    .await
+                    .map_err(|e| HookError::message(e.to_string()))
+            })
+        }))
+        .build()
+        .expect("the pool has a runtime for its timeouts")
+}
+
+/// Bring the database up to date with the migrations in `migrations/`
+pub async fn run_migrations(pool: &Pool) -> Result<(), String> {
+    let mut conn = pool.get().await.map_err(|e| e.to_string())?;
+    // the migration harness is synchronous, so it gets the connection underneath
+    conn.spawn_blocking(|conn| {
+        Ok(conn
+            .run_pending_migrations(MIGRATIONS)
+            .map(|_| ())
+            .map_err(|e| e.to_string()))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
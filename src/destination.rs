@@ -0,0 +1,48 @@
+//! Rules about which urls may be shortened
+
+use crate::{config::Config, error::UrlErr};
+
+/// Schemes that run code or read local files when followed, which can't be allowed
+pub const UNSAFE_SCHEMES: &[&str] = &["javascript", "data", "vbscript", "file", "blob"];
+
+/// Whether browsers follow the url as a normal page, other schemes hand off to another app
+pub fn is_web(url: &url::Url) -> bool {
+    matches!(url.scheme(), "http" | "https")
+}
+
+/// Fail unless the url's scheme is one of [`Config::allowed_schemes`]
+pub fn check_scheme(url: &url::Url, config: &Config) -> Result<(), UrlErr> {
+    let scheme = url.scheme();
+    let allowed = !UNSAFE_SCHEMES.contains(&scheme)
+        && config
+            .allowed_schemes
+            .iter()
+            .any(|s| s.eq_ignore_ascii_case(scheme));
+    if allowed {
+        Ok(())
+    } else {
+        Err(UrlErr::SchemeNotAllowed)
+    }
+}
+
+/// Whether the url's host is one of [`Config::blocked_domains`] or a subdomain of one
+pub fn is_blocked(url: &url::Url, config: &Config) -> bool {
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    let host = host.trim_end_matches('.').to_lowercase();
+
+    config
+        .blocked_domains
+        .iter()
+        .any(|domain| is_under(&host, domain))
+}
+
+/// Whether `host` (trimmed and in lower case) is `domain` or a subdomain of it
+pub fn is_under(host: &str, domain: &str) -> bool {
+    let domain = domain.trim_end_matches('.').to_lowercase();
+    host == domain
+        || host
+            .strip_suffix(&domain)
+            .is_some_and(|sub| sub.ends_with('.'))
+}
@@ -0,0 +1,278 @@
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use fluent_bundle::FluentArgs;
+use serde::Serialize;
+use tracing::error;
+
+use crate::{locale, request_id};
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// A stable identifier for each kind of error, for clients that need to tell them apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    SlugOccupied,
+    SlugReserved,
+    InvalidSlug,
+    SlugTooManyTries,
+    DatabaseError,
+    InvalidJson,
+    InvalidUrl,
+    InvalidCacheControl,
+    InvalidEmail,
+    InvalidCursor,
+    Unauthorized,
+    FeatureDisabled,
+    NotFound,
+    Expired,
+    RateLimited,
+    Banned,
+    BlockedDestination,
+    Overloaded,
+    LinkDisabled,
+    StaleClaim,
+    QuotaExceeded,
+    LikelySpam,
+    SchemeNotAllowed,
+    InvalidCampaign,
+    CampaignExists,
+    InvalidTemplate,
+    PreviewUnavailable,
+    InvalidPage,
+    ReadOnly,
+    InvalidDescription,
+    InvalidMessage,
+    UnsupportedFormat,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::SlugOccupied => "slug_occupied",
+            ErrorCode::SlugReserved => "slug_reserved",
+            ErrorCode::InvalidSlug => "invalid_slug",
+            ErrorCode::SlugTooManyTries => "slug_too_many_tries",
+            ErrorCode::DatabaseError => "database_error",
+            ErrorCode::InvalidJson => "invalid_json",
+            ErrorCode::InvalidUrl => "invalid_url",
+            ErrorCode::InvalidCacheControl => "invalid_cache_control",
+            ErrorCode::InvalidEmail => "invalid_email",
+            ErrorCode::InvalidCursor => "invalid_cursor",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::FeatureDisabled => "feature_disabled",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::Expired => "expired",
+            ErrorCode::RateLimited => "rate_limited",
+            ErrorCode::Banned => "banned",
+            ErrorCode::BlockedDestination => "blocked_destination",
+            ErrorCode::Overloaded => "overloaded",
+            ErrorCode::LinkDisabled => "link_disabled",
+            ErrorCode::StaleClaim => "stale_claim",
+            ErrorCode::QuotaExceeded => "quota_exceeded",
+            ErrorCode::LikelySpam => "likely_spam",
+            ErrorCode::SchemeNotAllowed => "scheme_not_allowed",
+            ErrorCode::InvalidCampaign => "invalid_campaign",
+            ErrorCode::CampaignExists => "campaign_exists",
+            ErrorCode::InvalidTemplate => "invalid_template",
+            ErrorCode::PreviewUnavailable => "preview_unavailable",
+            ErrorCode::InvalidPage => "invalid_page",
+            ErrorCode::ReadOnly => "read_only",
+            ErrorCode::InvalidDescription => "invalid_description",
+            ErrorCode::InvalidMessage => "invalid_message",
+            ErrorCode::UnsupportedFormat => "unsupported_format",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum UrlErr {
+    SlugOccupied,
+    SlugReserved,
+    InvalidSlug,
+    SlugTooManyTries,
+    DBError,
+    JsonError(serde_json::Error),
+    InvalidUrl(url::ParseError),
+    InvalidCacheControl,
+    InvalidEmail,
+    InvalidCursor,
+    Unauthorized,
+    /// The named feature is turned off in the config
+    FeatureDisabled(&'static str),
+    NotFound,
+    Expired,
+    RateLimited,
+    Banned,
+    BlockedDestination,
+    Overloaded,
+    LinkDisabled,
+    StaleClaim,
+    /// The named quota has been used up
+    QuotaExceeded(&'static str),
+    LikelySpam,
+    SchemeNotAllowed,
+    InvalidCampaign,
+    CampaignExists,
+    InvalidTemplate,
+    PreviewUnavailable,
+    /// Why the page was refused
+    InvalidPage(&'static str),
+    ReadOnly,
+    InvalidDescription,
+    InvalidMessage,
+    /// Asked for a response in a format other than json
+    UnsupportedFormat,
+}
+
+impl UrlErr {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            UrlErr::SlugOccupied => ErrorCode::SlugOccupied,
+            UrlErr::SlugReserved => ErrorCode::SlugReserved,
+            UrlErr::InvalidSlug => ErrorCode::InvalidSlug,
+            UrlErr::SlugTooManyTries => ErrorCode::SlugTooManyTries,
+            UrlErr::DBError => ErrorCode::DatabaseError,
+            UrlErr::JsonError(_) => ErrorCode::InvalidJson,
+            UrlErr::InvalidUrl(_) => ErrorCode::InvalidUrl,
+            UrlErr::InvalidCacheControl => ErrorCode::InvalidCacheControl,
+            UrlErr::InvalidEmail => ErrorCode::InvalidEmail,
+            UrlErr::InvalidCursor => ErrorCode::InvalidCursor,
+            UrlErr::Unauthorized => ErrorCode::Unauthorized,
+            UrlErr::FeatureDisabled(_) => ErrorCode::FeatureDisabled,
+            UrlErr::NotFound => ErrorCode::NotFound,
+            UrlErr::Expired => ErrorCode::Expired,
+            UrlErr::RateLimited => ErrorCode::RateLimited,
+            UrlErr::Banned => ErrorCode::Banned,
+            UrlErr::BlockedDestination => ErrorCode::BlockedDestination,
+            UrlErr::Overloaded => ErrorCode::Overloaded,
+            UrlErr::LinkDisabled => ErrorCode::LinkDisabled,
+            UrlErr::StaleClaim => ErrorCode::StaleClaim,
+            UrlErr::QuotaExceeded(_) => ErrorCode::QuotaExceeded,
+            UrlErr::LikelySpam => ErrorCode::LikelySpam,
+            UrlErr::SchemeNotAllowed => ErrorCode::SchemeNotAllowed,
+            UrlErr::InvalidCampaign => ErrorCode::InvalidCampaign,
+            UrlErr::CampaignExists => ErrorCode::CampaignExists,
+            UrlErr::InvalidTemplate => ErrorCode::InvalidTemplate,
+            UrlErr::PreviewUnavailable => ErrorCode::PreviewUnavailable,
+            UrlErr::InvalidPage(_) => ErrorCode::InvalidPage,
+            UrlErr::ReadOnly => ErrorCode::ReadOnly,
+            UrlErr::InvalidDescription => ErrorCode::InvalidDescription,
+            UrlErr::InvalidMessage => ErrorCode::InvalidMessage,
+            UrlErr::UnsupportedFormat => ErrorCode::UnsupportedFormat,
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            UrlErr::SlugOccupied | UrlErr::SlugReserved | UrlErr::CampaignExists => {
+                StatusCode::CONFLICT
+            }
+            UrlErr::SlugTooManyTries => StatusCode::REQUEST_TIMEOUT,
+            UrlErr::DBError => StatusCode::INTERNAL_SERVER_ERROR,
+            UrlErr::JsonError(_)
+            | UrlErr::InvalidSlug
+            | UrlErr::InvalidUrl(_)
+            | UrlErr::InvalidCacheControl
+            | UrlErr::InvalidEmail
+            | UrlErr::InvalidCursor
+            | UrlErr::BlockedDestination
+            | UrlErr::LikelySpam
+            | UrlErr::SchemeNotAllowed
+            | UrlErr::InvalidCampaign
+            | UrlErr::InvalidTemplate
+            | UrlErr::InvalidPage(_)
+            | UrlErr::InvalidDescription
+            | UrlErr::InvalidMessage => StatusCode::BAD_REQUEST,
+            UrlErr::Unauthorized => StatusCode::UNAUTHORIZED,
+            UrlErr::NotFound | UrlErr::FeatureDisabled(_) => StatusCode::NOT_FOUND,
+            UrlErr::Expired | UrlErr::LinkDisabled | UrlErr::StaleClaim => StatusCode::GONE,
+            UrlErr::RateLimited | UrlErr::QuotaExceeded(_) => StatusCode::TOO_MANY_REQUESTS,
+            UrlErr::Banned => StatusCode::FORBIDDEN,
+            UrlErr::Overloaded | UrlErr::ReadOnly => StatusCode::SERVICE_UNAVAILABLE,
+            UrlErr::PreviewUnavailable => StatusCode::BAD_GATEWAY,
+            UrlErr::UnsupportedFormat => StatusCode::NOT_IMPLEMENTED,
+        }
+    }
+
+    /// What went wrong, in the language of the request being handled
+    pub fn title(&self) -> String {
+        locale::text(self.code().as_str(), None)
+    }
+
+    /// An explanation of this specific occurrence, in the language of the request being handled
+    pub fn detail(&self) -> String {
+        let id = format!("{}.detail", self.code().as_str());
+        locale::text(&id, self.args().as_ref())
+    }
+
+    /// What [`UrlErr::detail`] fills in
+    fn args(&self) -> Option<FluentArgs<'static>> {
+        let (name, value) = match self {
+            UrlErr::JsonError(err) => ("error", err.to_string()),
+            UrlErr::InvalidUrl(err) => ("error", err.to_string()),
+            UrlErr::FeatureDisabled(feature) => ("feature", feature.to_string()),
+            UrlErr::QuotaExceeded(quota) => ("quota", quota.to_string()),
+            UrlErr::InvalidPage(reason) => ("reason", reason.to_string()),
+            _ => return None,
+        };
+        let mut args = FluentArgs::new();
+        args.set(name, value);
+        Some(args)
+    }
+}
+
+impl From<diesel::result::Error> for UrlErr {
+    fn from(err: diesel::result::Error) -> Self {
+        error!("Database error: {}", err);
+        UrlErr::DBError
+    }
+}
+
+/// An RFC 7807 problem details body
+#[derive(Debug, Serialize)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    pub code: ErrorCode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl From<&UrlErr> for Problem {
+    fn from(err: &UrlErr) -> Self {
+        Self {
+            kind: format!("urn:url-shortener:error:{}", err.code().as_str()),
+            title: err.title(),
+            status: err.status().as_u16(),
+            detail: err.detail(),
+            code: err.code(),
+            request_id: request_id::current(),
+        }
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> axum::response::Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut res = (status, Json(self)).into_response();
+        res.headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+        // the title and detail are translated
+        res.headers_mut()
+            .append(header::VARY, HeaderValue::from_static("accept-language"));
+        res
+    }
+}
+
+impl IntoResponse for UrlErr {
+    fn into_response(self) -> axum::response::Response {
+        Problem::from(&self).into_response()
+    }
+}
@@ -0,0 +1,66 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json, TypedHeader,
+};
+use diesel::{sql_types::Text, QueryableByName};
+use headers::{ETag, IfNoneMatch};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// What a response's ETag is made from, read with one aggregate query such as
+/// `SELECT COUNT(*) || ':' || MAX(updated_at) AS version`, so that it is cheap to get
+#[derive(Debug, QueryableByName)]
+pub struct Version {
+    #[diesel(sql_type = Text)]
+    pub version: String,
+}
+
+/// Build a weak ETag from `validator`, which has to change whenever the response would.  It is
+/// hashed with SHA-256 rather than the std hasher, so that it is the same from build to build.
+pub fn weak(validator: &str) -> ETag {
+    let hex = Sha256::digest(validator.as_bytes())[..8]
+        .iter()
+        .fold(String::new(), |hex, b| hex + &format!("{:02x}", b));
+    format!("W/\"{}\"", hex)
+        .parse()
+        .expect("formatted ETag is valid")
+}
+
+/// A json response that honours `If-None-Match`.  Check [`Conditional::not_modified`] first,
+/// so that the body is only built when the client's copy is out of date.
+pub struct Conditional<T> {
+    etag: ETag,
+    body: Option<T>,
+}
+
+impl<T> Conditional<T> {
+    /// A `304 Not Modified` if the client already has the version named by `etag`
+    pub fn not_modified(
+        etag: &ETag,
+        if_none_match: Option<&TypedHeader<IfNoneMatch>>,
+    ) -> Option<Self> {
+        let fresh = if_none_match.is_none_or(|TypedHeader(inm)| inm.precondition_passes(etag));
+        (!fresh).then(|| Self {
+            etag: etag.clone(),
+            body: None,
+        })
+    }
+
+    pub fn new(etag: ETag, body: T) -> Self {
+        Self {
+            etag,
+            body: Some(body),
+        }
+    }
+}
+
+impl<T: Serialize> IntoResponse for Conditional<T> {
+    fn into_response(self) -> Response {
+        let etag = TypedHeader(self.etag);
+        match self.body {
+            Some(body) => (etag, Json(body)).into_response(),
+            None => (StatusCode::NOT_MODIFIED, etag).into_response(),
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use diesel::{prelude::*, sql_query};
+use diesel_async::{pooled_connection::deadpool::Object, RunQueryDsl};
+use diesel_migrations::MigrationHarness;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::{self, ReadPool, MIGRATIONS},
+    models::Url,
+    schema::urls,
+};
+
+/// How long to wait for a pooled connection before reporting the pool as unavailable
+const POOL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long to wait for the writer, which is busy whenever anything is being written
+const WRITER_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Error,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    /// How long the check took, in milliseconds
+    pub duration_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Check {
+    fn new(name: &'static str, started: Instant, result: Result<(), String>) -> Self {
+        let (status, error) = match result {
+            Ok(()) => (Status::Ok, None),
+            Err(err) => (Status::Error, Some(err)),
+        };
+
+        Self {
+            name,
+            status,
+            duration_ms: started.elapsed().as_millis(),
+            error,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub status: Status,
+    pub checks: Vec<Check>,
+}
+
+/// Liveness probe, this only shows that the server is accepting requests
+pub async fn healthz() -> &'static str {
+    "ok"
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ReadyParams {
+    /// Also check that the writer's connection can be had
+    write: bool,
+}
+
+/// Take a connection from `pool`, giving up after `timeout`
+async fn acquire(pool: &db::Pool, timeout: Duration) -> Result<Object<db::Connection>, String> {
+    match tokio::time::timeout(timeout, pool.get()).await {
+        Ok(Ok(conn)) => Ok(conn),
+        Ok(Err(err)) => Err(err.to_string()),
+        Err(_) => Err("timed out waiting for a connection".to_string()),
+    }
+}
+
+/// Readiness probe, checks that a database connection can be acquired, that it answers
+/// queries, that every migration has been run and that the `urls` table matches the schema
+/// that this build expects.  This uses the read pool, so that it doesn't queue behind writes
+/// for the only writer; `?write=true` checks the writer too, with a short timeout.
+pub async fn readyz(
+    State(ReadPool(pool)): State<ReadPool>,
+    State(write_pool): State<db::Pool>,
+    Query(params): Query<ReadyParams>,
+) -> (StatusCode, Json<Readiness>) {
+    let mut checks = Vec::new();
+
+    let started = Instant::now();
+    let conn = acquire(&pool, POOL_TIMEOUT).await;
+    checks.push(Check::new(
+        "pool",
+        started,
+        conn.as_ref().map(|_| ()).map_err(Clone::clone),
+    ));
+
+    if params.write {
+        let started = Instant::now();
+        let writer = acquire(&write_pool, WRITER_TIMEOUT).await.map(drop);
+        checks.push(Check::new("writer", started, writer));
+    }
+
+    if let Ok(mut conn) = conn {
+        let started = Instant::now();
+        let query = sql_query("SELECT 1")
+            .execute(&mut conn)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string());
+        checks.push(Check::new("query", started, query));
+
+        let started = Instant::now();
+        // the migration harness is synchronous, so it gets the connection underneath
+        let migrations = conn
+            .spawn_blocking(|conn| {
+                Ok(match conn.has_pending_migration(MIGRATIONS) {
+                    Ok(false) => Ok(()),
+                    Ok(true) => Err("there are pending migrations".to_string()),
+                    Err(e) => Err(e.to_string()),
+                })
+            })
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+        checks.push(Check::new("migrations", started, migrations));
+
+        let started = Instant::now();
+        let schema = urls::table
+            .select(Url::as_select())
+            .limit(1)
+            .load::<Url>(&mut conn)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string());
+        checks.push(Check::new("schema", started, schema));
+    }
+
+    let status = if checks.iter().all(|c| c.status == Status::Ok) {
+        Status::Ok
+    } else {
+        Status::Error
+    };
+    let code = match status {
+        Status::Ok => StatusCode::OK,
+        Status::Error => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    (code, Json(Readiness { status, checks }))
+}
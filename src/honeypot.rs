@@ -0,0 +1,188 @@
+//! Answers requests for paths that only vulnerability scanners ask for (`/wp-login.php`,
+//! `/.env`, ...) before they reach the slug lookup, where they would count as misses and cost a
+//! database query each.  Clients that keep asking for them are banned.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use axum::{
+    extract::State,
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::{ban::Bans, config::LiveConfig, ip::ClientIp};
+
+/// How many requests have been answered by the honeypot, for `/metrics`
+pub static HIT_COUNT: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HoneypotConfig {
+    pub enabled: bool,
+    /// Paths to answer without looking them up, where `*` matches anything.  Matching ignores
+    /// case.
+    pub paths: Vec<String>,
+    /// The status to answer with.  `444` (as nginx uses it) also closes the connection.
+    pub status: u16,
+}
+
+impl Default for HoneypotConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            paths: [
+                "*.php",
+                "*.asp",
+                "*.aspx",
+                "*.cgi",
+                "/.env*",
+                "/.git/*",
+                "/.aws/*",
+                "/.ssh/*",
+                "/.ds_store",
+                "/wp-admin*",
+                "/wp-content/*",
+                "/wp-includes/*",
+                "/phpmyadmin*",
+                "/cgi-bin/*",
+                "/vendor/*",
+                "/actuator/*",
+                "/server-status",
+            ]
+            .iter()
+            .map(|p| p.to_string())
+            .collect(),
+            status: 404,
+        }
+    }
+}
+
+impl HoneypotConfig {
+    /// Whether `path` is one of the honeypot's
+    pub fn matches(&self, path: &str) -> bool {
+        self.enabled && self.paths.iter().any(|pattern| glob(pattern, path))
+    }
+}
+
+/// Match `text` against `pattern`, where `*` matches any run of characters, ignoring ascii case
+fn glob(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = strip_prefix_ignore_case(text, first) else {
+        return false;
+    };
+
+    let mut parts = parts.peekable();
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            // the last part has to be at the very end
+            return rest.len() >= part.len()
+                && rest.is_char_boundary(rest.len() - part.len())
+                && rest[rest.len() - part.len()..].eq_ignore_ascii_case(part);
+        }
+        let lower = rest.to_ascii_lowercase();
+        let Some(found) = lower.find(&part.to_ascii_lowercase()) else {
+            return false;
+        };
+        rest = &rest[found + part.len()..];
+    }
+    // there was no `*`
+    rest.is_empty()
+}
+
+fn strip_prefix_ignore_case<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = text.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &text[prefix.len()..])
+}
+
+fn respond(status: u16) -> Response {
+    match StatusCode::from_u16(status) {
+        Ok(status) if status.as_u16() == 444 => {
+            (status, [(header::CONNECTION, "close")]).into_response()
+        }
+        Ok(status) => status.into_response(),
+        Err(_) => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Answer requests for honeypot paths straight away
+pub async fn check<B>(
+    State(bans): State<Arc<Bans>>,
+    State(config): State<Arc<LiveConfig>>,
+    ClientIp(ip): ClientIp,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let config = config.get();
+    let path = req.uri().path();
+    if !config.honeypot.matches(path) {
+        return next.run(req).await;
+    }
+
+    HIT_COUNT.fetch_add(1, Ordering::Relaxed);
+    debug!("Honeypot hit from {}: {}", ip, path);
+    bans.record_honeypot(ip, &config.bans);
+    respond(config.honeypot.status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs() {
+        for (pattern, text) in [
+            ("/server-status", "/server-status"),
+            ("/server-status", "/Server-Status"),
+            ("*.php", "/index.php"),
+            ("*.php", "/a/b/.PHP"),
+            ("/.env*", "/.env"),
+            ("/.env*", "/.env.local"),
+            ("*admin*", "/admin"),
+            ("*admin*", "/wp-admin/setup"),
+            ("*", ""),
+            ("*", "/anything"),
+            ("/a*b*c", "/abc"),
+            ("/a*b*c", "/a-b-b-c"),
+            ("/ü*", "/üx"),
+        ] {
+            assert!(glob(pattern, text), "{} should match {}", pattern, text);
+        }
+    }
+
+    #[test]
+    fn not_globs() {
+        for (pattern, text) in [
+            ("/server-status", "/server-status/"),
+            ("/server-status", "/server"),
+            ("*.php", "/index.php.bak"),
+            ("*.php", "php"),
+            ("/.env*", "/x/.env"),
+            ("*admin*", "/adm"),
+            // the start and end of a pattern can't share characters
+            ("/ab*ba", "/aba"),
+            ("/a*b*c", "/acb"),
+            ("/ü*", "/u"),
+            // the end of the text isn't on a character boundary
+            ("*x", "/ü"),
+        ] {
+            assert!(!glob(pattern, text), "{} shouldn't match {}", pattern, text);
+        }
+    }
+
+    #[test]
+    fn disabled() {
+        let mut config = HoneypotConfig::default();
+        assert!(config.matches("/wp-login.php"));
+        assert!(!config.matches("/abc123"));
+        config.enabled = false;
+        assert!(!config.matches("/wp-login.php"));
+    }
+}
@@ -0,0 +1,10 @@
+//! Helpers for the few pages that are filled in on the server
+
+/// Escape text for use in HTML, including inside quoted attributes
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
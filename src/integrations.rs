@@ -0,0 +1,215 @@
+//! Webhooks for chat bots: a Slack slash command and a Telegram bot that shorten the url they
+//! are sent and reply with the link.  Each is disabled until its secret is configured.
+
+use std::net::IpAddr;
+
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+    Json,
+};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{
+    auth::constant_time_eq, check_destination, check_quota, config::Config, create_url,
+    error::UrlErr, ip::ClientIp, quota::Client, signed::now, slug, AppState, LimitedBody, ShortReq,
+};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct IntegrationsConfig {
+    /// The app's signing secret, from its "Basic Information" page
+    pub slack_signing_secret: Option<String>,
+    /// The `secret_token` given to Telegram's `setWebhook`
+    pub telegram_secret_token: Option<String>,
+}
+
+/// How old (in seconds) a Slack request may be, so that captured ones can't be replayed
+const SLACK_MAX_AGE: u64 = 5 * 60;
+
+const USAGE: &str = "Usage: /shorten <url> [slug]";
+
+/// Shorten the url in a command's text, which may be followed by a custom slug, returning the
+/// reply to send back
+async fn shorten(state: &AppState, text: &str, ip: IpAddr) -> Result<String, UrlErr> {
+    let mut words = text.split_whitespace();
+    let Some(url) = words.next() else {
+        return Ok(USAGE.to_string());
+    };
+    // Slack sends links as `<https://example.com|example.com>`
+    let url = url.trim_start_matches('<').trim_end_matches('>');
+    let url = url.split('|').next().unwrap_or(url);
+
+    let config = state.config.get();
+    // bots don't send API keys, so everyone using one shares the quota of its servers
+    let client = Client::Ip(ip);
+    check_quota(state, &client, ip, &config)
+        .await
+        .map_err(|(_, err)| err)?;
+    let mut req = ShortReq::from_url(url.to_string());
+    check_destination(&mut req, ip, &state.bans, &config)?;
+    if let Some(custom) = words.next() {
+        let custom = slug::normalize(custom, &config);
+        slug::validate(&custom, &config)?;
+        req.slug = Some(custom);
+    }
+
+    let author = ip.to_string();
+    let (entry, is_new) = create_url(req, author, config.clone(), state.pool.clone()).await?;
+    if is_new {
+        state.quotas.record_creation(client);
+    }
+    Ok(reply_text(&config, &entry.slug, &entry.url))
+}
+
+fn reply_text(config: &Config, slug: &str, url: &str) -> String {
+    format!("{}/{} → {}", config.base_url(), slug, url)
+}
+
+#[derive(Debug, Serialize)]
+pub struct SlackReply {
+    /// `ephemeral` so only whoever ran the command sees it
+    response_type: &'static str,
+    text: String,
+}
+
+/// Check `X-Slack-Signature`, an HMAC of the timestamp and body with the signing secret
+fn verify_slack(headers: &HeaderMap, body: &str, secret: &str) -> Result<(), UrlErr> {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    let timestamp = header("x-slack-request-timestamp").ok_or(UrlErr::Unauthorized)?;
+    let signature = header("x-slack-signature").ok_or(UrlErr::Unauthorized)?;
+
+    let sent_at = timestamp.parse::<u64>().map_err(|_| UrlErr::Unauthorized)?;
+    if now().abs_diff(sent_at) > SLACK_MAX_AGE {
+        return Err(UrlErr::Unauthorized);
+    }
+
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("v0:{}:{}", timestamp, body).as_bytes());
+    let expected = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .fold(String::from("v0="), |hex, b| hex + &format!("{:02x}", b));
+
+    if constant_time_eq(expected.as_bytes(), signature.as_bytes()) {
+        Ok(())
+    } else {
+        Err(UrlErr::Unauthorized)
+    }
+}
+
+/// A Slack slash command, sent as a form
+pub async fn slack(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Json<SlackReply>, UrlErr> {
+    let config = state.config.get();
+    let secret = config
+        .integrations
+        .slack_signing_secret
+        .as_deref()
+        .ok_or(UrlErr::FeatureDisabled("Slack commands"))?;
+    verify_slack(&headers, &body, secret)?;
+
+    let text = url::form_urlencoded::parse(body.as_bytes())
+        .find(|(key, _)| key == "text")
+        .map(|(_, value)| value.into_owned())
+        .unwrap_or_default();
+
+    // Slack shows anything but a 200 as a generic failure, so errors are replied with too
+    let text = shorten(&state, &text, ip)
+        .await
+        .unwrap_or_else(|err| err.detail());
+    Ok(Json(SlackReply {
+        response_type: "ephemeral",
+        text,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TelegramUpdate {
+    message: Option<TelegramMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TelegramMessage {
+    message_id: i64,
+    chat: TelegramChat,
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TelegramChat {
+    id: i64,
+}
+
+/// A Bot API call made by answering the webhook, which saves a request back to Telegram
+#[derive(Debug, Serialize)]
+struct TelegramReply {
+    method: &'static str,
+    chat_id: i64,
+    text: String,
+    reply_to_message_id: i64,
+}
+
+/// An update from Telegram, sent to the url given to `setWebhook`
+pub async fn telegram(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+    Json(update): Json<TelegramUpdate>,
+) -> Result<Response, UrlErr> {
+    let config = state.config.get();
+    let secret = config
+        .integrations
+        .telegram_secret_token
+        .as_deref()
+        .ok_or(UrlErr::FeatureDisabled("Telegram bots"))?;
+    let sent = headers
+        .get("x-telegram-bot-api-secret-token")
+        .map(|v| v.as_bytes())
+        .unwrap_or_default();
+    if !constant_time_eq(sent, secret.as_bytes()) {
+        return Err(UrlErr::Unauthorized);
+    }
+
+    // anything other than a text message (edits, joins, ...) is ignored
+    let Some(message) = update.message else {
+        return Ok(().into_response());
+    };
+    let Some(text) = message.text else {
+        return Ok(().into_response());
+    };
+
+    // drop the command (`/shorten` or `/shorten@SomeBot`), if there is one
+    let text = match text.strip_prefix('/') {
+        Some(command) => command.split_once(' ').map_or("", |(_, rest)| rest),
+        None => &text,
+    };
+    let text = shorten(&state, text, ip)
+        .await
+        .unwrap_or_else(|err| err.detail());
+
+    Ok(Json(TelegramReply {
+        method: "sendMessage",
+        chat_id: message.chat.id,
+        text,
+        reply_to_message_id: message.message_id,
+    })
+    .into_response())
+}
+
+pub fn router() -> axum::Router<AppState, LimitedBody> {
+    use axum::routing::post;
+
+    axum::Router::new()
+        .route("/slack", post(slack))
+        .route("/telegram", post(telegram))
+}
@@ -0,0 +1,242 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRef, FromRequestParts},
+    http::{request::Parts, HeaderMap, StatusCode},
+};
+use ipnet::IpNet;
+use serde::Deserialize;
+
+use crate::config::LiveConfig;
+
+/// Where the client's ip should be read from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IpSource {
+    /// The address of the peer that is connected to the server
+    #[default]
+    ConnectInfo,
+    /// The `X-Forwarded-For` header, ignoring any trusted proxies in the chain
+    XForwardedFor,
+    /// The `X-Real-IP` header
+    XRealIp,
+    /// The `CF-Connecting-IP` header, set by Cloudflare
+    CfConnectingIp,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ClientIpConfig {
+    pub source: IpSource,
+    /// Proxies that are allowed to set the header in `source`.  If the connecting peer is not
+    /// in this list, the header is ignored and the peer's address is used instead.
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+impl Default for ClientIpConfig {
+    fn default() -> Self {
+        Self {
+            source: IpSource::default(),
+            trusted_proxies: vec!["127.0.0.1/32".parse().unwrap(), "::1/128".parse().unwrap()],
+        }
+    }
+}
+
+impl ClientIpConfig {
+    fn is_trusted(&self, ip: &IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|net| net.contains(ip))
+    }
+
+    /// Find the client's ip for a request that came from `peer`, see [`canonical`]
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let peer = canonical(peer);
+        if self.source == IpSource::ConnectInfo || !self.is_trusted(&peer) {
+            return peer;
+        }
+
+        let found = match self.source {
+            IpSource::ConnectInfo => None,
+            IpSource::XForwardedFor => self.forwarded_for_ip(headers),
+            IpSource::XRealIp => single_ip_header(headers, "x-real-ip"),
+            IpSource::CfConnectingIp => single_ip_header(headers, "cf-connecting-ip"),
+        };
+
+        found.unwrap_or(peer)
+    }
+
+    /// Walk the `X-Forwarded-For` chain from the right, returning the first address that isn't
+    /// one of our trusted proxies.  If every address is trusted, the leftmost one is used.
+    fn forwarded_for_ip(&self, headers: &HeaderMap) -> Option<IpAddr> {
+        let chain: Vec<IpAddr> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(|v| v.trim().parse().ok().map(canonical))
+            .collect::<Option<_>>()?;
+
+        chain
+            .iter()
+            .rev()
+            .find(|ip| !self.is_trusted(ip))
+            .or_else(|| chain.first())
+            .copied()
+    }
+}
+
+fn single_ip_header(headers: &HeaderMap, name: &str) -> Option<IpAddr> {
+    headers
+        .get(name)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(canonical)
+}
+
+/// The one form that an address is kept and compared in.  IPv4 clients of a dual-stack listener
+/// show up as IPv4-mapped IPv6 addresses (`::ffff:192.0.2.1`), which would otherwise miss IPv4
+/// bans and trusted proxies and be stored differently from the same client on an IPv4 listener.
+/// Its `to_string` is the canonical text form, lower case and compressed for IPv6.
+pub fn canonical(ip: IpAddr) -> IpAddr {
+    ip.to_canonical()
+}
+
+/// Extracts the client's ip according to the current [`ClientIpConfig`]
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientIp
+where
+    Arc<LiveConfig>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Some(ConnectInfo(peer)) = parts.extensions.get::<ConnectInfo<SocketAddr>>() else {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Can't extract `ClientIp`, provide `axum::extract::ConnectInfo`",
+            ));
+        };
+
+        let config = Arc::<LiveConfig>::from_ref(state).get();
+        Ok(Self(config.client_ip.client_ip(peer.ip(), &parts.headers)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn config(source: IpSource) -> ClientIpConfig {
+        ClientIpConfig {
+            source,
+            trusted_proxies: vec!["10.0.0.0/8".parse().unwrap()],
+        }
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn connect_info() {
+        let config = config(IpSource::ConnectInfo);
+        let headers = headers(&[("x-forwarded-for", "203.0.113.1")]);
+        assert_eq!(config.client_ip(ip("10.0.0.1"), &headers), ip("10.0.0.1"));
+        // mapped addresses are kept as IPv4
+        assert_eq!(
+            config.client_ip(ip("::ffff:192.0.2.1"), &headers),
+            ip("192.0.2.1")
+        );
+    }
+
+    #[test]
+    fn untrusted_peer() {
+        // anyone can send the header, only a trusted proxy's is believed
+        let config = config(IpSource::XForwardedFor);
+        let headers = headers(&[("x-forwarded-for", "203.0.113.1")]);
+        assert_eq!(
+            config.client_ip(ip("198.51.100.7"), &headers),
+            ip("198.51.100.7")
+        );
+        assert_eq!(
+            config.client_ip(ip("10.0.0.1"), &headers),
+            ip("203.0.113.1")
+        );
+    }
+
+    #[test]
+    fn forwarded_for_chain() {
+        let config = config(IpSource::XForwardedFor);
+        let peer = ip("10.0.0.1");
+        // the client made up the first address, the untrusted hop after it is who connected to
+        // our proxies
+        let chain = headers(&[("x-forwarded-for", "1.2.3.4, 203.0.113.1, 10.0.0.2")]);
+        assert_eq!(config.client_ip(peer, &chain), ip("203.0.113.1"));
+
+        // split over several headers, in order
+        let split = headers(&[
+            ("x-forwarded-for", "1.2.3.4"),
+            ("x-forwarded-for", "203.0.113.1 , 10.0.0.2"),
+        ]);
+        assert_eq!(config.client_ip(peer, &split), ip("203.0.113.1"));
+
+        let all_trusted = headers(&[("x-forwarded-for", "10.0.0.3, 10.0.0.2")]);
+        assert_eq!(config.client_ip(peer, &all_trusted), ip("10.0.0.3"));
+
+        let mapped = headers(&[("x-forwarded-for", "::ffff:203.0.113.1")]);
+        assert_eq!(config.client_ip(peer, &mapped), ip("203.0.113.1"));
+    }
+
+    #[test]
+    fn malformed() {
+        let config = config(IpSource::XForwardedFor);
+        let peer = ip("10.0.0.1");
+        for value in ["", "nonsense", "203.0.113.1, nonsense", "203.0.113.1,"] {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+            assert_eq!(config.client_ip(peer, &headers), peer, "{:?}", value);
+        }
+        assert_eq!(config.client_ip(peer, &HeaderMap::new()), peer);
+    }
+
+    #[test]
+    fn single_headers() {
+        let peer = ip("10.0.0.1");
+        let headers = headers(&[
+            ("x-real-ip", " 203.0.113.1 "),
+            ("cf-connecting-ip", "2001:db8::1"),
+        ]);
+        assert_eq!(
+            config(IpSource::XRealIp).client_ip(peer, &headers),
+            ip("203.0.113.1")
+        );
+        assert_eq!(
+            config(IpSource::CfConnectingIp).client_ip(peer, &headers),
+            ip("2001:db8::1")
+        );
+        assert_eq!(
+            config(IpSource::CfConnectingIp).client_ip(ip("198.51.100.7"), &headers),
+            ip("198.51.100.7")
+        );
+    }
+}
@@ -0,0 +1,745 @@
+use std::{
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use axum::{
+    error_handling::HandleErrorLayer,
+    extract::{DefaultBodyLimit, FromRef, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue},
+    middleware,
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Extension, Router,
+};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use fluent_bundle::FluentArgs;
+use headers::{Expires, HeaderMapExt};
+use models::{NewReport, NewUrl};
+use schema::{reports, urls};
+use serde::{Deserialize, Serialize};
+use tower::ServiceBuilder;
+use tower_http::{
+    compression::CompressionLayer,
+    limit::RequestBodyLimitLayer,
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    timeout::TimeoutLayer,
+    trace::TraceLayer,
+};
+use tracing::{info, warn};
+
+use crate::{
+    analytics::Visitor,
+    auth::PublicListener,
+    ban::Bans,
+    cache::{Cache, Target},
+    config::{Config, LiveConfig},
+    db::ReadPool,
+    error::UrlErr,
+    ip::ClientIp,
+    mail::Notifier,
+    models::Url,
+    preview::Previews,
+    quota::{Client, QuotaStatus, Quotas},
+    redirect_limit::RedirectLimiter,
+    scan::ScanGuard,
+    spam::Verdict,
+    unfurl::Unfurls,
+    usage::{UsageQueue, UsageTally},
+};
+
+pub mod admin;
+pub mod alias;
+pub mod analytics;
+pub mod api;
+pub mod audit;
+pub mod auth;
+pub mod backup;
+pub mod ban;
+pub mod bookmarklet;
+pub mod cache;
+pub mod campaign;
+pub mod check;
+pub mod claim;
+pub mod config;
+pub mod db;
+pub mod destination;
+pub mod error;
+pub mod etag;
+pub mod health;
+pub mod honeypot;
+pub mod html;
+pub mod integrations;
+pub mod ip;
+pub mod load_shed;
+pub mod locale;
+pub mod logging;
+pub mod mail;
+pub mod maintenance;
+pub mod metrics;
+pub mod models;
+pub mod page;
+pub mod pagination;
+pub mod preview;
+pub mod profanity;
+pub mod quota;
+pub mod rate;
+pub mod redirect_limit;
+pub mod request_id;
+pub mod scan;
+pub mod schema;
+pub mod signed;
+pub mod slug;
+pub mod spam;
+pub mod suggest;
+pub mod template;
+pub mod unavailable;
+pub mod unfurl;
+pub mod usage;
+
+/// Request bodies as the routes see them, capped at `limits.max_body_size` by
+/// [`RequestBodyLimitLayer`] however they are read
+pub type LimitedBody = http_body::Limited<axum::body::Body>;
+
+#[derive(Clone)]
+pub struct AppState {
+    /// For writes, reads go through `read_pool`
+    pub pool: db::Pool,
+    pub read_pool: ReadPool,
+    pub config: Arc<LiveConfig>,
+    pub scan_guard: Arc<ScanGuard>,
+    pub bans: Arc<Bans>,
+    pub redirect_limiter: Arc<RedirectLimiter>,
+    pub cache: Option<Arc<Cache>>,
+    /// Clicks waiting to be written
+    pub usage: Arc<UsageTally>,
+    /// Where redirects put their clicks, for [`usage::flush_every`] to add to `usage`
+    pub usage_queue: UsageQueue,
+    /// Emails link owners, if mail is set up
+    pub notifier: Option<Arc<Notifier>>,
+    pub quotas: Arc<Quotas>,
+    /// What destinations say about themselves, for unfurl bots
+    pub unfurls: Arc<Unfurls>,
+    pub previews: Arc<Previews>,
+}
+
+impl FromRef<AppState> for db::Pool {
+    fn from_ref(state: &AppState) -> Self {
+        state.pool.clone()
+    }
+}
+
+impl FromRef<AppState> for ReadPool {
+    fn from_ref(state: &AppState) -> Self {
+        state.read_pool.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<UsageTally> {
+    fn from_ref(state: &AppState) -> Self {
+        state.usage.clone()
+    }
+}
+
+impl FromRef<AppState> for UsageQueue {
+    fn from_ref(state: &AppState) -> Self {
+        state.usage_queue.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<LiveConfig> {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<ScanGuard> {
+    fn from_ref(state: &AppState) -> Self {
+        state.scan_guard.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Bans> {
+    fn from_ref(state: &AppState) -> Self {
+        state.bans.clone()
+    }
+}
+
+impl FromRef<AppState> for Option<Arc<Cache>> {
+    fn from_ref(state: &AppState) -> Self {
+        state.cache.clone()
+    }
+}
+
+impl FromRef<AppState> for Option<Arc<Notifier>> {
+    fn from_ref(state: &AppState) -> Self {
+        state.notifier.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Quotas> {
+    fn from_ref(state: &AppState) -> Self {
+        state.quotas.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<RedirectLimiter> {
+    fn from_ref(state: &AppState) -> Self {
+        state.redirect_limiter.clone()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShortReq {
+    pub url: String,
+    pub slug: Option<String>,
+    /// Sent as the `Cache-Control` header when redirecting, instead of the configured default
+    pub cache_control: Option<String>,
+    /// How many times the url may be followed within the configured window, `0` for no limit
+    pub redirect_limit: Option<u32>,
+    /// Emailed about the link, if the server is set up to send mail
+    pub owner_email: Option<String>,
+    /// A note on what the link is for
+    pub description: Option<String>,
+    /// Shown to visitors when the link is disabled or over its limit
+    pub unavailable_message: Option<String>,
+    /// Where visitors are sent when the link is disabled or over its limit
+    pub unavailable_url: Option<String>,
+    /// The name of the API key the request was made with, set by the handler
+    #[serde(skip)]
+    pub api_key: Option<String>,
+    /// Why the link should be created disabled and reported for review, set by
+    /// [`check_destination`]
+    #[serde(skip)]
+    pub held_for_review: Option<String>,
+}
+
+impl ShortReq {
+    pub fn from_url(url: String) -> Self {
+        Self {
+            url,
+            slug: None,
+            cache_control: None,
+            redirect_limit: None,
+            owner_email: None,
+            description: None,
+            unavailable_message: None,
+            unavailable_url: None,
+            api_key: None,
+            held_for_review: None,
+        }
+    }
+
+    fn new_url<'a>(&'a self, slug: &'a str, author_ip: &'a str) -> NewUrl<'a> {
+        let now = signed::now() as i64;
+        NewUrl {
+            slug,
+            url: &self.url,
+            author_ip,
+            usage_count: 0,
+            cache_control: self.cache_control.as_deref(),
+            redirect_limit: self.redirect_limit.map(clamp_limit),
+            owner_email: self.owner_email.as_deref(),
+            api_key: self.api_key.as_deref(),
+            disabled: self.held_for_review.is_some(),
+            created_at: now,
+            updated_at: now,
+            description: self.description.as_deref(),
+            unavailable_message: self.unavailable_message.as_deref(),
+            unavailable_url: self.unavailable_url.as_deref(),
+        }
+    }
+}
+
+/// The longest description a url may have, in characters
+const MAX_DESCRIPTION: usize = 1000;
+
+fn check_description(description: &str) -> Result<(), UrlErr> {
+    if description.chars().count() > MAX_DESCRIPTION {
+        return Err(UrlErr::InvalidDescription);
+    }
+    Ok(())
+}
+
+/// Fit a redirect limit from a request into the database's column
+fn clamp_limit(limit: u32) -> i32 {
+    i32::try_from(limit).unwrap_or(i32::MAX)
+}
+
+/// Check that `ip` may shorten `req`'s url: its scheme is allowed, it isn't blocked (which counts
+/// towards banning `ip`) and it doesn't look like spam.  Urls that only might be spam are marked
+/// to be held for review.  Every way of making a link goes through this before [`create_url`].
+fn check_destination(
+    req: &mut ShortReq,
+    ip: IpAddr,
+    bans: &Bans,
+    config: &Config,
+) -> Result<(), UrlErr> {
+    let url = url::Url::parse(&req.url).map_err(UrlErr::InvalidUrl)?;
+    destination::check_scheme(&url, config)?;
+    if destination::is_blocked(&url, config) {
+        bans.record_blocked(ip, &config.bans);
+        return Err(UrlErr::BlockedDestination);
+    }
+    let score = spam::score(&url, config);
+    match score.verdict(&config.spam) {
+        Verdict::Allow => {}
+        Verdict::Review => {
+            info!("Holding {} from {} for review, {}", req.url, ip, score);
+            req.held_for_review = Some(score.to_string());
+        }
+        Verdict::Reject => {
+            info!("Refusing {} from {}, {}", req.url, ip, score);
+            return Err(UrlErr::LikelySpam);
+        }
+    }
+    Ok(())
+}
+
+/// Check that `client` may make another link: `ip` isn't making them quickly enough to be banned
+/// and `client` has quota left, returning where they stand.  Every way of making a link goes
+/// through this along with [`check_destination`], then counts a new link with
+/// [`Quotas::record_creation`].  A used up quota comes with its headers.
+async fn check_quota(
+    state: &AppState,
+    client: &Client,
+    ip: IpAddr,
+    config: &Config,
+) -> Result<QuotaStatus, (HeaderMap, UrlErr)> {
+    let no_headers = |err| (HeaderMap::new(), err);
+    state
+        .bans
+        .record_creation(ip, &config.bans)
+        .map_err(no_headers)?;
+    let status = quota_status(client, state.read_pool.clone(), &state.quotas, config)
+        .await
+        .map_err(no_headers)?;
+    status.check().map_err(|err| (status.headers(), err))?;
+    Ok(status)
+}
+
+async fn quota_status(
+    client: &Client,
+    ReadPool(pool): ReadPool,
+    quotas: &Quotas,
+    config: &Config,
+) -> Result<QuotaStatus, UrlErr> {
+    let mut conn = db::get(&pool).await?;
+    let links = client.count_links(&mut conn).await?;
+    Ok(quotas.status(client, links, &config.quotas))
+}
+
+/// Make a link for `req`, returning it along with whether it is new.  With
+/// [`slug::SlugStrategy::Hash`] a url that was shortened before gets the link it already has, which is
+/// left as it is: the rest of `req` is ignored and nothing is reported for review.
+async fn create_url(
+    req: ShortReq,
+    author_ip: String,
+    config: Arc<Config>,
+    pool: db::Pool,
+) -> Result<(Url, bool), UrlErr> {
+    template::validate(&req.url)?;
+    if let Some(description) = &req.description {
+        check_description(description)?;
+    }
+    unavailable::validate(
+        req.unavailable_message.as_deref(),
+        req.unavailable_url.as_deref(),
+        &config,
+    )?;
+    let case_insensitive = config.case_insensitive_slugs;
+    let mut conn = db::get(&pool).await?;
+
+    let (new_slug, is_new) = if let Some(slug) = req.slug.clone() {
+        // If there's been some other error, let's just pretend that it's colliding
+        let collides = slug::exists(&mut conn, slug.clone(), case_insensitive).await;
+        if collides.unwrap_or(true) {
+            return Err(UrlErr::SlugOccupied);
+        }
+        diesel::insert_into(urls::table)
+            .values(req.new_url(&slug, &author_ip))
+            .execute(&mut conn)
+            .await?;
+        (slug, true)
+    } else if let Some(strategy) = config.slug_strategy.generated() {
+        let mut slug = None;
+        for _ in 0..10 {
+            let try_slug = slug::generate(&mut conn, strategy, &config).await?;
+            let profane = config.profanity_filter && profanity::is_profane(&try_slug, &config);
+            // the counter based strategies will get to short words like `api` eventually
+            let reserved = slug::is_reserved(&try_slug, &config);
+            let collides = slug::exists(&mut conn, try_slug.clone(), case_insensitive).await;
+            if !profane && !reserved && !collides.unwrap_or(true) {
+                slug = Some(try_slug);
+                break;
+            }
+        }
+
+        let slug = match slug {
+            Some(slug) => slug,
+            None => return Err(UrlErr::SlugTooManyTries),
+        };
+        diesel::insert_into(urls::table)
+            .values(req.new_url(&slug, &author_ip))
+            //.returning(Url::as_returning())
+            .execute(&mut conn)
+            .await?;
+        (slug, true)
+    } else {
+        // Hashed slugs: rather than checking whether the url has been shortened before, try to
+        // insert it and see who owns the slug if that doesn't work
+        let target = slug::normalize_url(&req.url);
+        let mut found = None;
+        for try_slug in slug::hashed(&target, case_insensitive) {
+            let profane = config.profanity_filter && profanity::is_profane(&try_slug, &config);
+            if profane || slug::is_reserved(&try_slug, &config) {
+                continue;
+            }
+            let alias = alias::exists(&mut conn, try_slug.clone(), case_insensitive).await?;
+            if alias || page::exists(&mut conn, try_slug.clone(), case_insensitive).await? {
+                continue;
+            }
+
+            let inserted = diesel::insert_into(urls::table)
+                .values(req.new_url(&try_slug, &author_ip))
+                .on_conflict_do_nothing()
+                .execute(&mut conn)
+                .await?;
+            let same_url = inserted == 1
+                || urls::table
+                    .find(&try_slug)
+                    .select(urls::url)
+                    .first::<String>(&mut conn)
+                    .await
+                    .map(|existing| slug::normalize_url(&existing) == target)?;
+            if same_url {
+                found = Some((try_slug, inserted == 1));
+                break;
+            }
+        }
+
+        match found {
+            Some(found) => found,
+            None => return Err(UrlErr::SlugTooManyTries),
+        }
+    };
+
+    if let Some(reason) = req.held_for_review.as_ref().filter(|_| is_new) {
+        diesel::insert_into(reports::table)
+            .values(NewReport {
+                slug: &new_slug,
+                reason,
+                reporter_ip: spam::REPORTER,
+                created_at: signed::now() as i64,
+            })
+            .on_conflict_do_nothing()
+            .execute(&mut conn)
+            .await?;
+    }
+
+    let url = urls::table.find(new_slug).first::<Url>(&mut conn).await?;
+    Ok((url, is_new))
+}
+
+/// Build the `Cache-Control` header for a redirect, along with a matching `Expires` for caches
+/// that only understand HTTP/1.0
+fn cache_headers(cache_control: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let Ok(value) = HeaderValue::from_str(cache_control) else {
+        warn!("Ignoring invalid Cache-Control value: {:?}", cache_control);
+        return headers;
+    };
+    headers.insert(header::CACHE_CONTROL, value);
+
+    let max_age = cache_control
+        .split(',')
+        .filter_map(|d| d.trim().strip_prefix("max-age="))
+        .find_map(|secs| secs.parse().ok());
+    if let Some(max_age) = max_age {
+        headers.typed_insert(Expires::from(
+            SystemTime::now() + Duration::from_secs(max_age),
+        ));
+    }
+
+    headers
+}
+
+#[derive(Debug, Deserialize)]
+struct RedirParams {
+    /// The link to follow, when the slug is a page
+    to: Option<i64>,
+}
+
+async fn get_redir(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+    Path(slug_id): Path<String>,
+    Query(params): Query<RedirParams>,
+) -> Result<Response, UrlErr> {
+    follow(state, ip, headers, slug_id, Vec::new(), params.to).await
+}
+
+/// Follow a template link, filling its placeholders from the rest of the path
+async fn get_template_redir(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+    Path((slug_id, args)): Path<(String, String)>,
+) -> Result<Response, UrlErr> {
+    let args = args
+        .trim_start_matches('/')
+        .split('/')
+        .map(String::from)
+        .collect::<Vec<_>>();
+    if args.len() > template::MAX_ARGS {
+        return Err(UrlErr::NotFound);
+    }
+    follow(state, ip, headers, slug_id, args, None).await
+}
+
+async fn follow(
+    state: AppState,
+    ip: IpAddr,
+    headers: HeaderMap,
+    slug_id: String,
+    args: Vec<String>,
+    to: Option<i64>,
+) -> Result<Response, UrlErr> {
+    let config = state.config.get();
+    let scan_guard = &state.scan_guard;
+    if scan_guard.is_blocked(ip, &config.scan_guard) {
+        return Err(UrlErr::RateLimited);
+    }
+
+    let started = Instant::now();
+    let visitor = Visitor::new(ip, &headers, &config.analytics);
+    let result = match lookup(&state, &config, slug_id.clone(), &args, &headers, visitor).await {
+        // pages are rarer than links, so they are only looked for once there isn't a link
+        Err(UrlErr::NotFound) if args.is_empty() => {
+            page::visit(&state, &config, slug_id.clone(), to).await
+        }
+        result => result,
+    };
+    if matches!(result, Err(UrlErr::NotFound)) && scan_guard.record_miss(ip, &config.scan_guard) {
+        state.bans.record_scanning(ip, &config.bans);
+    }
+    let result = match (result, &config.fallback_url) {
+        (Err(UrlErr::NotFound), Some(fallback)) => Ok(fall_back(fallback, &slug_id)),
+        (result, _) => result,
+    };
+
+    let min_time = config.scan_guard.min_lookup_time();
+    if let Some(remaining) = min_time.checked_sub(started.elapsed()) {
+        tokio::time::sleep(remaining).await;
+    }
+
+    result
+}
+
+/// Find where `slug_id` redirects to, counting the click (and recording it, if analytics are
+/// enabled) in the background
+async fn lookup(
+    state: &AppState,
+    config: &Config,
+    slug_id: String,
+    args: &[String],
+    headers: &HeaderMap,
+    visitor: Visitor,
+) -> Result<Response, UrlErr> {
+    // signed links are checked before normalizing, which could change their case
+    if slug_id.starts_with(signed::PREFIX) {
+        if !args.is_empty() {
+            return Err(UrlErr::NotFound);
+        }
+        let key = config.signing_key.as_deref().ok_or(UrlErr::NotFound)?;
+        let (url, ttl) = signed::verify(&slug_id, key)?;
+        let cache_control = format!("private, max-age={}", ttl);
+        return send_to(cache_headers(&cache_control), &url, config);
+    }
+
+    let case_insensitive = config.case_insensitive_slugs;
+    let slug_id = slug::normalize(&slug_id, config);
+    let cache = state.cache.as_deref();
+    let cached = match cache {
+        Some(cache) => cache.get(&slug_id).await,
+        None => None,
+    };
+    let target = match cached {
+        Some(target) => target,
+        None => {
+            let url = find_url(state.read_pool.clone(), slug_id.clone(), case_insensitive).await?;
+            let target = Target::from(url);
+            if let Some(cache) = cache {
+                cache.set(&slug_id, &target).await;
+            }
+            target
+        }
+    };
+    if target.disabled {
+        return unavailable::respond(UrlErr::LinkDisabled, &target, headers, config);
+    }
+    // template links need exactly as many arguments as they have placeholders, others none
+    let url = template::fill(&target.url, args).ok_or(UrlErr::NotFound)?;
+    // bots showing a preview of the link aren't following it
+    if unfurl::is_bot(visitor.user_agent.as_deref(), &config.unfurl) {
+        if let Some(page) = unfurl::page(&state.unfurls, &target.slug, &url, config).await {
+            return Ok(page);
+        }
+    }
+    let limited =
+        state
+            .redirect_limiter
+            .check(&target.slug, target.redirect_limit, &config.redirect_limit);
+    if let Err(err) = limited {
+        return unavailable::respond(err, &target, headers, config);
+    }
+    let click = visitor.click(&target.slug, &config.analytics);
+    state.usage_queue.push(target.slug, click).await;
+
+    let cache_control = target
+        .cache_control
+        .or_else(|| config.redirect_cache_control.clone());
+    let headers = cache_control
+        .as_deref()
+        .map(cache_headers)
+        .unwrap_or_default();
+
+    send_to(headers, &url, config)
+}
+
+/// Send a visitor who asked for a slug that doesn't exist to the configured fallback
+fn fall_back(fallback: &str, slug_id: &str) -> Response {
+    let slug_id = url::form_urlencoded::byte_serialize(slug_id.as_bytes()).collect::<String>();
+    let url = fallback.replace("{slug}", &slug_id);
+    // the slug may be made later, so the fallback mustn't stick
+    let headers = [(header::CACHE_CONTROL, "no-store")];
+    (headers, Redirect::to(&url)).into_response()
+}
+
+const OPEN_PAGE: &str = include_str!("open.html");
+
+/// Send the visitor on to `url`.  Only web urls are redirected to, anything else (such as
+/// `mailto:`) gets a page linking to it, since browsers handle redirects to other apps poorly.
+fn send_to(headers: HeaderMap, url: &str, config: &Config) -> Result<Response, UrlErr> {
+    // the scheme may have been allowed when the link was made, but not anymore
+    let parsed = url::Url::parse(url).map_err(|_| UrlErr::LinkDisabled)?;
+    destination::check_scheme(&parsed, config).map_err(|_| UrlErr::LinkDisabled)?;
+
+    if destination::is_web(&parsed) {
+        return Ok((headers, Redirect::to(url)).into_response());
+    }
+    let mut args = FluentArgs::new();
+    args.set(
+        "scheme",
+        format!("<b>{}:</b>", html::escape(parsed.scheme())),
+    );
+    let page = OPEN_PAGE
+        .replace("{lang}", &locale::current())
+        .replace("{title}", &html::escape(&locale::text("open-title", None)))
+        .replace("{text}", &locale::text("open-text", Some(&args)))
+        .replace("{url}", &html::escape(url));
+    let vary = [(header::VARY, "accept-language")];
+    Ok((headers, vary, Html(page)).into_response())
+}
+
+/// Find the url for a normalized slug (or alias) in the database
+async fn find_url(
+    ReadPool(pool): ReadPool,
+    slug_id: String,
+    case_insensitive: bool,
+) -> Result<Url, UrlErr> {
+    let mut conn = db::get(&pool).await?;
+    alias::resolve(&mut conn, slug_id, case_insensitive)
+        .await?
+        .ok_or(UrlErr::NotFound)
+}
+
+/// The dashboard, metrics and health checks, which are served with the api on the admin listener
+/// when there is one
+fn admin_routes() -> Router<AppState, LimitedBody> {
+    Router::new()
+        .route("/admin", get(admin::dashboard))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .route("/metrics", get(metrics::metrics))
+}
+
+/// Build the app with every route and layer.  If `admin_bind` is set, the admin routes are left
+/// out and the api refuses admins, see [`admin_router`].
+pub fn router(state: AppState, config: &Config) -> Router {
+    let mut app = Router::new()
+        .nest("/api", api::router())
+        // kept so that clients from before the api was versioned keep working
+        .route("/", post(api::v1::create))
+        .route("/new", get(bookmarklet::new))
+        .route("/oembed", get(preview::oembed))
+        .route("/:slug", get(get_redir))
+        .route("/:slug/stats", get(analytics::stats_page))
+        .route("/:slug/preview.png", get(preview::preview))
+        .route("/:slug/*args", get(get_template_redir));
+    app = match config.admin_bind {
+        Some(_) => app.layer(Extension(PublicListener)),
+        None => app.merge(admin_routes()),
+    };
+    with_layers(app, state, config)
+}
+
+/// Build the app for the admin listener at `admin_bind`, which has the api, the dashboard, the
+/// metrics and the health checks
+pub fn admin_router(state: AppState, config: &Config) -> Router {
+    let app = Router::new()
+        .nest("/api", api::router())
+        .merge(admin_routes());
+    with_layers(app, state, config)
+}
+
+fn with_layers(app: Router<AppState, LimitedBody>, state: AppState, config: &Config) -> Router {
+    let mut app = app
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            honeypot::check,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            maintenance::check,
+        ))
+        .layer(middleware::from_fn_with_state(state.clone(), ban::check))
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(config.limits.max_body_size))
+        .layer(TimeoutLayer::new(config.limits.request_timeout()));
+
+    if config.limits.max_concurrent_requests > 0 {
+        let retry_after = config.limits.retry_after;
+        app = app.layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(move |err| {
+                    load_shed::overloaded(err, retry_after)
+                }))
+                .load_shed()
+                .concurrency_limit(config.limits.max_concurrent_requests),
+        );
+    }
+
+    app = app.layer(
+        ServiceBuilder::new()
+            .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+            .layer(TraceLayer::new_for_http().make_span_with(request_id::make_span))
+            .layer(PropagateRequestIdLayer::x_request_id())
+            .layer(middleware::from_fn(request_id::scope))
+            .layer(middleware::from_fn(locale::scope))
+            .into_inner(),
+    );
+
+    if let Some(cors) = config.cors.layer() {
+        app = app.layer(cors);
+    }
+
+    if config.compression {
+        app = app.layer(CompressionLayer::new());
+    }
+
+    app.with_state(state)
+}
@@ -0,0 +1,25 @@
+//! Refusing requests once too many are in flight, so that a spike gets quick 503s instead of
+//! piling up behind the database pools until everything times out
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use axum::{
+    http::{header, HeaderValue},
+    response::{IntoResponse, Response},
+    BoxError,
+};
+
+use crate::error::UrlErr;
+
+/// How many requests have been refused, for `/metrics`
+pub static SHED_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Turn the load shedding layer's error into a response telling the client when to come back.
+/// The router itself can't fail, so the only error that reaches this is `Overloaded`.
+pub async fn overloaded(_: BoxError, retry_after: u64) -> Response {
+    SHED_COUNT.fetch_add(1, Ordering::Relaxed);
+    let mut res = UrlErr::Overloaded.into_response();
+    res.headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    res
+}
@@ -0,0 +1,243 @@
+//! Translations of what visitors read: error messages and the page for links that open another
+//! app.  Messages are [Fluent](https://projectfluent.org) files in `locales/`, picked by the
+//! request's `Accept-Language`, and anything missing from a translation is shown in English.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use axum::{
+    http::{header, Request},
+    middleware::Next,
+    response::Response,
+};
+use fluent_bundle::{concurrent::FluentBundle, FluentArgs, FluentResource};
+use serde::Deserialize;
+use tracing::warn;
+use unic_langid::LanguageIdentifier;
+
+/// The translations that are built in, English has to be first since it is the fallback
+const BUNDLED: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.ftl")),
+    ("de", include_str!("../locales/de.ftl")),
+];
+
+/// The most languages that are looked at from one `Accept-Language`
+const MAX_LANGUAGES: usize = 10;
+
+static LOCALES: OnceLock<Locales> = OnceLock::new();
+
+tokio::task_local! {
+    static LANGUAGES: Vec<LanguageIdentifier>;
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LocaleConfig {
+    /// A directory of `<language>.ftl` files, which add languages or replace the built in
+    /// messages of one
+    pub dir: Option<PathBuf>,
+    /// The language used when none of the visitor's are available
+    pub default: String,
+}
+
+impl Default for LocaleConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            default: "en".into(),
+        }
+    }
+}
+
+struct Locales {
+    bundles: Vec<(LanguageIdentifier, FluentBundle<FluentResource>)>,
+    default: LanguageIdentifier,
+}
+
+fn parse(language: &str, source: String) -> Result<FluentResource, String> {
+    FluentResource::try_new(source).map_err(|(_, errs)| {
+        let errs = errs.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        format!(
+            "Unable to parse the {} translation: {}",
+            language,
+            errs.join(", ")
+        )
+    })
+}
+
+fn bundle(language: &LanguageIdentifier) -> FluentBundle<FluentResource> {
+    let mut bundle = FluentBundle::new_concurrent(vec![language.clone()]);
+    // the marks around arguments end up as stray characters in json and titles
+    bundle.set_use_isolating(false);
+    bundle
+}
+
+impl Locales {
+    fn load(config: &LocaleConfig) -> Result<Self, String> {
+        let mut sources = BUNDLED
+            .iter()
+            .map(|(language, source)| (language.to_string(), vec![source.to_string()]))
+            .collect::<Vec<_>>();
+        if let Some(dir) = &config.dir {
+            for (language, source) in read_dir(dir)? {
+                match sources.iter_mut().find(|(l, _)| *l == language) {
+                    Some((_, existing)) => existing.push(source),
+                    None => sources.push((language, vec![source])),
+                }
+            }
+        }
+
+        let mut bundles = Vec::new();
+        for (language, sources) in sources {
+            let id = language
+                .parse::<LanguageIdentifier>()
+                .map_err(|e| format!("{} isn't a language: {}", language, e))?;
+            let mut bundle = bundle(&id);
+            for source in sources {
+                // later files replace the messages of earlier ones
+                bundle.add_resource_overriding(parse(&language, source)?);
+            }
+            bundles.push((id, bundle));
+        }
+
+        let default = config
+            .default
+            .parse::<LanguageIdentifier>()
+            .map_err(|e| format!("{} isn't a language: {}", config.default, e))?;
+        Ok(Self { bundles, default })
+    }
+
+    /// The bundles to try for `languages`, best first, ending in English
+    fn negotiate<'a>(
+        &'a self,
+        languages: &'a [LanguageIdentifier],
+    ) -> impl Iterator<Item = &'a (LanguageIdentifier, FluentBundle<FluentResource>)> {
+        let exact = |wanted: &LanguageIdentifier| self.bundles.iter().find(|(id, _)| id == wanted);
+        // `de-AT` is better served in `de` than in English
+        let similar = |wanted: &LanguageIdentifier| {
+            self.bundles
+                .iter()
+                .find(|(id, _)| id.language == wanted.language)
+        };
+        languages
+            .iter()
+            .chain([&self.default])
+            .filter_map(move |wanted| exact(wanted).or_else(|| similar(wanted)))
+            .chain(self.bundles.first())
+    }
+}
+
+/// `<language>.ftl` files in `dir`
+fn read_dir(dir: &Path) -> Result<Vec<(String, String)>, String> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| format!("Unable to read translations from {}: {}", dir.display(), e))?;
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.extension().is_some_and(|ext| ext == "ftl") {
+            let Some(language) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            let source = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+            files.push((language.to_string(), source));
+        }
+    }
+    Ok(files)
+}
+
+/// Load the translations, which is only done at startup.  Until this is called only the built in
+/// ones are used.
+pub fn init(config: &LocaleConfig) -> Result<(), String> {
+    let locales = Locales::load(config)?;
+    let _ = LOCALES.set(locales);
+    Ok(())
+}
+
+/// Check that the translations in `config` load, without using them
+pub fn check(config: &LocaleConfig) -> Result<usize, String> {
+    Locales::load(config).map(|locales| locales.bundles.len())
+}
+
+fn locales() -> &'static Locales {
+    LOCALES.get_or_init(|| {
+        Locales::load(&LocaleConfig::default()).expect("the built in translations are valid")
+    })
+}
+
+/// The languages in an `Accept-Language` header, most preferred first
+fn accepted(header: &str) -> Vec<LanguageIdentifier> {
+    let mut languages = header
+        .split(',')
+        .take(MAX_LANGUAGES)
+        .filter_map(|part| {
+            let mut params = part.split(';');
+            let language = params.next()?.trim().parse::<LanguageIdentifier>().ok()?;
+            let quality = params
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+            (quality > 0.0).then_some((language, quality))
+        })
+        .collect::<Vec<_>>();
+    // stable, so that languages with the same quality keep their order
+    languages.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    languages
+        .into_iter()
+        .map(|(language, _)| language)
+        .collect()
+}
+
+fn current_languages() -> Vec<LanguageIdentifier> {
+    LANGUAGES.try_with(Clone::clone).unwrap_or_default()
+}
+
+/// Middleware that makes the request's languages available to [`text`] while the rest of the
+/// stack handles it
+pub async fn scope<B>(req: Request<B>, next: Next<B>) -> Response {
+    let languages = req
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|v| v.to_str().ok())
+        .map(accepted)
+        .unwrap_or_default();
+    LANGUAGES.scope(languages, next.run(req)).await
+}
+
+/// The language that [`text`] is answering in, for `<html lang>`
+pub fn current() -> String {
+    let languages = current_languages();
+    let best = locales().negotiate(&languages).next();
+    best.map_or_else(|| "en".to_string(), |(id, _)| id.to_string())
+}
+
+/// The message `id` (or its `attribute`, as in `not_found.detail`) in the language of the request
+/// being handled, falling back to English and then to `id` itself
+pub fn text(id: &str, args: Option<&FluentArgs>) -> String {
+    let (message_id, attribute) = match id.split_once('.') {
+        Some((message_id, attribute)) => (message_id, Some(attribute)),
+        None => (id, None),
+    };
+    let languages = current_languages();
+    for (_, bundle) in locales().negotiate(&languages) {
+        let Some(message) = bundle.get_message(message_id) else {
+            continue;
+        };
+        let pattern = match attribute {
+            Some(attribute) => message.get_attribute(attribute).map(|a| a.value()),
+            None => message.value(),
+        };
+        let Some(pattern) = pattern else {
+            continue;
+        };
+        let mut errs = Vec::new();
+        let text = bundle.format_pattern(pattern, args, &mut errs);
+        if !errs.is_empty() {
+            warn!("Unable to format the message {}: {:?}", id, errs);
+        }
+        return text.into_owned();
+    }
+    warn!("The message {} is missing", id);
+    id.to_string()
+}
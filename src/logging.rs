@@ -0,0 +1,51 @@
+use serde::Deserialize;
+use syslog_tracing::{Facility, Options, Syslog};
+use tracing_subscriber::{
+    fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry,
+};
+
+use crate::config::Config;
+
+/// Where log output should be sent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogOutput {
+    /// Human readable output on stdout
+    #[default]
+    Stdout,
+    /// The systemd journal, with each event's level mapped to a journal priority
+    Journald,
+    /// The local syslog daemon, under the `daemon` facility
+    Syslog,
+}
+
+/// Install the global subscriber for `config`, returning a handle that can be used to swap
+/// the log filter later on.
+pub fn init(config: &Config) -> Result<reload::Handle<EnvFilter, Registry>, String> {
+    let (filter, handle) = reload::Layer::new(config.env_filter());
+    let registry = tracing_subscriber::registry().with(filter);
+
+    match config.log_output {
+        LogOutput::Stdout => registry.with(fmt::layer()).init(),
+        LogOutput::Journald => {
+            let journald = tracing_journald::layer()
+                .map_err(|err| format!("Unable to connect to journald: {}", err))?;
+            registry.with(journald).init();
+        }
+        LogOutput::Syslog => {
+            let syslog = Syslog::new(c"url-shortener", Options::LOG_PID, Facility::Daemon)
+                .ok_or("Unable to connect to syslog")?;
+            // syslog adds its own timestamp
+            registry
+                .with(
+                    fmt::layer()
+                        .with_writer(syslog)
+                        .with_ansi(false)
+                        .without_time(),
+                )
+                .init();
+        }
+    }
+
+    Ok(handle)
+}
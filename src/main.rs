@@ -1,237 +1,171 @@
-use std::net::SocketAddr;
-
-use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::{ErrorResponse, IntoResponse, Redirect},
-    routing::{get, post},
-    Json, Router, TypedHeader,
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::Router;
+use axum_server::{tls_rustls::RustlsConfig, HttpConfig};
+use socket2::{Domain, Socket, Type};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    task::JoinSet,
+};
+use tracing::{error, warn};
+use url_shortener::{
+    analytics, backup,
+    cache::Cache,
+    check,
+    config::{Config, LiveConfig},
+    db, locale, logging,
+    mail::Notifier,
+    usage::{self, UsageQueue, UsageTally},
+    AppState,
 };
-use axum_client_ip::{InsecureClientIp, SecureClientIpSource};
-use diesel::prelude::*;
-use headers::ContentType;
-use models::NewUrl;
-use nanoid::nanoid;
-use schema::urls;
-use serde::{Deserialize, Serialize};
-use tower::ServiceBuilder;
-use tower_http::trace::TraceLayer;
-use tracing::warn;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-
-use crate::models::Url;
-
-pub mod models;
-pub mod schema;
-
-pub fn gen_slug() -> String {
-    nanoid!(10)
-}
-
-#[derive(Debug)]
-pub enum UrlErr {
-    SlugOccupied,
-    SlugTooManyTries,
-    DBError,
-    JsonError(serde_json::Error),
-    NotFound,
-}
 
-impl IntoResponse for UrlErr {
-    fn into_response(self) -> axum::response::Response {
-        let (res, status) = match self {
-            UrlErr::SlugOccupied => (
-                "This slug is already in use.".to_string(),
-                StatusCode::CONFLICT,
-            ),
-            UrlErr::SlugTooManyTries => (
-                "Unable to find a random slug to use, try again later.".to_string(),
-                StatusCode::REQUEST_TIMEOUT,
-            ),
-            UrlErr::DBError => (
-                "There was an error with the database.".to_string(),
-                StatusCode::INTERNAL_SERVER_ERROR,
-            ),
-            UrlErr::JsonError(err) => (
-                format!("Error parsing json: {}", err),
-                StatusCode::BAD_REQUEST,
-            ),
-            UrlErr::NotFound => (
-                "Shortened URL not found.".to_string(),
-                StatusCode::NOT_FOUND,
-            ),
-        };
-
-        #[derive(Debug, Serialize)]
-        struct Error {
-            message: String,
+/// Reload the config file whenever the process receives `SIGHUP`
+async fn reload_on_sighup(config: Arc<LiveConfig>) {
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(s) => s,
+        Err(err) => {
+            warn!(
+                "Unable to listen for SIGHUP, config reloading is disabled: {}",
+                err
+            );
+            return;
         }
+    };
 
-        let mut res = Json(Error { message: res }).into_response();
-        let s = res.status_mut();
-        *s = status;
-        res
+    while hangups.recv().await.is_some() {
+        if let Err(err) = config.reload() {
+            error!("{}, keeping the current config", err);
+        }
     }
 }
 
-async fn create_url(
-    url: String,
-    slug: Option<String>,
-    author_ip: String,
-    pool: deadpool_diesel::sqlite::Pool,
-) -> Result<Url, UrlErr> {
-    let conn = pool.get().await.unwrap();
-    conn.interact(move |conn| {
-        let mut collides = |try_slug| {
-            use self::schema::urls::dsl::*;
-            let result = urls.filter(slug.eq(try_slug)).limit(1).load::<Url>(conn);
-            if let Ok(v) = result {
-                v.len() > 0
-            } else {
-                true // There's been some other error, so let's just pretend that it's colliding
-            }
-        };
-
-        let new_slug = if let Some(slug) = slug {
-            if collides(slug.clone()) {
-                return Err(UrlErr::SlugOccupied);
-            }
-            slug
-        } else {
-            let mut slug = Some(gen_slug());
-            for _ in 0..10 {
-                slug = Some(gen_slug());
-                if !collides(slug.clone().unwrap()) {
-                    break;
-                }
-                slug = None;
-            }
-
-            match slug {
-                Some(slug) => slug,
-                None => return Err(UrlErr::SlugTooManyTries),
-            }
-        };
-
-        let np = NewUrl {
-            slug: &new_slug,
-            url: &url,
-            author_ip: &author_ip,
-            usage_count: 0,
-        };
-        diesel::insert_into(urls::table)
-            .values(np)
-            //.returning(Url::as_returning())
-            .execute(conn)
-            .map_err(|_| UrlErr::DBError)?;
-
-        let new_url = {
-            use self::schema::urls::dsl::*;
-            urls.filter(slug.eq(new_slug))
-                .limit(1)
-                .load::<Url>(conn)
-                .map_err(|_| UrlErr::DBError)?
-        };
-        Ok(new_url.get(0).cloned().unwrap())
-    })
-    .await
-    .map_err(|_| UrlErr::DBError)?
-}
+#[tokio::main]
+async fn main() {
+    if std::env::args().skip(1).any(|arg| arg == "--check") {
+        let passed = check::run();
+        std::process::exit(if passed { 0 } else { 1 });
+    }
 
-async fn post_root(
-    State(pool): State<deadpool_diesel::sqlite::Pool>,
-    content_type: Option<TypedHeader<ContentType>>,
-    InsecureClientIp(ip): InsecureClientIp,
-    body: String,
-) -> Result<Json<Url>, ErrorResponse> {
-    let (url, slug) = if let Some(TypedHeader(ct)) = content_type {
-        if ct == ContentType::json() {
-            let json = serde_json::from_str::<ShortReq>(&body).map_err(UrlErr::JsonError)?;
-            (json.url, json.slug)
-        } else {
-            (body.clone(), None)
-        }
-    } else {
-        (body.clone(), None)
-    };
+    let config = Config::load().expect("Unable to load config");
 
-    let author_ip = format!("{:?}", ip);
+    let log_filter_handle = logging::init(&config).expect("Unable to set up logging");
+    locale::init(&config.locale).expect("Unable to load translations");
 
-    let entry = create_url(url, slug, author_ip, pool);
-    Ok(Json(entry.await?))
-}
+    // set up connection pool
+    let pool = db::write_pool(&config.database_url, &config.pool);
+    db::run_migrations(&pool)
+        .await
+        .expect("Unable to run database migrations");
+    let read_pool = db::read_pool(&config.database_url, &config.pool);
+
+    tokio::spawn(backup::run(
+        config.database_url.clone(),
+        config.backup.clone(),
+    ));
+
+    let live_config = Arc::new(LiveConfig::new(config.clone(), log_filter_handle));
+    tokio::spawn(reload_on_sighup(live_config.clone()));
+    tokio::spawn(analytics::roll_up_every(pool.clone(), live_config.clone()));
+
+    let notifier = Notifier::new(
+        config.mail.smtp_url.as_deref(),
+        read_pool.clone(),
+        live_config.clone(),
+    )
+    .expect("Unable to set up mail")
+    .map(Arc::new);
+
+    let usage = Arc::new(UsageTally::new(config.usage_counts.shards));
+    let (usage_queue, usage_events) = UsageQueue::new(&config.usage_counts);
+    tokio::spawn(usage::flush_every(
+        usage_events,
+        usage.clone(),
+        pool.clone(),
+        config.usage_counts.clone(),
+        notifier.clone(),
+        live_config.clone(),
+    ));
+
+    let state = AppState {
+        pool,
+        read_pool,
+        config: live_config,
+        scan_guard: Arc::default(),
+        bans: Arc::default(),
+        redirect_limiter: Arc::default(),
+        cache: Cache::connect(&config.cache)
+            .await
+            .expect("Unable to connect to the Redis cache")
+            .map(Arc::new),
+        usage,
+        usage_queue,
+        notifier,
+        quotas: Arc::default(),
+        unfurls: Arc::default(),
+        previews: Arc::default(),
+    };
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct ShortReq {
-    url: String,
-    slug: Option<String>,
-}
+    let mut servers = JoinSet::new();
+    let public = url_shortener::router(state.clone(), &config);
+    servers.spawn(serve(config.bind, false, public.clone(), config.clone()));
+    if let Some(addr) = config.bind_v6 {
+        servers.spawn(serve(addr, true, public, config.clone()));
+    }
+    if let Some(addr) = config.admin_bind {
+        let admin = url_shortener::admin_router(state, &config);
+        servers.spawn(serve(addr, false, admin, config.clone()));
+    }
 
-async fn get_redir(
-    State(pool): State<deadpool_diesel::sqlite::Pool>,
-    Path(slug_id): Path<String>,
-) -> Result<Redirect, UrlErr> {
-    let conn = pool.get().await.unwrap();
-    let url: Result<String, UrlErr> = conn
-        .interact(move |conn| {
-            use self::schema::urls::dsl::*;
-
-            let result = urls
-                .filter(slug.eq(&slug_id))
-                .limit(1)
-                .load::<Url>(conn)
-                .map_err(|_| UrlErr::DBError)?;
-
-            if result.len() == 0 {
-                return Err(UrlErr::NotFound);
-            } else {
-                diesel::update(urls.find(&slug_id))
-                    .set(usage_count.eq(usage_count + 1))
-                    .execute(conn)
-                    .map_err(|_| warn!("Unable to update `usage_count` for {}", slug_id))
-                    .unwrap();
-                return Ok(result[0].url.clone());
-            }
-        })
-        .await
-        .unwrap();
-    url.map(|ref s| Redirect::to(s))
+    // the listeners only stop if something went wrong
+    if let Some(result) = servers.join_next().await {
+        result.unwrap().unwrap();
+    }
 }
 
-#[tokio::main]
-async fn main() {
-    let db_url = "sqlite://db/db.sqlite";
-
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "example_todos=debug,tower_http=debug".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+/// Open a listener on `addr`.  `v6_only` keeps an IPv6 listener from taking IPv4 connections
+/// too, so that it can share its port with an IPv4 one.
+fn listen(addr: SocketAddr, v6_only: bool) -> std::io::Result<std::net::TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    if addr.is_ipv6() && v6_only {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
 
-    // set up connection pool
-    let manager = deadpool_diesel::sqlite::Manager::new(db_url, deadpool_diesel::Runtime::Tokio1);
-    let pool = deadpool_diesel::sqlite::Pool::builder(manager)
-        .build()
-        .unwrap();
-
-    // build our application with a single route
-    let app = Router::new()
-        .route("/", post(post_root))
-        .route("/:slug", get(get_redir))
-        .layer(
-            ServiceBuilder::new()
-                .layer(TraceLayer::new_for_http())
-                .into_inner(),
-        )
-        .layer(SecureClientIpSource::ConnectInfo.into_extension())
-        .layer(tower_http::trace::TraceLayer::new_for_http())
-        .with_state(pool);
-
-    // run it with hyper on localhost:3000
-    axum::Server::bind(&"0.0.0.0:3000".parse().unwrap())
-        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
-        .await
-        .unwrap();
+/// Run `app` with hyper on `addr`
+async fn serve(
+    addr: SocketAddr,
+    v6_only: bool,
+    app: Router,
+    config: Config,
+) -> std::io::Result<()> {
+    let mut http = HttpConfig::new();
+    http.http1_header_read_timeout(config.limits.header_read_timeout())
+        .http1_only(!config.http2);
+    let http = http.build();
+
+    let make_service = app.into_make_service_with_connect_info::<SocketAddr>();
+    let listener = listen(addr, v6_only)?;
+
+    match &config.tls {
+        Some(tls) => {
+            let rustls = RustlsConfig::from_pem_file(&tls.cert_path, &tls.key_path)
+                .await
+                .expect("Unable to load TLS certificate or key");
+            axum_server::from_tcp_rustls(listener, rustls)
+                .http_config(http)
+                .serve(make_service)
+                .await
+        }
+        None => {
+            axum_server::from_tcp(listener)
+                .http_config(http)
+                .serve(make_service)
+                .await
+        }
+    }
 }
@@ -0,0 +1,72 @@
+//! Read-only mode, for migrations and backups: redirects keep working, but anything that would
+//! change the database is refused with a 503.  Click counts are held in memory until it ends
+//! (see [`crate::usage`]).
+
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{Method, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{auth::Admin, config::LiveConfig, error::UrlErr};
+
+/// Where read-only mode is turned on and off, which has to keep working while it is on
+const TOGGLE_PATH: &str = "/api/v1/maintenance";
+
+/// Requests that change things even though they are `GET`s
+const WRITING_GETS: &[&str] = &["/new"];
+
+/// Refuse requests that would change something while the server is read-only
+pub async fn check<B>(
+    State(config): State<Arc<LiveConfig>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let path = req.uri().path();
+    let reads = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+        && !WRITING_GETS.contains(&path);
+    if reads || path == TOGGLE_PATH || !config.is_read_only() {
+        return next.run(req).await;
+    }
+
+    UrlErr::ReadOnly.into_response()
+}
+
+#[derive(Debug, Serialize)]
+pub struct MaintenanceStatus {
+    read_only: bool,
+    /// Set by `read_only` in the config, so it can't be turned off here
+    from_config: bool,
+}
+
+fn status(config: &LiveConfig) -> MaintenanceStatus {
+    MaintenanceStatus {
+        read_only: config.is_read_only(),
+        from_config: config.get().read_only,
+    }
+}
+
+/// Whether the server is read-only
+pub async fn get(_: Admin, State(config): State<Arc<LiveConfig>>) -> Json<MaintenanceStatus> {
+    Json(status(&config))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceReq {
+    read_only: bool,
+}
+
+/// Turn read-only mode on or off
+pub async fn set(
+    _: Admin,
+    State(config): State<Arc<LiveConfig>>,
+    Json(req): Json<MaintenanceReq>,
+) -> Json<MaintenanceStatus> {
+    config.set_read_only(req.read_only);
+    Json(status(&config))
+}
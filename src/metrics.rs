@@ -0,0 +1,153 @@
+use std::{fmt::Write, sync::atomic::Ordering, sync::Arc};
+
+use axum::{
+    extract::State,
+    http::{header, HeaderValue},
+    response::IntoResponse,
+};
+
+use crate::{
+    auth::Admin,
+    db::{self, ReadPool, WAIT_STATS},
+    honeypot,
+    load_shed::SHED_COUNT,
+    redirect_limit::RedirectLimiter,
+    scan::ScanGuard,
+    unfurl::UNFURL_COUNT,
+    usage::{self, UsageQueue},
+};
+
+/// Counters and gauges in the Prometheus text format
+pub async fn metrics(
+    _: Admin,
+    State(scan_guard): State<Arc<ScanGuard>>,
+    State(redirect_limiter): State<Arc<RedirectLimiter>>,
+    State(write_pool): State<db::Pool>,
+    State(ReadPool(read_pool)): State<ReadPool>,
+    State(usage_queue): State<UsageQueue>,
+) -> impl IntoResponse {
+    let mut body = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, values: &[(&str, f64)]| {
+        let _ = writeln!(body, "# HELP {} {}", name, help);
+        let _ = writeln!(body, "# TYPE {} {}", name, kind);
+        for (labels, value) in values {
+            let _ = writeln!(body, "{}{} {}", name, labels, value);
+        }
+    };
+
+    metric(
+        "url_shortener_slug_misses_total",
+        "counter",
+        "Lookups of slugs that don't exist.",
+        &[("", scan_guard.miss_count.load(Ordering::Relaxed) as f64)],
+    );
+    metric(
+        "url_shortener_scans_blocked_total",
+        "counter",
+        "Lookups refused because the client asked for too many unknown slugs.",
+        &[("", scan_guard.blocked_count.load(Ordering::Relaxed) as f64)],
+    );
+    metric(
+        "url_shortener_honeypot_hits_total",
+        "counter",
+        "Requests for paths that only scanners ask for, such as /wp-login.php.",
+        &[("", honeypot::HIT_COUNT.load(Ordering::Relaxed) as f64)],
+    );
+    metric(
+        "url_shortener_redirects_limited_total",
+        "counter",
+        "Redirects refused because their link was followed too often.",
+        &[(
+            "",
+            redirect_limiter.limited_count.load(Ordering::Relaxed) as f64,
+        )],
+    );
+    metric(
+        "url_shortener_requests_shed_total",
+        "counter",
+        "Requests refused with a 503 because too many were already being handled.",
+        &[("", SHED_COUNT.load(Ordering::Relaxed) as f64)],
+    );
+    metric(
+        "url_shortener_clicks_queued",
+        "gauge",
+        "Clicks waiting on the queue to be counted.",
+        &[("", usage_queue.len() as f64)],
+    );
+    metric(
+        "url_shortener_clicks_dropped_total",
+        "counter",
+        "Clicks that weren't counted because the queue was full, or too many were held in read-only mode.",
+        &[("", usage::DROPPED_COUNT.load(Ordering::Relaxed) as f64)],
+    );
+    metric(
+        "url_shortener_unfurls_total",
+        "counter",
+        "Requests from unfurl bots answered with a preview page, which aren't counted as clicks.",
+        &[("", UNFURL_COUNT.load(Ordering::Relaxed) as f64)],
+    );
+
+    let write = write_pool.status();
+    let read = read_pool.status();
+    metric(
+        "url_shortener_db_connections",
+        "gauge",
+        "Open database connections, by pool and whether they are in use.",
+        &[
+            (
+                "{pool=\"write\",state=\"in_use\"}",
+                (write.size - write.available) as f64,
+            ),
+            ("{pool=\"write\",state=\"idle\"}", write.available as f64),
+            (
+                "{pool=\"read\",state=\"in_use\"}",
+                (read.size - read.available) as f64,
+            ),
+            ("{pool=\"read\",state=\"idle\"}", read.available as f64),
+        ],
+    );
+    metric(
+        "url_shortener_db_connections_max",
+        "gauge",
+        "The most connections each pool will open.",
+        &[
+            ("{pool=\"write\"}", write.max_size as f64),
+            ("{pool=\"read\"}", read.max_size as f64),
+        ],
+    );
+    metric(
+        "url_shortener_db_waiting",
+        "gauge",
+        "Requests waiting for a database connection.",
+        &[
+            ("{pool=\"write\"}", write.waiting as f64),
+            ("{pool=\"read\"}", read.waiting as f64),
+        ],
+    );
+    metric(
+        "url_shortener_db_wait_seconds",
+        "summary",
+        "Time spent waiting for a database connection.",
+        &[
+            (
+                "_sum",
+                WAIT_STATS.total_micros.load(Ordering::Relaxed) as f64 / 1e6,
+            ),
+            ("_count", WAIT_STATS.count.load(Ordering::Relaxed) as f64),
+        ],
+    );
+    metric(
+        "url_shortener_db_wait_timeouts_total",
+        "counter",
+        "Requests that gave up waiting for a database connection.",
+        &[("", WAIT_STATS.timeouts.load(Ordering::Relaxed) as f64)],
+    );
+
+    (
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; version=0.0.4"),
+        )],
+        body,
+    )
+}
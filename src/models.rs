@@ -1,4 +1,7 @@
-use crate::schema::urls;
+use crate::schema::{
+    aliases, audit_log, campaigns, clicks, notification_preferences, page_links, pages, reports,
+    urls,
+};
 use diesel::prelude::*;
 use serde::Serialize;
 
@@ -8,6 +11,58 @@ pub struct Url {
     pub url: String,
     pub author_ip: String,
     pub usage_count: i32,
+    /// Overrides the configured `Cache-Control` for redirects to this url
+    pub cache_control: Option<String>,
+    /// How many times this url may be followed within the configured window, overriding the
+    /// global limit.  `0` means no limit.
+    pub redirect_limit: Option<i32>,
+    /// Disabled by an admin, usually after it was reported
+    pub disabled: bool,
+    /// Emailed about milestones and moderation, if mail is set up
+    pub owner_email: Option<String>,
+    /// The name of the API key it was created with, if any
+    pub api_key: Option<String>,
+    /// The campaign it is grouped into, if any
+    pub campaign: Option<String>,
+    /// In seconds since the unix epoch
+    pub created_at: i64,
+    /// When its settings, owner or campaign last changed, in seconds since the unix epoch
+    pub updated_at: i64,
+    /// What the link is for, only shown to whoever manages it
+    pub description: Option<String>,
+    /// Shown instead of the usual error when the link is disabled or over its limit
+    pub unavailable_message: Option<String>,
+    /// Where visitors are sent instead when the link is disabled or over its limit
+    pub unavailable_url: Option<String>,
+}
+
+/// A url as shown to whoever asked for it, which may not be whoever made it, so it leaves out
+/// who made it, who owns it and their description
+#[derive(Serialize, Debug, Clone)]
+pub struct PublicUrl {
+    pub slug: String,
+    pub url: String,
+    pub cache_control: Option<String>,
+    pub redirect_limit: Option<i32>,
+    pub disabled: bool,
+    pub created_at: i64,
+    pub unavailable_message: Option<String>,
+    pub unavailable_url: Option<String>,
+}
+
+impl From<Url> for PublicUrl {
+    fn from(url: Url) -> Self {
+        Self {
+            slug: url.slug,
+            url: url.url,
+            cache_control: url.cache_control,
+            redirect_limit: url.redirect_limit,
+            disabled: url.disabled,
+            created_at: url.created_at,
+            unavailable_message: url.unavailable_message,
+            unavailable_url: url.unavailable_url,
+        }
+    }
 }
 
 #[derive(Insertable, Clone)]
@@ -17,4 +72,154 @@ pub struct NewUrl<'a> {
     pub url: &'a str,
     pub author_ip: &'a str,
     pub usage_count: i32,
+    pub cache_control: Option<&'a str>,
+    pub redirect_limit: Option<i32>,
+    pub owner_email: Option<&'a str>,
+    pub api_key: Option<&'a str>,
+    pub disabled: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub description: Option<&'a str>,
+    pub unavailable_message: Option<&'a str>,
+    pub unavailable_url: Option<&'a str>,
+}
+
+#[derive(Selectable, Queryable, Serialize, Debug, Clone)]
+pub struct Report {
+    pub id: i64,
+    pub slug: String,
+    pub reason: String,
+    pub reporter_ip: String,
+    /// In seconds since the unix epoch
+    pub created_at: i64,
+}
+
+#[derive(Insertable, Clone)]
+#[diesel(table_name = reports)]
+pub struct NewReport<'a> {
+    pub slug: &'a str,
+    pub reason: &'a str,
+    pub reporter_ip: &'a str,
+    pub created_at: i64,
+}
+
+/// One visit to a link, as recorded for its stats
+#[derive(Selectable, Queryable, Serialize, Debug, Clone)]
+pub struct Click {
+    pub id: i64,
+    pub slug: String,
+    /// In seconds since the unix epoch
+    pub clicked_at: i64,
+    pub referrer: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub country: Option<String>,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = clicks)]
+pub struct NewClick {
+    pub slug: String,
+    pub clicked_at: i64,
+    pub referrer: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub country: Option<String>,
+}
+
+#[derive(Selectable, Queryable, Insertable, AsChangeset, Serialize, Debug, Clone)]
+#[diesel(table_name = notification_preferences)]
+pub struct NotificationPreferences {
+    pub email: String,
+    /// Emails when a link reaches one of the configured click counts
+    pub milestones: bool,
+    /// Emails when an admin disables a link
+    pub moderation: bool,
+}
+
+impl NotificationPreferences {
+    /// What someone who has never changed their preferences gets
+    pub fn new(email: String) -> Self {
+        Self {
+            email,
+            milestones: true,
+            moderation: true,
+        }
+    }
+}
+
+/// A change to a link, kept so that it can be traced back later
+#[derive(Selectable, Queryable, Serialize, Debug, Clone)]
+#[diesel(table_name = audit_log)]
+pub struct AuditEntry {
+    pub id: i64,
+    /// In seconds since the unix epoch
+    pub created_at: i64,
+    pub slug: String,
+    pub action: String,
+    /// `admin`, or the email of the owner that made the change
+    pub actor: String,
+    pub detail: Option<String>,
+}
+
+#[derive(Insertable, Clone)]
+#[diesel(table_name = audit_log)]
+pub struct NewAuditEntry<'a> {
+    pub created_at: i64,
+    pub slug: &'a str,
+    pub action: &'a str,
+    pub actor: &'a str,
+    pub detail: Option<&'a str>,
+}
+
+#[derive(Selectable, Queryable, Insertable, Serialize, Debug, Clone)]
+#[diesel(table_name = aliases)]
+pub struct Alias {
+    pub alias: String,
+    /// The slug of the link it leads to
+    pub slug: String,
+}
+
+/// A named group of links, whose stats can be seen together
+#[derive(Selectable, Queryable, Insertable, Serialize, Debug, Clone)]
+#[diesel(table_name = campaigns)]
+pub struct Campaign {
+    pub name: String,
+    /// In seconds since the unix epoch
+    pub created_at: i64,
+}
+
+/// A landing page listing several links, shown at its slug instead of redirecting
+#[derive(Selectable, Queryable, Insertable, Serialize, Debug, Clone)]
+#[diesel(table_name = pages)]
+pub struct Page {
+    pub slug: String,
+    pub title: String,
+    pub description: Option<String>,
+    /// In seconds since the unix epoch
+    pub created_at: i64,
+}
+
+#[derive(Selectable, Queryable, Serialize, Debug, Clone)]
+#[diesel(table_name = page_links)]
+pub struct PageLink {
+    pub id: i64,
+    /// The slug of the page it is on
+    #[serde(skip)]
+    pub page: String,
+    /// Where it is on the page, from the top
+    #[serde(skip)]
+    pub position: i32,
+    pub title: String,
+    pub url: String,
+    pub clicks: i32,
+}
+
+#[derive(Insertable, Clone)]
+#[diesel(table_name = page_links)]
+pub struct NewPageLink<'a> {
+    pub page: &'a str,
+    pub position: i32,
+    pub title: &'a str,
+    pub url: &'a str,
 }
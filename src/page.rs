@@ -0,0 +1,436 @@
+//! Landing pages that list several links ("link in bio" pages).  A page has a slug like any
+//! link, but shows its links instead of redirecting.  Each link on it goes through
+//! `/:slug?to=<id>`, so that its clicks are counted.
+
+use std::{collections::HashSet, sync::Arc};
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use diesel::{
+    dsl::sql,
+    expression::BoxableExpression,
+    prelude::*,
+    sql_types::{Bool, Text},
+    sqlite::Sqlite,
+};
+use diesel_async::{scoped_futures::ScopedFutureExt, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    auth::Admin,
+    config::{Config, LiveConfig},
+    db::{self, Connection, ReadPool},
+    destination,
+    error::UrlErr,
+    html::escape,
+    models::{NewPageLink, Page, PageLink},
+    schema::{page_links, pages},
+    send_to,
+    signed::now,
+    slug, AppState, LimitedBody,
+};
+
+const TEMPLATE: &str = include_str!("page.html");
+
+/// The most links a page may have
+const MAX_LINKS: usize = 50;
+
+/// The longest title (of a page or a link on it), in characters
+const MAX_TITLE: usize = 200;
+
+/// The longest description, in characters
+const MAX_DESCRIPTION: usize = 1000;
+
+pub fn router() -> Router<AppState, LimitedBody> {
+    Router::new()
+        .route("/", post(create))
+        .route("/:slug", get(show).put(update).delete(remove))
+}
+
+/// A filter matching the page with `slug`, like [`slug::eq`] does for urls
+pub fn eq(
+    slug: String,
+    case_insensitive: bool,
+) -> Box<dyn BoxableExpression<pages::table, Sqlite, SqlType = Bool>> {
+    if case_insensitive {
+        Box::new(
+            sql::<Bool>("pages.slug = ")
+                .bind::<Text, _>(slug)
+                .sql(" COLLATE NOCASE"),
+        )
+    } else {
+        Box::new(pages::slug.eq(slug))
+    }
+}
+
+/// Whether a page with `slug` already exists
+pub async fn exists(
+    conn: &mut Connection,
+    slug: String,
+    case_insensitive: bool,
+) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(
+        pages::table.filter(eq(slug, case_insensitive)),
+    ))
+    .get_result(conn)
+    .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LinkReq {
+    /// The link's id when editing a page, so that it keeps its clicks
+    id: Option<i64>,
+    title: String,
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PageReq {
+    title: String,
+    description: Option<String>,
+    /// In the order they are shown
+    links: Vec<LinkReq>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateReq {
+    slug: String,
+    #[serde(flatten)]
+    page: PageReq,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PageRes {
+    #[serde(flatten)]
+    page: Page,
+    links: Vec<PageLink>,
+}
+
+fn too_long(text: &str, max: usize) -> bool {
+    text.chars().count() > max
+}
+
+impl PageReq {
+    fn validate(&self, config: &Config) -> Result<(), UrlErr> {
+        if self.title.trim().is_empty() || too_long(&self.title, MAX_TITLE) {
+            return Err(UrlErr::InvalidPage(
+                "The title must be 1 to 200 characters.",
+            ));
+        }
+        if self
+            .description
+            .as_deref()
+            .is_some_and(|d| too_long(d, MAX_DESCRIPTION))
+        {
+            return Err(UrlErr::InvalidPage(
+                "The description must be at most 1000 characters.",
+            ));
+        }
+        if self.links.len() > MAX_LINKS {
+            return Err(UrlErr::InvalidPage("A page can have at most 50 links."));
+        }
+        let mut ids = HashSet::new();
+        if !self
+            .links
+            .iter()
+            .filter_map(|l| l.id)
+            .all(|id| ids.insert(id))
+        {
+            return Err(UrlErr::InvalidPage("Each link id can only be given once."));
+        }
+
+        for link in &self.links {
+            if link.title.trim().is_empty() || too_long(&link.title, MAX_TITLE) {
+                return Err(UrlErr::InvalidPage(
+                    "Link titles must be 1 to 200 characters.",
+                ));
+            }
+            let url = url::Url::parse(&link.url).map_err(UrlErr::InvalidUrl)?;
+            destination::check_scheme(&url, config)?;
+            if destination::is_blocked(&url, config) {
+                return Err(UrlErr::BlockedDestination);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Replace the links on `page` with `links`, keeping the clicks of those that are kept
+async fn write_links(conn: &mut Connection, page: &str, links: &[LinkReq]) -> QueryResult<()> {
+    let existing = page_links::table
+        .filter(page_links::page.eq(page))
+        .select(page_links::id)
+        .load::<i64>(conn)
+        .await?;
+    let kept = |link: &LinkReq| link.id.filter(|id| existing.contains(id));
+
+    let kept_ids = links.iter().filter_map(kept).collect::<Vec<_>>();
+    diesel::delete(
+        page_links::table
+            .filter(page_links::page.eq(page))
+            .filter(page_links::id.ne_all(kept_ids)),
+    )
+    .execute(conn)
+    .await?;
+
+    for (position, link) in links.iter().enumerate() {
+        let position = position as i32;
+        match kept(link) {
+            Some(id) => {
+                diesel::update(page_links::table.find(id))
+                    .set((
+                        page_links::position.eq(position),
+                        page_links::title.eq(&link.title),
+                        page_links::url.eq(&link.url),
+                    ))
+                    .execute(conn)
+                    .await?;
+            }
+            None => {
+                diesel::insert_into(page_links::table)
+                    .values(NewPageLink {
+                        page,
+                        position,
+                        title: &link.title,
+                        url: &link.url,
+                    })
+                    .execute(conn)
+                    .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A page and its links, if there is one with `slug`
+async fn load(
+    conn: &mut Connection,
+    slug: String,
+    case_insensitive: bool,
+) -> QueryResult<Option<PageRes>> {
+    let Some(page) = pages::table
+        .filter(eq(slug, case_insensitive))
+        .first::<Page>(conn)
+        .await
+        .optional()?
+    else {
+        return Ok(None);
+    };
+    let links = page_links::table
+        .filter(page_links::page.eq(&page.slug))
+        .order(page_links::position.asc())
+        .load::<PageLink>(conn)
+        .await?;
+    Ok(Some(PageRes { page, links }))
+}
+
+/// Make a page, which takes a slug like any link
+pub async fn create(
+    _: Admin,
+    State(pool): State<db::Pool>,
+    State(config): State<Arc<LiveConfig>>,
+    Json(req): Json<CreateReq>,
+) -> Result<(StatusCode, Json<PageRes>), UrlErr> {
+    let config = config.get();
+    let new_slug = slug::normalize(&req.slug, &config);
+    slug::validate(&new_slug, &config)?;
+    req.page.validate(&config)?;
+    let case_insensitive = config.case_insensitive_slugs;
+
+    let mut conn = db::get(&pool).await?;
+    let page = conn
+        .immediate_transaction(|conn| {
+            async move {
+                if slug::exists(conn, new_slug.clone(), case_insensitive).await? {
+                    return Err(UrlErr::SlugOccupied);
+                }
+                diesel::insert_into(pages::table)
+                    .values(Page {
+                        slug: new_slug.clone(),
+                        title: req.page.title,
+                        description: req.page.description,
+                        created_at: now() as i64,
+                    })
+                    .execute(conn)
+                    .await?;
+                write_links(conn, &new_slug, &req.page.links).await?;
+                load(conn, new_slug, false).await?.ok_or(UrlErr::NotFound)
+            }
+            .scope_boxed()
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(page)))
+}
+
+/// A page with its links and their clicks
+pub async fn show(
+    _: Admin,
+    State(ReadPool(pool)): State<ReadPool>,
+    State(config): State<Arc<LiveConfig>>,
+    Path(slug): Path<String>,
+) -> Result<Json<PageRes>, UrlErr> {
+    let config = config.get();
+    let slug = slug::normalize(&slug, &config);
+    let case_insensitive = config.case_insensitive_slugs;
+
+    let mut conn = db::get(&pool).await?;
+    let page = load(&mut conn, slug, case_insensitive)
+        .await?
+        .ok_or(UrlErr::NotFound)?;
+    Ok(Json(page))
+}
+
+/// Replace a page's title, description and links.  Links sent with their `id` keep their
+/// clicks, and links that are left out are removed.
+pub async fn update(
+    _: Admin,
+    State(pool): State<db::Pool>,
+    State(config): State<Arc<LiveConfig>>,
+    Path(slug): Path<String>,
+    Json(req): Json<PageReq>,
+) -> Result<Json<PageRes>, UrlErr> {
+    let config = config.get();
+    let slug = slug::normalize(&slug, &config);
+    req.validate(&config)?;
+    let case_insensitive = config.case_insensitive_slugs;
+
+    let mut conn = db::get(&pool).await?;
+    let page = conn
+        .immediate_transaction(|conn| {
+            async move {
+                let slug = pages::table
+                    .filter(eq(slug, case_insensitive))
+                    .select(pages::slug)
+                    .first::<String>(conn)
+                    .await
+                    .optional()?
+                    .ok_or(UrlErr::NotFound)?;
+                diesel::update(pages::table.find(&slug))
+                    .set((
+                        pages::title.eq(req.title),
+                        pages::description.eq(req.description),
+                    ))
+                    .execute(conn)
+                    .await?;
+                write_links(conn, &slug, &req.links).await?;
+                load(conn, slug, false).await?.ok_or(UrlErr::NotFound)
+            }
+            .scope_boxed()
+        })
+        .await?;
+    Ok(Json(page))
+}
+
+/// Delete a page and its links
+pub async fn remove(
+    _: Admin,
+    State(pool): State<db::Pool>,
+    State(config): State<Arc<LiveConfig>>,
+    Path(slug): Path<String>,
+) -> Result<StatusCode, UrlErr> {
+    let config = config.get();
+    let slug = slug::normalize(&slug, &config);
+    let case_insensitive = config.case_insensitive_slugs;
+
+    let mut conn = db::get(&pool).await?;
+    conn.immediate_transaction(|conn| {
+        async move {
+            let slug = pages::table
+                .filter(eq(slug, case_insensitive))
+                .select(pages::slug)
+                .first::<String>(conn)
+                .await
+                .optional()?
+                .ok_or(UrlErr::NotFound)?;
+            diesel::delete(pages::table.find(&slug))
+                .execute(conn)
+                .await?;
+            diesel::delete(page_links::table.filter(page_links::page.eq(&slug)))
+                .execute(conn)
+                .await?;
+            Ok::<_, UrlErr>(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn render(page: &PageRes) -> String {
+    let description = page
+        .page
+        .description
+        .as_deref()
+        .map(|d| format!("<p>{}</p>", escape(d)))
+        .unwrap_or_default();
+    let links = page
+        .links
+        .iter()
+        .map(|link| {
+            format!(
+                "<li><a href=\"?to={}\" rel=\"noreferrer\">{}</a></li>",
+                link.id,
+                escape(&link.title)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    TEMPLATE
+        .replace("{title}", &escape(&page.page.title))
+        .replace("{description}", &description)
+        .replace("{links}", &links)
+}
+
+/// Show the page at `slug_id`, or follow the link `to` on it
+pub(crate) async fn visit(
+    state: &AppState,
+    config: &Config,
+    slug_id: String,
+    to: Option<i64>,
+) -> Result<Response, UrlErr> {
+    let slug_id = slug::normalize(&slug_id, config);
+    let case_insensitive = config.case_insensitive_slugs;
+    let mut conn = db::get(&state.read_pool.0).await?;
+    let page = load(&mut conn, slug_id, case_insensitive)
+        .await?
+        .ok_or(UrlErr::NotFound)?;
+    drop(conn);
+
+    let Some(to) = to else {
+        return Ok(Html(render(&page)).into_response());
+    };
+    let link = page
+        .links
+        .into_iter()
+        .find(|link| link.id == to)
+        .ok_or(UrlErr::NotFound)?;
+
+    // the link still works in read-only mode, it just isn't counted
+    if state.config.is_read_only() {
+        return send_to(HeaderMap::new(), &link.url, config);
+    }
+    // the visitor needn't wait for the count
+    let pool = state.pool.clone();
+    tokio::spawn(async move {
+        let counted = async {
+            let mut conn = db::get(&pool).await?;
+            diesel::update(page_links::table.find(to))
+                .set(page_links::clicks.eq(page_links::clicks + 1))
+                .execute(&mut conn)
+                .await?;
+            Ok::<_, UrlErr>(())
+        }
+        .await;
+        if let Err(err) = counted {
+            warn!("Unable to count a click on page link {}: {:?}", to, err);
+        }
+    });
+    send_to(HeaderMap::new(), &link.url, config)
+}
@@ -0,0 +1,142 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+
+use crate::error::UrlErr;
+
+pub const DEFAULT_LIMIT: i64 = 50;
+pub const MAX_LIMIT: i64 = 500;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PageParams {
+    /// How many items to return, clamped to [`MAX_LIMIT`]
+    pub limit: Option<i64>,
+    /// The `next_cursor` from the previous page
+    pub cursor: Option<String>,
+}
+
+impl PageParams {
+    pub fn limit(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+
+    /// The key that the next page starts after, or `None` for the first page
+    pub fn after(&self) -> Result<Option<i64>, UrlErr> {
+        self.cursor.as_deref().map(decode_cursor).transpose()
+    }
+}
+
+/// One page of a keyset-paginated listing
+#[derive(Debug, Clone, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Pass this as `cursor` to get the next page, `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// Build a page from `limit + 1` rows (fetching the extra row tells us whether there is a
+    /// next page), where `key` gives the position of a row in the listing.
+    pub fn new(mut rows: Vec<T>, limit: i64, key: impl Fn(&T) -> i64) -> Self {
+        let next_cursor = if rows.len() as i64 > limit {
+            rows.truncate(limit as usize);
+            rows.last().map(|last| encode_cursor(key(last)))
+        } else {
+            None
+        };
+
+        Self {
+            items: rows,
+            next_cursor,
+        }
+    }
+
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            next_cursor: self.next_cursor,
+        }
+    }
+}
+
+fn encode_cursor(key: i64) -> String {
+    URL_SAFE_NO_PAD.encode(key.to_string())
+}
+
+fn decode_cursor(cursor: &str) -> Result<i64, UrlErr> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(cursor)
+        .map_err(|_| UrlErr::InvalidCursor)?;
+    String::from_utf8(bytes)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or(UrlErr::InvalidCursor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(cursor: &str) -> PageParams {
+        PageParams {
+            limit: None,
+            cursor: Some(cursor.into()),
+        }
+    }
+
+    #[test]
+    fn cursors() {
+        for key in [0, 1, 42, -7, i64::MAX, i64::MIN] {
+            assert_eq!(decode_cursor(&encode_cursor(key)).unwrap(), key);
+        }
+        assert_eq!(PageParams::default().after().unwrap(), None);
+        assert_eq!(params(&encode_cursor(5)).after().unwrap(), Some(5));
+    }
+
+    #[test]
+    fn bad_cursors() {
+        let not_a_number = URL_SAFE_NO_PAD.encode("abc");
+        let too_big = URL_SAFE_NO_PAD.encode("99999999999999999999");
+        let not_utf8 = URL_SAFE_NO_PAD.encode([0xff, 0xfe]);
+        for cursor in ["", "!!!", "a", &not_a_number, &too_big, &not_utf8] {
+            assert!(
+                matches!(params(cursor).after(), Err(UrlErr::InvalidCursor)),
+                "{:?}",
+                cursor
+            );
+        }
+    }
+
+    #[test]
+    fn limits() {
+        let limit = |limit| {
+            PageParams {
+                limit,
+                cursor: None,
+            }
+            .limit()
+        };
+        assert_eq!(limit(None), DEFAULT_LIMIT);
+        assert_eq!(limit(Some(0)), 1);
+        assert_eq!(limit(Some(-5)), 1);
+        assert_eq!(limit(Some(10)), 10);
+        assert_eq!(limit(Some(MAX_LIMIT + 1)), MAX_LIMIT);
+    }
+
+    #[test]
+    fn pages() {
+        let page = Page::new(vec![1, 2, 3], 2, |&n| n * 10);
+        assert_eq!(page.items, [1, 2]);
+        assert_eq!(
+            decode_cursor(page.next_cursor.as_deref().unwrap()).unwrap(),
+            20
+        );
+
+        let page = Page::new(vec![1, 2], 2, |&n| n);
+        assert_eq!(page.items, [1, 2]);
+        assert_eq!(page.next_cursor, None);
+
+        let page = Page::new(Vec::<i64>::new(), 2, |&n| n).map(|n| n + 1);
+        assert!(page.items.is_empty());
+        assert_eq!(page.next_cursor, None);
+    }
+}
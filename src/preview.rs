@@ -0,0 +1,361 @@
+//! Preview images of where links go, at `/:slug/preview.png`.  They are taken by a screenshot
+//! service (anything that answers `GET` with a png, such as a headless Chrome sidecar) and
+//! kept on disk, keyed by the destination so that links to the same page share one.
+//!
+//! `/oembed` describes them to sites that embed links, see <https://oembed.com>.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use axum::{
+    extract::{Path as UrlPath, Query, State},
+    http::header,
+    response::IntoResponse,
+    Json,
+};
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::OwnedMutexGuard;
+use tracing::{debug, warn};
+
+use crate::{
+    alias, config::Config, db, destination, error::UrlErr, slug, template, unfurl, AppState,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PreviewConfig {
+    /// The screenshot service, with `{url}` in place of the (url-encoded) page to capture, such
+    /// as `http://localhost:3001/screenshot?url={url}`.  It has to answer with a png.  Previews
+    /// are disabled while this is unset.
+    pub service_url: Option<String>,
+    /// Where images are kept
+    pub dir: PathBuf,
+    /// How long (in seconds) an image is used before it is taken again
+    pub max_age: u64,
+    /// How long (in seconds) to wait for the service
+    pub timeout: u64,
+    /// The largest image (in bytes) that is accepted from the service
+    pub max_size: usize,
+    /// The most pages that are taken at once, others get an older picture or none
+    pub max_captures: usize,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        Self {
+            service_url: None,
+            dir: PathBuf::from("previews"),
+            max_age: 7 * 24 * 60 * 60,
+            timeout: 30,
+            max_size: 5 * 1024 * 1024,
+            max_captures: 4,
+        }
+    }
+}
+
+/// Where the image of `url` is kept
+fn image_path(url: &str, config: &PreviewConfig) -> PathBuf {
+    let hash = Sha256::digest(url.as_bytes())
+        .iter()
+        .fold(String::new(), |hex, b| hex + &format!("{:02x}", b));
+    config.dir.join(format!("{}.png", hash))
+}
+
+/// How long ago the file at `path` was written, if it exists
+async fn age(path: &Path) -> Option<Duration> {
+    let modified = tokio::fs::metadata(path).await.ok()?.modified().ok()?;
+    Some(
+        SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default(),
+    )
+}
+
+/// Have the service take a picture of `url`, and keep it at `path`
+async fn capture(
+    url: &str,
+    path: &Path,
+    service_url: &str,
+    config: &PreviewConfig,
+) -> Result<Vec<u8>, String> {
+    let encoded = url::form_urlencoded::byte_serialize(url.as_bytes()).collect::<String>();
+    let request = service_url.replace("{url}", &encoded);
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(config.timeout))
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let res = client
+        .get(&request)
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .map_err(|e| e.to_string())?;
+    let is_png = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("image/png"));
+    if !is_png {
+        return Err("the service didn't answer with a png".to_string());
+    }
+    if res
+        .content_length()
+        .is_some_and(|len| len > config.max_size as u64)
+    {
+        return Err("the image is too large".to_string());
+    }
+    let image = res.bytes().await.map_err(|e| e.to_string())?;
+    if image.len() > config.max_size {
+        return Err("the image is too large".to_string());
+    }
+
+    // written under another name first, so that a half written image is never served.  The
+    // name is only this capture's, since another process may share the directory.
+    tokio::fs::create_dir_all(&config.dir)
+        .await
+        .map_err(|e| e.to_string())?;
+    let partial = path.with_extension(format!("{:016x}.part", rand::random::<u64>()));
+    let written = async {
+        tokio::fs::write(&partial, &image).await?;
+        tokio::fs::rename(&partial, path).await
+    };
+    if let Err(err) = written.await {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(err.to_string());
+    }
+    Ok(image.to_vec())
+}
+
+/// The pictures being taken, so that each page is only asked for once at a time
+#[derive(Debug, Default)]
+pub struct Previews {
+    in_flight: Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+/// A picture being taken by one request, which other requests for it wait on.  It is forgotten
+/// when this is dropped, even if the request was cancelled.
+struct Taking<'a> {
+    previews: &'a Previews,
+    path: PathBuf,
+    _lock: OwnedMutexGuard<()>,
+}
+
+impl Drop for Taking<'_> {
+    fn drop(&mut self) {
+        self.previews.in_flight.lock().unwrap().remove(&self.path);
+    }
+}
+
+enum Turn<'a> {
+    Take(Taking<'a>),
+    Wait(Arc<tokio::sync::Mutex<()>>),
+    Busy,
+}
+
+impl Previews {
+    /// The picture of `url`, taken again once it is older than `max_age`
+    async fn image(
+        &self,
+        url: &str,
+        service_url: &str,
+        config: &PreviewConfig,
+    ) -> Result<Vec<u8>, UrlErr> {
+        let path = image_path(url, config);
+        if age(&path)
+            .await
+            .is_some_and(|age| age.as_secs() < config.max_age)
+        {
+            if let Ok(image) = tokio::fs::read(&path).await {
+                return Ok(image);
+            }
+        }
+
+        let turn = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            if let Some(lock) = in_flight.get(&path) {
+                Turn::Wait(lock.clone())
+            } else if in_flight.len() >= config.max_captures {
+                Turn::Busy
+            } else {
+                let lock = Arc::new(tokio::sync::Mutex::new(()));
+                let guard = lock.clone().try_lock_owned().expect("the lock is new");
+                in_flight.insert(path.clone(), lock);
+                Turn::Take(Taking {
+                    previews: self,
+                    path: path.clone(),
+                    _lock: guard,
+                })
+            }
+        };
+        match turn {
+            Turn::Take(taking) => {
+                let captured = capture(url, &path, service_url, config).await;
+                drop(taking);
+                match captured {
+                    Ok(image) => {
+                        debug!("Took a preview of {}", url);
+                        return Ok(image);
+                    }
+                    Err(err) => warn!("Unable to take a preview of {}: {}", url, err),
+                }
+            }
+            // it is on disk once the lock is let go, unless it couldn't be taken
+            Turn::Wait(lock) => drop(lock.lock().await),
+            Turn::Busy => debug!("Too many previews are being taken to take one of {}", url),
+        }
+
+        // an old picture is better than none
+        tokio::fs::read(&path)
+            .await
+            .map_err(|_| UrlErr::PreviewUnavailable)
+    }
+}
+
+/// Where the link at `slug_id` goes, if it is a page that a picture can be taken of
+async fn target(state: &AppState, slug_id: &str, config: &Config) -> Result<String, UrlErr> {
+    let slug_id = slug::normalize(slug_id, config);
+    let case_insensitive = config.case_insensitive_slugs;
+    let mut conn = db::get(&state.read_pool.0).await?;
+    let target = alias::resolve(&mut conn, slug_id, case_insensitive)
+        .await?
+        .ok_or(UrlErr::NotFound)?;
+    drop(conn);
+    if target.disabled {
+        return Err(UrlErr::LinkDisabled);
+    }
+    // template links don't go to one page, and there's nothing to see at a `mailto:`
+    let parsed = url::Url::parse(&target.url)
+        .ok()
+        .filter(destination::is_web)
+        .filter(|_| template::arg_count(&target.url) == 0)
+        .ok_or(UrlErr::NotFound)?;
+    // the service would otherwise take pictures of the server's own network for anyone
+    if let Err(err) = unfurl::public_addr(&parsed).await {
+        debug!("not taking a preview of {}: {}", target.url, err);
+        return Err(UrlErr::NotFound);
+    }
+    Ok(target.url)
+}
+
+/// A picture of where a link goes, taken when it is first asked for
+pub async fn preview(
+    State(state): State<AppState>,
+    UrlPath(slug_id): UrlPath<String>,
+) -> Result<impl IntoResponse, UrlErr> {
+    let config = state.config.get();
+    let preview = &config.preview;
+    let service_url = preview
+        .service_url
+        .as_deref()
+        .ok_or(UrlErr::FeatureDisabled("Previews"))?;
+
+    let url = target(&state, &slug_id, &config).await?;
+    let image = state.previews.image(&url, service_url, preview).await?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/png"),
+            (header::CACHE_CONTROL, "public, max-age=86400"),
+        ],
+        image,
+    ))
+}
+
+/// The width and height of a png, from its header
+fn dimensions(png: &[u8]) -> Option<(u32, u32)> {
+    if !png.starts_with(b"\x89PNG\r\n\x1a\n") || png.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes(png.get(16..20)?.try_into().ok()?);
+    let height = u32::from_be_bytes(png.get(20..24)?.try_into().ok()?);
+    Some((width, height))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OEmbedParams {
+    /// A link, such as `https://sho.rt/abc`
+    url: String,
+    /// Only `json` is supported
+    format: Option<String>,
+    maxwidth: Option<u32>,
+    maxheight: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OEmbed {
+    version: &'static str,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    /// The link's preview image
+    url: String,
+    width: u32,
+    height: u32,
+    /// Where the link goes
+    title: String,
+    provider_url: String,
+    /// How long (in seconds) this may be kept for
+    cache_age: u64,
+}
+
+/// An oEmbed `photo` of the preview image of a link, so that sites which embed links can show
+/// where it goes
+pub async fn oembed(
+    State(state): State<AppState>,
+    Query(params): Query<OEmbedParams>,
+) -> Result<Json<OEmbed>, UrlErr> {
+    let config = state.config.get();
+    let preview = &config.preview;
+    let service_url = preview
+        .service_url
+        .as_deref()
+        .ok_or(UrlErr::FeatureDisabled("Previews"))?;
+    if params.format.as_deref().is_some_and(|f| f != "json") {
+        return Err(UrlErr::UnsupportedFormat);
+    }
+
+    // the image has to be given with a full url, so only links on our own host are known
+    let base_url = config
+        .base_url
+        .as_ref()
+        .and_then(|base| url::Url::parse(base).ok())
+        .ok_or(UrlErr::FeatureDisabled("oEmbed without a base_url"))?;
+    let link = url::Url::parse(&params.url).map_err(UrlErr::InvalidUrl)?;
+    let path = link
+        .path()
+        .strip_prefix(base_url.path().trim_end_matches('/'))
+        .and_then(|path| path.strip_prefix('/'))
+        .filter(|slug| !slug.is_empty() && !slug.contains('/'))
+        .filter(|_| link.origin() == base_url.origin())
+        .ok_or(UrlErr::NotFound)?;
+    let slug_id = percent_decode_str(path).decode_utf8_lossy();
+
+    let url = target(&state, &slug_id, &config).await?;
+    let image = state.previews.image(&url, service_url, preview).await?;
+    let (width, height) = dimensions(&image).ok_or(UrlErr::PreviewUnavailable)?;
+
+    // shown smaller when the consumer asks for it, keeping its shape
+    let scale =
+        |max: Option<u32>, size: u32| max.map_or(1.0, |max| f64::from(max) / f64::from(size));
+    let scale = scale(params.maxwidth, width)
+        .min(scale(params.maxheight, height))
+        .min(1.0);
+    let fit = |size: u32| ((f64::from(size) * scale).round() as u32).max(1);
+
+    Ok(Json(OEmbed {
+        version: "1.0",
+        kind: "photo",
+        url: format!("{}/{}/preview.png", config.base_url(), path),
+        width: fit(width),
+        height: fit(height),
+        title: url,
+        provider_url: config.base_url().to_string(),
+        cache_age: preview.max_age,
+    }))
+}
@@ -0,0 +1,34 @@
+use crate::config::Config;
+
+/// Bundled word list, one per line.  These are matched anywhere in a slug, so stems are enough.
+const WORDS: &str = include_str!("profanity.txt");
+
+/// Undo the usual number-for-letter swaps so that `sh1t` is caught as well
+fn deleet(c: char) -> char {
+    match c {
+        '0' => 'o',
+        '1' => 'i',
+        '3' => 'e',
+        '4' => 'a',
+        '5' => 's',
+        '7' => 't',
+        c => c.to_ascii_lowercase(),
+    }
+}
+
+/// Whether a generated slug contains a word from the bundled list or from
+/// [`Config::profanity_words`]
+pub fn is_profane(slug: &str, config: &Config) -> bool {
+    let slug = slug
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(deleet)
+        .collect::<String>();
+
+    WORDS
+        .lines()
+        .chain(config.profanity_words.iter().map(String::as_str))
+        .map(str::trim)
+        .filter(|w| !w.is_empty())
+        .any(|w| slug.contains(&w.to_ascii_lowercase()))
+}
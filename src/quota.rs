@@ -0,0 +1,285 @@
+//! Limits on how many links each client may have and create, so that one client can't take
+//! over a shared deployment.  Clients are told apart by their API key (`X-Api-Key`), or by
+//! their address if they don't send one.
+//!
+//! Creations are counted in memory, so restarting the server starts everyone's day over.
+
+use std::{net::IpAddr, time::Duration};
+
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::constant_time_eq, db::Connection, error::UrlErr, rate::WindowCounter, schema::urls,
+};
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// The window that creations are counted in
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct QuotaConfig {
+    /// How many links (that aren't disabled) each address may have, `0` for no limit
+    pub max_links: u64,
+    /// How many links each address may create per day, `0` for no limit
+    pub max_creations_per_day: u64,
+    /// Clients that send one of these keys get its quotas instead
+    pub api_keys: Vec<ApiKey>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiKey {
+    /// Stored with each link created with this key
+    pub name: String,
+    pub key: String,
+    #[serde(default)]
+    pub max_links: u64,
+    #[serde(default)]
+    pub max_creations_per_day: u64,
+}
+
+/// Who a request counts against
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Client {
+    /// The name of their API key
+    Key(String),
+    Ip(IpAddr),
+}
+
+impl Client {
+    /// Work out who sent a request, failing if they sent an API key that isn't configured
+    pub fn identify(headers: &HeaderMap, ip: IpAddr, config: &QuotaConfig) -> Result<Self, UrlErr> {
+        let Some(sent) = headers.get(API_KEY_HEADER) else {
+            return Ok(Client::Ip(ip));
+        };
+        config
+            .api_keys
+            .iter()
+            .find(|k| constant_time_eq(k.key.as_bytes(), sent.as_bytes()))
+            .map(|k| Client::Key(k.name.clone()))
+            .ok_or(UrlErr::Unauthorized)
+    }
+
+    /// The name of their API key, which is stored with the links they create
+    pub fn key_name(&self) -> Option<&str> {
+        match self {
+            Client::Key(name) => Some(name),
+            Client::Ip(_) => None,
+        }
+    }
+
+    /// Their `(max_links, max_creations_per_day)`
+    fn limits(&self, config: &QuotaConfig) -> (u64, u64) {
+        match self {
+            Client::Key(name) => config
+                .api_keys
+                .iter()
+                .find(|k| &k.name == name)
+                .map_or((0, 0), |k| (k.max_links, k.max_creations_per_day)),
+            Client::Ip(_) => (config.max_links, config.max_creations_per_day),
+        }
+    }
+
+    /// How many links they have that aren't disabled.  Links made with an API key don't count
+    /// against the address that sent them.
+    pub async fn count_links(&self, conn: &mut Connection) -> QueryResult<i64> {
+        let query = urls::table.filter(urls::disabled.eq(false)).into_boxed();
+        let query = match self {
+            Client::Key(name) => query.filter(urls::api_key.eq(name.clone())),
+            Client::Ip(ip) => query
+                .filter(urls::author_ip.eq(ip.to_string()))
+                .filter(urls::api_key.is_null()),
+        };
+        query.count().get_result(conn).await
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Usage {
+    pub used: u64,
+    /// `0` for no limit
+    pub limit: u64,
+}
+
+impl Usage {
+    fn remaining(&self) -> u64 {
+        self.limit.saturating_sub(self.used)
+    }
+
+    fn used_up(&self) -> bool {
+        self.limit > 0 && self.used >= self.limit
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QuotaStatus {
+    pub links: Usage,
+    /// Links created in the current day
+    pub creations: Usage,
+    /// Seconds until creations are counted from zero again
+    pub resets_in: u64,
+}
+
+impl QuotaStatus {
+    /// Fail if either quota has been used up
+    pub fn check(&self) -> Result<(), UrlErr> {
+        if self.links.used_up() {
+            return Err(UrlErr::QuotaExceeded("link"));
+        }
+        if self.creations.used_up() {
+            return Err(UrlErr::QuotaExceeded("daily creation"));
+        }
+        Ok(())
+    }
+
+    /// `X-RateLimit-*` headers for the daily creations, and `X-Quota-Links-*` for the links,
+    /// leaving out quotas that aren't limited
+    pub fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let mut insert = |name: &'static str, value: u64| {
+            headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+        };
+        if self.creations.limit > 0 {
+            insert("x-ratelimit-limit", self.creations.limit);
+            insert("x-ratelimit-remaining", self.creations.remaining());
+            insert("x-ratelimit-reset", self.resets_in);
+        }
+        if self.links.limit > 0 {
+            insert("x-quota-links-limit", self.links.limit);
+            insert("x-quota-links-remaining", self.links.remaining());
+        }
+        headers
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Quotas {
+    creations: WindowCounter<Client>,
+}
+
+impl Quotas {
+    /// Where `client` stands, given how many links they have
+    pub fn status(&self, client: &Client, links: i64, config: &QuotaConfig) -> QuotaStatus {
+        let (max_links, max_creations) = client.limits(config);
+        QuotaStatus {
+            links: Usage {
+                used: links.max(0) as u64,
+                limit: max_links,
+            },
+            creations: Usage {
+                used: u64::from(self.creations.count(client, DAY)),
+                limit: max_creations,
+            },
+            resets_in: self
+                .creations
+                .resets_in(client, DAY)
+                .unwrap_or(DAY)
+                .as_secs(),
+        }
+    }
+
+    /// Count a link that `client` created
+    pub fn record_creation(&self, client: Client) {
+        self.creations.hit(client, DAY);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip() -> IpAddr {
+        "203.0.113.7".parse().unwrap()
+    }
+
+    fn config(max_links: u64, max_creations_per_day: u64) -> QuotaConfig {
+        QuotaConfig {
+            max_links,
+            max_creations_per_day,
+            api_keys: vec![ApiKey {
+                name: "ci".into(),
+                key: "secret".into(),
+                max_links: 100,
+                max_creations_per_day: 10,
+            }],
+        }
+    }
+
+    #[test]
+    fn at_the_limit() {
+        let config = config(2, 0);
+        let quotas = Quotas::default();
+        let client = Client::Ip(ip());
+
+        assert!(quotas.status(&client, 1, &config).check().is_ok());
+        // a client at exactly its limit can't make another
+        assert!(matches!(
+            quotas.status(&client, 2, &config).check(),
+            Err(UrlErr::QuotaExceeded("link"))
+        ));
+        assert!(quotas.status(&client, 3, &config).check().is_err());
+    }
+
+    #[test]
+    fn unlimited() {
+        let config = config(0, 0);
+        let quotas = Quotas::default();
+        let client = Client::Ip(ip());
+        for _ in 0..100 {
+            quotas.record_creation(client.clone());
+        }
+        let status = quotas.status(&client, 1_000_000, &config);
+        assert!(status.check().is_ok());
+        assert!(status.headers().is_empty());
+    }
+
+    #[test]
+    fn daily_creations() {
+        let config = config(0, 2);
+        let quotas = Quotas::default();
+        let client = Client::Ip(ip());
+        assert_eq!(quotas.status(&client, 0, &config).resets_in, DAY.as_secs());
+
+        quotas.record_creation(client.clone());
+        let status = quotas.status(&client, 0, &config);
+        assert!(status.check().is_ok());
+        assert_eq!(status.creations.used, 1);
+        assert!(status.resets_in <= DAY.as_secs());
+
+        quotas.record_creation(client.clone());
+        assert!(matches!(
+            quotas.status(&client, 0, &config).check(),
+            Err(UrlErr::QuotaExceeded("daily creation"))
+        ));
+
+        // each client has its own window
+        let other = Client::Ip("203.0.113.8".parse().unwrap());
+        assert_eq!(quotas.status(&other, 0, &config).creations.used, 0);
+    }
+
+    #[test]
+    fn api_keys() {
+        let config = config(1, 1);
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            Client::identify(&headers, ip(), &config).unwrap(),
+            Client::Ip(ip())
+        );
+
+        headers.insert(API_KEY_HEADER, HeaderValue::from_static("secret"));
+        let client = Client::identify(&headers, ip(), &config).unwrap();
+        assert_eq!(client, Client::Key("ci".into()));
+        assert_eq!(client.key_name(), Some("ci"));
+        assert_eq!(client.limits(&config), (100, 10));
+
+        headers.insert(API_KEY_HEADER, HeaderValue::from_static("wrong"));
+        assert!(matches!(
+            Client::identify(&headers, ip(), &config),
+            Err(UrlErr::Unauthorized)
+        ));
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Past this many tracked keys, expired windows are dropped whenever a hit is recorded
+const PRUNE_AT: usize = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    since: Instant,
+    count: u32,
+}
+
+/// Counts hits per key in fixed windows, which start at the first hit after the last one ended
+#[derive(Debug)]
+pub struct WindowCounter<K> {
+    windows: Mutex<HashMap<K, Window>>,
+}
+
+impl<K> Default for WindowCounter<K> {
+    fn default() -> Self {
+        Self {
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K: Hash + Eq> WindowCounter<K> {
+    /// How many hits `key` has in its current window
+    pub fn count(&self, key: &K, window: Duration) -> u32 {
+        self.windows
+            .lock()
+            .unwrap()
+            .get(key)
+            .filter(|w| w.since.elapsed() < window)
+            .map_or(0, |w| w.count)
+    }
+
+    /// How long until the current window for `key` ends, `None` if it doesn't have one
+    pub fn resets_in(&self, key: &K, window: Duration) -> Option<Duration> {
+        self.windows
+            .lock()
+            .unwrap()
+            .get(key)
+            .and_then(|w| window.checked_sub(w.since.elapsed()))
+    }
+
+    /// Forget every hit for `key`
+    pub fn reset(&self, key: &K) {
+        self.windows.lock().unwrap().remove(key);
+    }
+
+    /// Record a hit for `key`, returning how many it has in its current window
+    pub fn hit(&self, key: K, window: Duration) -> u32 {
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() >= PRUNE_AT {
+            windows.retain(|_, w| w.since.elapsed() < window);
+        }
+
+        let entry = windows.entry(key).or_insert(Window {
+            since: Instant::now(),
+            count: 0,
+        });
+        if entry.since.elapsed() >= window {
+            *entry = Window {
+                since: Instant::now(),
+                count: 0,
+            };
+        }
+
+        entry.count += 1;
+        entry.count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows() {
+        let counter = WindowCounter::default();
+        let window = Duration::from_millis(50);
+        assert_eq!(counter.count(&"a", window), 0);
+        assert_eq!(counter.resets_in(&"a", window), None);
+
+        assert_eq!(counter.hit("a", window), 1);
+        assert_eq!(counter.hit("a", window), 2);
+        assert_eq!(counter.count(&"a", window), 2);
+        assert_eq!(counter.count(&"b", window), 0);
+        assert!(counter.resets_in(&"a", window).is_some_and(|d| d <= window));
+
+        // a hit after the window ends starts a new one
+        std::thread::sleep(window);
+        assert_eq!(counter.count(&"a", window), 0);
+        assert_eq!(counter.hit("a", window), 1);
+
+        counter.reset(&"a");
+        assert_eq!(counter.count(&"a", window), 0);
+    }
+}
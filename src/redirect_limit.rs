@@ -0,0 +1,65 @@
+//! Caps how often a single link may be followed, to contain one that has been posted somewhere
+//! it shouldn't have been without deleting it
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use crate::{error::UrlErr, rate::WindowCounter};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RedirectLimitConfig {
+    /// How many times any link may be followed within `window`, links with their own
+    /// `redirect_limit` use that instead.  `0` removes the limit.
+    pub max_redirects: u32,
+    /// The length (in seconds) of the window that redirects are counted in
+    pub window: u64,
+}
+
+impl Default for RedirectLimitConfig {
+    fn default() -> Self {
+        Self {
+            max_redirects: 0,
+            window: 60,
+        }
+    }
+}
+
+impl RedirectLimitConfig {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RedirectLimiter {
+    redirects: WindowCounter<String>,
+    /// Redirects that were refused because their link was over its limit
+    pub limited_count: AtomicU64,
+}
+
+impl RedirectLimiter {
+    /// Count a redirect for `slug`, failing if that takes it over `link_limit` (or the configured
+    /// limit, for links without their own)
+    pub fn check(
+        &self,
+        slug: &str,
+        link_limit: Option<i32>,
+        config: &RedirectLimitConfig,
+    ) -> Result<(), UrlErr> {
+        let max = link_limit.map_or(config.max_redirects, |l| l.max(0) as u32);
+        if max == 0 {
+            return Ok(());
+        }
+
+        if self.redirects.hit(slug.to_string(), config.window()) > max {
+            self.limited_count.fetch_add(1, Ordering::Relaxed);
+            return Err(UrlErr::RateLimited);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+use axum::{http::Request, middleware::Next, response::Response};
+use tracing::Span;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    static REQUEST_ID: Option<String>;
+}
+
+fn header_id<B>(req: &Request<B>) -> Option<&str> {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+}
+
+/// The id of the request that is currently being handled, if there is one
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok().flatten()
+}
+
+/// Middleware that makes the request's id available to [`current`] while the rest of the stack
+/// handles it.  This needs to run after the id has been set on the request.
+pub async fn scope<B>(req: Request<B>, next: Next<B>) -> Response {
+    let id = header_id(&req).map(String::from);
+    REQUEST_ID.scope(id, next.run(req)).await
+}
+
+/// Build the tracing span for a request, tagged with its id
+pub fn make_span<B>(req: &Request<B>) -> Span {
+    tracing::debug_span!(
+        "request",
+        method = %req.method(),
+        uri = %req.uri(),
+        version = ?req.version(),
+        request_id = %header_id(req).unwrap_or_default(),
+    )
+}
@@ -0,0 +1,92 @@
+//! Tracks clients that keep asking for slugs that don't exist, so that scanners can't walk the
+//! slug space looking for links that were only meant for a few people.
+
+use std::{
+    net::IpAddr,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::rate::WindowCounter;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ScanGuardConfig {
+    pub enabled: bool,
+    /// How many unknown slugs a client may ask for within `window` before being blocked
+    pub max_misses: u32,
+    /// The length (in seconds) of the window that misses are counted in, a blocked client stays
+    /// blocked until it ends
+    pub window: u64,
+    /// The least time (in milliseconds) that a slug lookup takes, so that hits and misses can't
+    /// be told apart by timing.  `0` turns this off.
+    pub min_lookup_time: u64,
+}
+
+impl Default for ScanGuardConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_misses: 20,
+            window: 60,
+            min_lookup_time: 0,
+        }
+    }
+}
+
+impl ScanGuardConfig {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window)
+    }
+
+    pub fn min_lookup_time(&self) -> Duration {
+        Duration::from_millis(self.min_lookup_time)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ScanGuard {
+    misses: WindowCounter<IpAddr>,
+    /// Lookups for slugs that don't exist
+    pub miss_count: AtomicU64,
+    /// Lookups that were refused because the client was blocked
+    pub blocked_count: AtomicU64,
+}
+
+impl ScanGuard {
+    /// Whether `ip` has asked for too many unknown slugs recently, counting the request as blocked
+    /// if so
+    pub fn is_blocked(&self, ip: IpAddr, config: &ScanGuardConfig) -> bool {
+        if !config.enabled {
+            return false;
+        }
+
+        let blocked = self.misses.count(&ip, config.window()) >= config.max_misses;
+        if blocked {
+            self.blocked_count.fetch_add(1, Ordering::Relaxed);
+        }
+        blocked
+    }
+
+    /// Count a lookup of a slug that doesn't exist, returning whether this pushed `ip` over the
+    /// limit
+    pub fn record_miss(&self, ip: IpAddr, config: &ScanGuardConfig) -> bool {
+        self.miss_count.fetch_add(1, Ordering::Relaxed);
+        if !config.enabled {
+            return false;
+        }
+
+        let count = self.misses.hit(ip, config.window());
+        if count == config.max_misses {
+            warn!(
+                "{} asked for {} unknown slugs within {}s, blocking lookups until the window ends",
+                ip, count, config.window
+            );
+            return true;
+        }
+        false
+    }
+}
@@ -1,8 +1,108 @@
+diesel::table! {
+    aliases (alias) {
+        alias -> Text,
+        slug -> Text,
+    }
+}
+
+diesel::table! {
+    audit_log (id) {
+        id -> BigInt,
+        created_at -> BigInt,
+        slug -> Text,
+        action -> Text,
+        actor -> Text,
+        detail -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     urls (slug) {
         slug -> Text,
         url -> Text,
         author_ip -> Text,
         usage_count -> Integer,
+        cache_control -> Nullable<Text>,
+        redirect_limit -> Nullable<Integer>,
+        disabled -> Bool,
+        owner_email -> Nullable<Text>,
+        api_key -> Nullable<Text>,
+        campaign -> Nullable<Text>,
+        created_at -> BigInt,
+        updated_at -> BigInt,
+        description -> Nullable<Text>,
+        unavailable_message -> Nullable<Text>,
+        unavailable_url -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    page_links (id) {
+        id -> BigInt,
+        page -> Text,
+        position -> Integer,
+        title -> Text,
+        url -> Text,
+        clicks -> Integer,
+    }
+}
+
+diesel::table! {
+    pages (slug) {
+        slug -> Text,
+        title -> Text,
+        description -> Nullable<Text>,
+        created_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    reports (id) {
+        id -> BigInt,
+        slug -> Text,
+        reason -> Text,
+        reporter_ip -> Text,
+        created_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    slug_sequence (id) {
+        id -> BigInt,
+    }
+}
+
+diesel::table! {
+    campaigns (name) {
+        name -> Text,
+        created_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    clicks (id) {
+        id -> BigInt,
+        slug -> Text,
+        clicked_at -> BigInt,
+        referrer -> Nullable<Text>,
+        user_agent -> Nullable<Text>,
+        ip -> Nullable<Text>,
+        country -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    daily_clicks (slug, day) {
+        slug -> Text,
+        day -> Text,
+        clicks -> BigInt,
+    }
+}
+
+diesel::table! {
+    notification_preferences (email) {
+        email -> Text,
+        milestones -> Bool,
+        moderation -> Bool,
     }
 }
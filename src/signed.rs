@@ -0,0 +1,224 @@
+//! Short links that carry their destination and expiry in the slug itself, signed so that they
+//! can be checked without a database row.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::error::UrlErr;
+
+/// Every signed slug starts with this, which custom slugs may not
+pub const PREFIX: char = '~';
+
+/// How many bytes of the HMAC are kept, enough that guessing one is hopeless while keeping the
+/// slug from getting too long
+const MAC_LEN: usize = 16;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// What each kind of message starts with before it is signed, so that the MAC of one is never
+/// valid for the other even though they share a key
+const SLUG_TAG: &[u8] = b"slug:";
+const TOKEN_TAG: &[u8] = b"token:";
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn mac(key: &str) -> HmacSha256 {
+    HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any length")
+}
+
+/// Build a slug that redirects to `url` until `expires_at` (unix seconds)
+pub fn mint(url: &str, expires_at: u64, key: &str) -> String {
+    let mut payload = expires_at.to_be_bytes().to_vec();
+    payload.extend_from_slice(url.as_bytes());
+
+    let mut mac = mac(key);
+    mac.update(SLUG_TAG);
+    mac.update(&payload);
+    payload.extend_from_slice(&mac.finalize().into_bytes()[..MAC_LEN]);
+
+    format!("{}{}", PREFIX, URL_SAFE_NO_PAD.encode(payload))
+}
+
+/// Check a slug made by [`mint`], returning the url and how many seconds it has left
+pub fn verify(slug: &str, key: &str) -> Result<(String, u64), UrlErr> {
+    let bytes = slug
+        .strip_prefix(PREFIX)
+        .and_then(|s| URL_SAFE_NO_PAD.decode(s).ok())
+        .ok_or(UrlErr::NotFound)?;
+    if bytes.len() < 8 + MAC_LEN {
+        return Err(UrlErr::NotFound);
+    }
+
+    let (payload, tag) = bytes.split_at(bytes.len() - MAC_LEN);
+    let mut mac = mac(key);
+    mac.update(SLUG_TAG);
+    mac.update(payload);
+    mac.verify_truncated_left(tag)
+        .map_err(|_| UrlErr::NotFound)?;
+
+    let (expires_at, url) = payload.split_at(8);
+    let expires_at = u64::from_be_bytes(expires_at.try_into().unwrap());
+    let ttl = expires_at.saturating_sub(now());
+    if ttl == 0 {
+        return Err(UrlErr::Expired);
+    }
+
+    let url = String::from_utf8(url.to_vec()).map_err(|_| UrlErr::NotFound)?;
+    Ok((url, ttl))
+}
+
+/// A token proving that whoever holds it was sent `value` by this server, for links (such as in
+/// emails) that let someone manage something without an account
+pub fn token(value: &str, key: &str) -> String {
+    let mut mac = mac(key);
+    mac.update(TOKEN_TAG);
+    mac.update(value.as_bytes());
+    format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(value),
+        URL_SAFE_NO_PAD.encode(&mac.finalize().into_bytes()[..MAC_LEN])
+    )
+}
+
+/// Check a token made by [`token`], returning the value it was made for
+pub fn check_token(token: &str, key: &str) -> Result<String, UrlErr> {
+    let (value, tag) = token
+        .split_once('.')
+        .and_then(|(v, t)| {
+            Some((
+                URL_SAFE_NO_PAD.decode(v).ok()?,
+                URL_SAFE_NO_PAD.decode(t).ok()?,
+            ))
+        })
+        .ok_or(UrlErr::Unauthorized)?;
+
+    let mut mac = mac(key);
+    mac.update(TOKEN_TAG);
+    mac.update(&value);
+    mac.verify_truncated_left(&tag)
+        .map_err(|_| UrlErr::Unauthorized)?;
+
+    String::from_utf8(value).map_err(|_| UrlErr::Unauthorized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "test key";
+    const URL: &str = "https://example.com/some/page?q=1";
+
+    fn decode(slug: &str) -> Vec<u8> {
+        URL_SAFE_NO_PAD.decode(&slug[1..]).unwrap()
+    }
+
+    fn encode(bytes: &[u8]) -> String {
+        format!("{}{}", PREFIX, URL_SAFE_NO_PAD.encode(bytes))
+    }
+
+    #[test]
+    fn round_trip() {
+        let slug = mint(URL, now() + 60, KEY);
+        assert!(slug.starts_with(PREFIX));
+
+        let (url, ttl) = verify(&slug, KEY).unwrap();
+        assert_eq!(url, URL);
+        assert!((59..=60).contains(&ttl));
+    }
+
+    #[test]
+    fn tampered_mac() {
+        let mut bytes = decode(&mint(URL, now() + 60, KEY));
+        *bytes.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            verify(&encode(&bytes), KEY),
+            Err(UrlErr::NotFound)
+        ));
+    }
+
+    #[test]
+    fn tampered_payload() {
+        let mut bytes = decode(&mint(URL, now() + 60, KEY));
+        // push the expiry back without signing it again
+        bytes[0] ^= 1;
+        assert!(matches!(
+            verify(&encode(&bytes), KEY),
+            Err(UrlErr::NotFound)
+        ));
+    }
+
+    #[test]
+    fn wrong_key() {
+        let slug = mint(URL, now() + 60, KEY);
+        assert!(matches!(verify(&slug, "other key"), Err(UrlErr::NotFound)));
+    }
+
+    #[test]
+    fn expired() {
+        let slug = mint(URL, now() - 1, KEY);
+        assert!(matches!(verify(&slug, KEY), Err(UrlErr::Expired)));
+
+        let slug = mint(URL, now(), KEY);
+        assert!(matches!(verify(&slug, KEY), Err(UrlErr::Expired)));
+
+        let slug = mint(URL, 0, KEY);
+        assert!(matches!(verify(&slug, KEY), Err(UrlErr::Expired)));
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = decode(&mint(URL, now() + 60, KEY));
+        for len in [0, 1, 8, 8 + MAC_LEN - 1, bytes.len() - 1] {
+            let slug = encode(&bytes[..len]);
+            assert!(
+                matches!(verify(&slug, KEY), Err(UrlErr::NotFound)),
+                "{}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn malformed() {
+        let slug = mint(URL, now() + 60, KEY);
+        // without the prefix, or with characters that aren't base64
+        assert!(matches!(verify(&slug[1..], KEY), Err(UrlErr::NotFound)));
+        assert!(matches!(
+            verify(&format!("{}!", slug), KEY),
+            Err(UrlErr::NotFound)
+        ));
+    }
+
+    #[test]
+    fn tokens() {
+        let token = token("owner@example.com", KEY);
+        assert_eq!(check_token(&token, KEY).unwrap(), "owner@example.com");
+        assert!(check_token(&token, "other key").is_err());
+        assert!(check_token(&token.replace('.', ""), KEY).is_err());
+    }
+
+    #[test]
+    fn token_is_not_a_slug() {
+        // without its own tag, a slug whose payload is the token's signed bytes would verify,
+        // and "token:" read as an expiry is far in the future
+        let value = format!("ab{}", URL);
+        let token = token(&value, KEY);
+        let (_, tag) = token.split_once('.').unwrap();
+
+        let mut bytes = TOKEN_TAG.to_vec();
+        bytes.extend_from_slice(value.as_bytes());
+        bytes.extend_from_slice(&URL_SAFE_NO_PAD.decode(tag).unwrap());
+        assert!(matches!(
+            verify(&encode(&bytes), KEY),
+            Err(UrlErr::NotFound)
+        ));
+    }
+}
@@ -0,0 +1,391 @@
+use diesel::{
+    define_sql_function,
+    dsl::sql,
+    expression::BoxableExpression,
+    prelude::*,
+    sql_types::{Bool, Text},
+    sqlite::Sqlite,
+};
+use diesel_async::{scoped_futures::ScopedFutureExt, RunQueryDsl};
+use harsh::Harsh;
+use nanoid::nanoid;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{
+    alias,
+    config::Config,
+    db::Connection,
+    error::UrlErr,
+    page,
+    schema::{slug_sequence, urls},
+    signed,
+};
+
+define_sql_function!(fn last_insert_rowid() -> BigInt);
+
+const BASE62_ALPHABET: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const BASE36_ALPHABET: &str = "0123456789abcdefghijklmnopqrstuvwxyz";
+
+const LOWERCASE_ALPHABET: [char; 38] = [
+    '_', '-', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'a', 'b', 'c', 'd', 'e', 'f', 'g',
+    'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z',
+];
+
+/// How random slugs are generated when the user doesn't ask for one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SlugStrategy {
+    /// 10 random characters
+    #[default]
+    Nanoid,
+    /// A counter, written in base 62 (or base 36 with case-insensitive slugs)
+    Base62,
+    /// A counter, obfuscated with hashids and [`Config::hashids_salt`]
+    Hashids,
+    /// A hash of the destination, so the same url always gets the same slug
+    Hash,
+}
+
+/// The strategies that [`generate`] can make slugs with on their own, which is every one but
+/// [`SlugStrategy::Hash`], since those slugs come from the url (see [`hashed`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Generated {
+    Nanoid,
+    Base62,
+    Hashids,
+}
+
+impl SlugStrategy {
+    /// The strategy for [`generate`], or `None` for [`SlugStrategy::Hash`]
+    pub fn generated(self) -> Option<Generated> {
+        match self {
+            SlugStrategy::Nanoid => Some(Generated::Nanoid),
+            SlugStrategy::Base62 => Some(Generated::Base62),
+            SlugStrategy::Hashids => Some(Generated::Hashids),
+            SlugStrategy::Hash => None,
+        }
+    }
+}
+
+/// The shortest and longest slugs that [`SlugStrategy::Hash`] will use
+const HASH_SLUG_LENGTHS: std::ops::RangeInclusive<usize> = 7..=16;
+
+/// Slugs that are, or might one day be, used by the server's own routes
+pub const RESERVED_SLUGS: &[&str] = &[
+    "admin",
+    "api",
+    "assets",
+    "dashboard",
+    "docs",
+    "favicon.ico",
+    "healthz",
+    "login",
+    "logout",
+    "metrics",
+    "new",
+    "oembed",
+    "readyz",
+    "robots.txt",
+    "static",
+];
+
+/// Which characters a user may use in a slug
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SlugCharset {
+    /// `A-Z`, `a-z`, `0-9`, `-` and `_`, the same as generated slugs
+    Ascii,
+    /// Letters and numbers from any script, emoji and other symbols, `-` and `_`
+    #[default]
+    Unicode,
+    /// Anything but whitespace, control characters and `/`
+    Any,
+}
+
+impl SlugCharset {
+    pub fn allows(self, c: char) -> bool {
+        if c.is_whitespace() || c.is_control() || c == '/' {
+            return false;
+        }
+
+        match self {
+            SlugCharset::Ascii => c.is_ascii_alphanumeric() || c == '-' || c == '_',
+            // anything outside of ascii is let through, which covers emoji (and the joiners
+            // and variation selectors in emoji sequences) without keeping a table of them
+            SlugCharset::Unicode => {
+                !c.is_ascii() || c.is_ascii_alphanumeric() || c == '-' || c == '_'
+            }
+            SlugCharset::Any => true,
+        }
+    }
+}
+
+pub fn gen_slug(lowercase: bool) -> String {
+    if lowercase {
+        nanoid!(10, &LOWERCASE_ALPHABET)
+    } else {
+        nanoid!(10)
+    }
+}
+
+/// Generate a slug with `strategy`
+pub async fn generate(
+    conn: &mut Connection,
+    strategy: Generated,
+    config: &Config,
+) -> QueryResult<String> {
+    let lowercase = config.case_insensitive_slugs;
+    let slug = match strategy {
+        Generated::Nanoid => gen_slug(lowercase),
+        Generated::Base62 => {
+            let alphabet = if lowercase {
+                BASE36_ALPHABET
+            } else {
+                BASE62_ALPHABET
+            };
+            encode_id(next_id(conn).await?, alphabet)
+        }
+        Generated::Hashids => {
+            let mut harsh = Harsh::builder()
+                .salt(config.hashids_salt.as_str())
+                .length(config.hashids_min_length);
+            if lowercase {
+                harsh = harsh.alphabet(BASE36_ALPHABET);
+            }
+            let harsh: Harsh = harsh.build().expect("hashids alphabet is valid");
+            harsh.encode(&[next_id(conn).await?])
+        }
+    };
+    Ok(slug)
+}
+
+/// Put a url into a canonical form, so that `HTTP://Example.com:80` and `http://example.com/`
+/// hash the same
+pub fn normalize_url(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(url) => url.to_string(),
+        Err(_) => url.to_string(),
+    }
+}
+
+/// Slugs for [`SlugStrategy::Hash`], from shortest to longest.  Longer ones only get used if
+/// the shorter ones are taken by a different url.
+pub fn hashed(normalized_url: &str, lowercase: bool) -> impl Iterator<Item = String> {
+    let alphabet = if lowercase {
+        BASE36_ALPHABET
+    } else {
+        BASE62_ALPHABET
+    }
+    .as_bytes();
+
+    let digest = Sha256::digest(normalized_url.as_bytes());
+    let full = digest
+        .iter()
+        .map(|b| alphabet[*b as usize % alphabet.len()] as char)
+        .collect::<String>();
+
+    HASH_SLUG_LENGTHS.map(move |len| full[..len].to_string())
+}
+
+/// Take the next id from the `slug_sequence` table
+async fn next_id(conn: &mut Connection) -> QueryResult<u64> {
+    conn.immediate_transaction(|conn| {
+        async move {
+            diesel::insert_into(slug_sequence::table)
+                .default_values()
+                .execute(conn)
+                .await?;
+            let id = diesel::select(last_insert_rowid())
+                .get_result::<i64>(conn)
+                .await?;
+            diesel::delete(slug_sequence::table).execute(conn).await?;
+            Ok(id as u64)
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+fn encode_id(mut id: u64, alphabet: &str) -> String {
+    let alphabet = alphabet.as_bytes();
+    let base = alphabet.len() as u64;
+    let mut out = Vec::new();
+    loop {
+        out.push(alphabet[(id % base) as usize]);
+        id /= base;
+        if id == 0 {
+            break;
+        }
+    }
+    out.reverse();
+    String::from_utf8(out).unwrap()
+}
+
+/// Whether `slug` is reserved, either built in or by the config.  This ignores case so that a
+/// route can't be shadowed by a differently cased slug.
+pub fn is_reserved(slug: &str, config: &Config) -> bool {
+    RESERVED_SLUGS
+        .iter()
+        .copied()
+        .chain(config.reserved_slugs.iter().map(String::as_str))
+        .any(|r| r.eq_ignore_ascii_case(slug))
+        // a link there could never be followed
+        || config.honeypot.matches(&format!("/{}", slug))
+}
+
+/// Normalize a slug to the form that is stored.  This is used both when creating and when
+/// looking up a slug, so that the two always agree.
+pub fn normalize(slug: &str, config: &Config) -> String {
+    let slug = slug.nfc().collect::<String>();
+    if config.case_insensitive_slugs {
+        slug.to_lowercase()
+    } else {
+        slug
+    }
+}
+
+/// A filter matching the url with `slug`, ignoring case if `case_insensitive` is set so that
+/// slugs stored before the option was turned on are still found.
+pub fn eq(
+    slug: String,
+    case_insensitive: bool,
+) -> Box<dyn BoxableExpression<urls::table, Sqlite, SqlType = Bool>> {
+    if case_insensitive {
+        Box::new(
+            sql::<Bool>("urls.slug = ")
+                .bind::<Text, _>(slug)
+                .sql(" COLLATE NOCASE"),
+        )
+    } else {
+        Box::new(urls::slug.eq(slug))
+    }
+}
+
+/// Whether a url (or alias, or page) with `slug` already exists
+pub async fn exists(
+    conn: &mut Connection,
+    slug: String,
+    case_insensitive: bool,
+) -> QueryResult<bool> {
+    let url = diesel::select(diesel::dsl::exists(
+        urls::table.filter(eq(slug.clone(), case_insensitive)),
+    ))
+    .get_result(conn)
+    .await?;
+    Ok(url
+        || alias::exists(conn, slug.clone(), case_insensitive).await?
+        || page::exists(conn, slug, case_insensitive).await?)
+}
+
+/// Check that a slug requested by a user may be used
+pub fn validate(slug: &str, config: &Config) -> Result<(), UrlErr> {
+    if slug.is_empty()
+        || slug.starts_with(signed::PREFIX)
+        || !slug.chars().all(|c| config.slug_charset.allows(c))
+    {
+        return Err(UrlErr::InvalidSlug);
+    }
+    if is_reserved(slug, config) {
+        return Err(UrlErr::SlugReserved);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(case_insensitive_slugs: bool, slug_charset: SlugCharset) -> Config {
+        Config {
+            case_insensitive_slugs,
+            slug_charset,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn normalizes() {
+        let sensitive = config(false, SlugCharset::Unicode);
+        let insensitive = config(true, SlugCharset::Unicode);
+        // `e` and a combining accent are stored as the single character
+        assert_eq!(normalize("cafe\u{301}", &sensitive), "caf\u{e9}");
+        assert_eq!(normalize("Promo", &sensitive), "Promo");
+        assert_eq!(normalize("Promo", &insensitive), "promo");
+        assert_eq!(normalize("ÄBC-Straße", &insensitive), "äbc-straße");
+        assert_eq!(normalize("CAFE\u{301}", &insensitive), "caf\u{e9}");
+        assert_eq!(normalize("🦀", &insensitive), "🦀");
+    }
+
+    #[test]
+    fn valid() {
+        let unicode = config(false, SlugCharset::Unicode);
+        for slug in ["abc", "A-b_9", "café", "日本語", "🦀", "👩‍💻", "MiXeD"] {
+            assert!(validate(slug, &unicode).is_ok(), "{}", slug);
+        }
+        let any = config(false, SlugCharset::Any);
+        for slug in ["a.b", "a+b", "50%", "a~b"] {
+            assert!(validate(slug, &any).is_ok(), "{}", slug);
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        let ascii = config(false, SlugCharset::Ascii);
+        let unicode = config(false, SlugCharset::Unicode);
+        let any = config(false, SlugCharset::Any);
+        for (slug, config) in [
+            ("", &unicode),
+            ("a b", &unicode),
+            ("a/b", &any),
+            ("a\u{0}b", &any),
+            ("a\u{3000}b", &any),
+            ("~abc", &any),
+            ("a.b", &unicode),
+            ("café", &ascii),
+            ("🦀", &ascii),
+        ] {
+            assert!(
+                matches!(validate(slug, config), Err(UrlErr::InvalidSlug)),
+                "{}",
+                slug
+            );
+        }
+    }
+
+    #[test]
+    fn reserved() {
+        let mut config = config(false, SlugCharset::Any);
+        config.reserved_slugs = vec!["Promo".into()];
+        for slug in ["api", "API", "Admin", "promo", "robots.txt", "x.php"] {
+            assert!(
+                matches!(validate(slug, &config), Err(UrlErr::SlugReserved)),
+                "{}",
+                slug
+            );
+        }
+        assert!(validate("apis", &config).is_ok());
+    }
+
+    #[test]
+    fn encodes_ids() {
+        assert_eq!(encode_id(0, BASE62_ALPHABET), "0");
+        assert_eq!(encode_id(61, BASE62_ALPHABET), "z");
+        assert_eq!(encode_id(62, BASE62_ALPHABET), "10");
+        assert_eq!(encode_id(35, BASE36_ALPHABET), "z");
+        assert_eq!(encode_id(36, BASE36_ALPHABET), "10");
+    }
+
+    #[test]
+    fn hashes() {
+        let url = normalize_url("HTTP://Example.com:80");
+        assert_eq!(url, "http://example.com/");
+        let slugs = hashed(&url, false).collect::<Vec<_>>();
+        assert_eq!(slugs.len(), HASH_SLUG_LENGTHS.count());
+        assert_eq!(slugs, hashed(&url, false).collect::<Vec<_>>());
+        // each is the one before with another character
+        assert!(slugs.windows(2).all(|w| w[1].starts_with(&w[0])));
+        assert!(hashed(&url, true).all(|s| !s.chars().any(|c| c.is_ascii_uppercase())));
+    }
+}
@@ -0,0 +1,253 @@
+//! Scores destinations on a few signs of spam, so that likely spam can be refused or held for
+//! an admin to look at before it works
+
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::{config::Config, destination::is_under};
+
+/// The reporter recorded on reports filed for links held for review
+pub const REPORTER: &str = "spam-filter";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SpamConfig {
+    /// Urls scoring at least this are created disabled, with a report for an admin to review.
+    /// `0` never holds a url.
+    pub review_at: u32,
+    /// Urls scoring at least this are refused, `0` never refuses one
+    pub reject_at: u32,
+    /// Hosts with more labels than this (`a.b.example.com` has 4) look like spam
+    pub max_host_labels: usize,
+    /// Other url shorteners, which are used to hide where a link really goes
+    pub shorteners: Vec<String>,
+    /// Top level domains that are mostly used for spam
+    pub tlds: Vec<String>,
+}
+
+impl Default for SpamConfig {
+    fn default() -> Self {
+        let strings = |s: &[&str]| s.iter().map(|s| s.to_string()).collect();
+        Self {
+            review_at: 4,
+            reject_at: 7,
+            max_host_labels: 4,
+            shorteners: strings(&[
+                "bit.ly",
+                "buff.ly",
+                "cutt.ly",
+                "goo.gl",
+                "is.gd",
+                "ow.ly",
+                "rb.gy",
+                "shorturl.at",
+                "t.co",
+                "t.ly",
+                "tiny.cc",
+                "tinyurl.com",
+            ]),
+            tlds: strings(&[
+                "cf", "click", "ga", "gq", "loan", "ml", "tk", "top", "work", "xyz", "zip",
+            ]),
+        }
+    }
+}
+
+/// A sign of spam that a url showed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    /// The host is an IP address rather than a domain
+    IpHost,
+    /// The host has more labels than `max_host_labels`
+    ManySubdomains,
+    /// The url points at another url shortener (or this one)
+    Shortener,
+    /// The host is under one of the configured `tlds`
+    SpamTld,
+    /// A query parameter holds another url, as open redirects and click trackers use
+    TrackingRedirect,
+    /// The url has a username or password, as in `https://bank.com@evil.example`
+    Credentials,
+}
+
+impl Rule {
+    pub fn weight(self) -> u32 {
+        match self {
+            Rule::IpHost => 3,
+            Rule::ManySubdomains => 2,
+            Rule::Shortener => 3,
+            Rule::SpamTld => 2,
+            Rule::TrackingRedirect => 2,
+            Rule::Credentials => 4,
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Rule::IpHost => "ip-host",
+            Rule::ManySubdomains => "many-subdomains",
+            Rule::Shortener => "shortener",
+            Rule::SpamTld => "spam-tld",
+            Rule::TrackingRedirect => "tracking-redirect",
+            Rule::Credentials => "credentials",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    Review,
+    Reject,
+}
+
+#[derive(Debug, Clone)]
+pub struct Score {
+    pub rules: Vec<Rule>,
+}
+
+impl Score {
+    pub fn total(&self) -> u32 {
+        self.rules.iter().map(|r| r.weight()).sum()
+    }
+
+    pub fn verdict(&self, config: &SpamConfig) -> Verdict {
+        let total = self.total();
+        let reached = |threshold| threshold > 0 && total >= threshold;
+        if reached(config.reject_at) {
+            Verdict::Reject
+        } else if reached(config.review_at) {
+            Verdict::Review
+        } else {
+            Verdict::Allow
+        }
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "spam score {}", self.total())?;
+        for (i, rule) in self.rules.iter().enumerate() {
+            f.write_str(if i == 0 { ": " } else { ", " })?;
+            write!(f, "{}", rule)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn score(url: &url::Url, config: &Config) -> Score {
+    let spam = &config.spam;
+    let mut rules = Vec::new();
+
+    match url.host() {
+        Some(url::Host::Ipv4(_) | url::Host::Ipv6(_)) => rules.push(Rule::IpHost),
+        Some(url::Host::Domain(host)) => {
+            let host = host.trim_end_matches('.').to_lowercase();
+            if host.split('.').count() > spam.max_host_labels {
+                rules.push(Rule::ManySubdomains);
+            }
+            let own_host = url::Url::parse(config.base_url())
+                .ok()
+                .and_then(|u| u.host_str().map(str::to_lowercase));
+            let own = own_host.as_deref() == Some(host.as_str());
+            if own || spam.shorteners.iter().any(|s| is_under(&host, s)) {
+                rules.push(Rule::Shortener);
+            }
+            if spam.tlds.iter().any(|tld| is_under(&host, tld)) {
+                rules.push(Rule::SpamTld);
+            }
+        }
+        None => {}
+    }
+
+    let holds_url = |value: &str| {
+        let value = value.to_lowercase();
+        value.starts_with("http://") || value.starts_with("https://") || value.starts_with("//")
+    };
+    if url.query_pairs().any(|(_, value)| holds_url(&value)) {
+        rules.push(Rule::TrackingRedirect);
+    }
+    if !url.username().is_empty() || url.password().is_some() {
+        rules.push(Rule::Credentials);
+    }
+
+    Score { rules }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(url: &str, config: &Config) -> Vec<Rule> {
+        score(&url::Url::parse(url).unwrap(), config).rules
+    }
+
+    #[test]
+    fn clean() {
+        let config = Config::default();
+        assert_eq!(rules("https://example.com/page?q=1", &config), []);
+        assert_eq!(rules("https://docs.rs/axum", &config), []);
+    }
+
+    #[test]
+    fn each_rule() {
+        let config = Config {
+            base_url: Some("https://sho.rt/".into()),
+            ..Config::default()
+        };
+        for (url, rule) in [
+            ("http://93.184.216.34/", Rule::IpHost),
+            ("http://[2606:2800::1]/", Rule::IpHost),
+            ("https://a.b.c.example.com/", Rule::ManySubdomains),
+            ("https://bit.ly/abc", Rule::Shortener),
+            ("https://www.Bit.ly./abc", Rule::Shortener),
+            ("https://sho.rt/abc", Rule::Shortener),
+            ("https://example.xyz/", Rule::SpamTld),
+            (
+                "https://example.com/?to=https://evil.example",
+                Rule::TrackingRedirect,
+            ),
+            (
+                "https://example.com/?to=%2F%2Fevil.example",
+                Rule::TrackingRedirect,
+            ),
+            ("https://bank.com@evil.example/", Rule::Credentials),
+        ] {
+            assert_eq!(rules(url, &config), [rule], "{}", url);
+        }
+        // only a whole label counts
+        assert_eq!(rules("https://notbit.ly/", &config), []);
+    }
+
+    #[test]
+    fn verdicts() {
+        let config = SpamConfig::default();
+        let verdict = |rules: Vec<Rule>| Score { rules }.verdict(&config);
+        assert_eq!(verdict(vec![]), Verdict::Allow);
+        assert_eq!(verdict(vec![Rule::IpHost]), Verdict::Allow);
+        // exactly at a threshold reaches it
+        assert_eq!(verdict(vec![Rule::Credentials]), Verdict::Review);
+        assert_eq!(
+            verdict(vec![Rule::Credentials, Rule::IpHost]),
+            Verdict::Reject
+        );
+
+        let off = SpamConfig {
+            review_at: 0,
+            reject_at: 0,
+            ..SpamConfig::default()
+        };
+        let score = Score {
+            rules: vec![Rule::Credentials, Rule::IpHost, Rule::Shortener],
+        };
+        assert_eq!(score.verdict(&off), Verdict::Allow);
+        assert_eq!(
+            score.to_string(),
+            "spam score 10: credentials, ip-host, shortener"
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use rand::seq::SliceRandom;
+
+/// Short, inoffensive words used to pad out suggestions
+const WORDS: &[&str] = &[
+    "amber", "apple", "arch", "aspen", "atlas", "bay", "beam", "bird", "bloom", "blue", "bolt",
+    "brook", "cedar", "cloud", "coast", "comet", "coral", "crane", "dawn", "delta", "dune", "echo",
+    "ember", "fern", "field", "finch", "flame", "fox", "frost", "glade", "grove", "harbor", "hill",
+    "iris", "jade", "lake", "leaf", "lime", "lunar", "maple", "meadow", "mint", "moss", "nova",
+    "oak", "ocean", "olive", "orbit", "palm", "peak", "pine", "plum", "pond", "quartz", "rain",
+    "reef", "ridge", "river", "robin", "sage", "sand", "shore", "sky", "slate", "snow", "solar",
+    "spark", "star", "stone", "storm", "sun", "tide", "trail", "vale", "wave", "willow", "wind",
+    "wren",
+];
+
+/// Parts of a host name that say nothing about the site
+const HOST_NOISE: &[&str] = &[
+    "www", "m", "amp", "com", "org", "net", "io", "co", "uk", "dev",
+];
+
+/// Break `s` up into lowercase alphanumeric words
+fn words(s: &str) -> impl Iterator<Item = String> + '_ {
+    s.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Build slug candidates for `url`, most descriptive first.  These still need to be checked
+/// against the config and the database.
+pub fn candidates(url: &url::Url) -> Vec<String> {
+    let host = url
+        .host_str()
+        .unwrap_or_default()
+        .split('.')
+        .filter(|p| !HOST_NOISE.contains(p))
+        .flat_map(words)
+        .collect::<Vec<_>>();
+    let site = host.last().cloned();
+
+    // the last couple of path segments are usually the most specific part of a url
+    let path = url
+        .path_segments()
+        .into_iter()
+        .flatten()
+        .flat_map(words)
+        .filter(|w| w.len() > 2 && !w.chars().all(|c| c.is_ascii_digit()))
+        .collect::<Vec<_>>();
+    let tail = path.iter().rev().take(2).rev().cloned().collect::<Vec<_>>();
+
+    let mut rng = rand::thread_rng();
+    let mut random = WORDS.choose_multiple(&mut rng, 4).copied();
+
+    let mut out = Vec::new();
+    if let Some(site) = &site {
+        out.push(site.clone());
+        if !tail.is_empty() {
+            out.push(format!("{}-{}", site, tail.join("-")));
+        }
+    }
+    if !tail.is_empty() {
+        out.push(tail.join("-"));
+    }
+    for word in random.by_ref().take(2) {
+        match &site {
+            Some(site) => out.push(format!("{}-{}", site, word)),
+            None => out.push(word.to_string()),
+        }
+    }
+    if let Some(last) = tail.last() {
+        out.extend(random.map(|word| format!("{}-{}", word, last)));
+    }
+
+    out.dedup();
+    out
+}
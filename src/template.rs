@@ -0,0 +1,70 @@
+//! Links whose url has placeholders (`https://tracker.example/ticket/{1}`) that are filled from
+//! the path segments after the slug, so that `/t/1234` goes to ticket 1234.
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use crate::error::UrlErr;
+
+/// The most placeholders a url may have, `{1}` to `{9}`
+pub const MAX_ARGS: usize = 9;
+
+/// Everything but the unreserved characters, so that an argument can't add path segments or
+/// query parameters of its own
+const ARG: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// How many arguments `url` takes, which is its highest placeholder
+pub fn arg_count(url: &str) -> usize {
+    (1..=MAX_ARGS)
+        .rev()
+        .find(|n| url.contains(&placeholder(*n)))
+        .unwrap_or(0)
+}
+
+fn placeholder(n: usize) -> String {
+    format!("{{{}}}", n)
+}
+
+/// Fill the placeholders in `url`, if there are as many `args` as it takes
+pub fn fill(url: &str, args: &[String]) -> Option<String> {
+    if args.len() != arg_count(url) {
+        return None;
+    }
+    let filled = args
+        .iter()
+        .enumerate()
+        .fold(url.to_string(), |url, (i, arg)| {
+            let arg = utf8_percent_encode(arg, ARG).to_string();
+            url.replace(&placeholder(i + 1), &arg)
+        });
+    Some(filled)
+}
+
+/// Check a url's placeholders: they have to be numbered from `{1}` without gaps, and can't be
+/// in the scheme or host, since that would let the link go anywhere
+pub fn validate(url: &str) -> Result<(), UrlErr> {
+    let count = arg_count(url);
+    if count == 0 {
+        return Ok(());
+    }
+    if (1..count).any(|n| !url.contains(&placeholder(n))) {
+        return Err(UrlErr::InvalidTemplate);
+    }
+
+    let origin = |arg: &str| {
+        let url = fill(url, &vec![arg.to_string(); count])?;
+        let url = url::Url::parse(&url).ok()?;
+        Some((
+            url.scheme().to_string(),
+            url.host_str()?.to_string(),
+            url.port(),
+        ))
+    };
+    match (origin("a"), origin("b")) {
+        (Some(a), Some(b)) if a == b => Ok(()),
+        _ => Err(UrlErr::InvalidTemplate),
+    }
+}
@@ -0,0 +1,79 @@
+//! What visitors get when a link can't be followed right now, because it was disabled or has
+//! been followed too often.  Its creator can leave a message for them, or a url to send them to
+//! instead, in place of the usual error.
+
+use axum::{
+    http::{header, HeaderMap, HeaderValue},
+    response::{Html, IntoResponse, Response},
+};
+
+use crate::{
+    cache::Target,
+    config::Config,
+    destination,
+    error::{Problem, UrlErr},
+    html, locale, send_to,
+};
+
+const TEMPLATE: &str = include_str!("unavailable.html");
+
+/// The longest message, in characters
+const MAX_MESSAGE: usize = 1000;
+
+/// Check a link's message and url for when it is unavailable, like the link itself is checked
+pub fn validate(message: Option<&str>, url: Option<&str>, config: &Config) -> Result<(), UrlErr> {
+    if message.is_some_and(|m| m.chars().count() > MAX_MESSAGE) {
+        return Err(UrlErr::InvalidMessage);
+    }
+    if let Some(url) = url {
+        let url = url::Url::parse(url).map_err(UrlErr::InvalidUrl)?;
+        destination::check_scheme(&url, config)?;
+        if destination::is_blocked(&url, config) {
+            return Err(UrlErr::BlockedDestination);
+        }
+    }
+    Ok(())
+}
+
+/// Whether the request came from a browser, rather than an api client
+fn wants_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"))
+}
+
+/// Answer for `target` that it can't be followed because of `err`: with its url if it has one,
+/// or else with its message.  Links with neither get `err` as usual.
+pub fn respond(
+    err: UrlErr,
+    target: &Target,
+    headers: &HeaderMap,
+    config: &Config,
+) -> Result<Response, UrlErr> {
+    // the link may be followed again later, so this mustn't stick
+    let no_store =
+        HeaderMap::from_iter([(header::CACHE_CONTROL, HeaderValue::from_static("no-store"))]);
+    if let Some(url) = &target.unavailable_url {
+        // the scheme may have been allowed when it was set, but not anymore
+        if let Ok(res) = send_to(no_store.clone(), url, config) {
+            return Ok(res);
+        }
+    }
+    let Some(message) = &target.unavailable_message else {
+        return Err(err);
+    };
+
+    let vary = [(header::VARY, "accept, accept-language")];
+    if !wants_html(headers) {
+        let mut problem = Problem::from(&err);
+        problem.detail = message.clone();
+        return Ok((no_store, vary, problem).into_response());
+    }
+    let title = err.title();
+    let page = TEMPLATE
+        .replace("{lang}", &locale::current())
+        .replace("{title}", &html::escape(&title))
+        .replace("{message}", &html::escape(message));
+    Ok((err.status(), no_store, vary, Html(page)).into_response())
+}
@@ -0,0 +1,739 @@
+//! Link previews in chat apps.  When an unfurl bot (Slackbot, Twitterbot, Discord's and so on)
+//! asks for a slug, it gets a small page with the destination's OpenGraph tags instead of a
+//! redirect, and isn't counted as a click.  The tags are read from the destination itself and
+//! kept in memory for a while.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    http::header,
+    response::{Html, IntoResponse, Response},
+};
+use serde::Deserialize;
+use tracing::{debug, warn};
+use url::Host;
+
+use crate::{config::Config, destination, html::escape, ip, template};
+
+const TEMPLATE: &str = include_str!("unfurl.html");
+
+/// Sent when reading a destination, some sites only send their tags to bots they recognise
+const USER_AGENT: &str = concat!(
+    "url-shortener/",
+    env!("CARGO_PKG_VERSION"),
+    " (link preview)"
+);
+
+/// How many redirects are followed from a destination, each is checked like the first
+const MAX_REDIRECTS: usize = 5;
+
+/// The longest title that is passed on, in characters
+const MAX_TITLE: usize = 200;
+
+/// The longest description that is passed on, in characters
+const MAX_DESCRIPTION: usize = 500;
+
+/// How many bots have been answered with a page instead of a redirect
+pub static UNFURL_COUNT: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct UnfurlConfig {
+    /// Answer unfurl bots with a page of tags instead of a redirect
+    pub enabled: bool,
+    /// Parts of a `User-Agent` that mark it as an unfurl bot, matched ignoring case
+    pub bots: Vec<String>,
+    /// How long (in seconds) the tags of a destination are kept
+    pub max_age: u64,
+    /// How long (in seconds) to wait for a destination
+    pub timeout: u64,
+    /// The most (in bytes) that is read of a destination, the tags are near the start
+    pub max_size: usize,
+    /// The most destinations whose tags are kept in memory
+    pub capacity: usize,
+}
+
+impl Default for UnfurlConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bots: [
+                "Slackbot",
+                "Twitterbot",
+                "Discordbot",
+                "facebookexternalhit",
+                "LinkedInBot",
+                "TelegramBot",
+                "WhatsApp",
+            ]
+            .map(String::from)
+            .to_vec(),
+            max_age: 60 * 60,
+            timeout: 5,
+            max_size: 256 * 1024,
+            capacity: 1000,
+        }
+    }
+}
+
+/// Whether the `User-Agent` is one of [`UnfurlConfig::bots`], and they are to be answered
+pub fn is_bot(user_agent: Option<&str>, config: &UnfurlConfig) -> bool {
+    let Some(user_agent) = user_agent.filter(|_| config.enabled) else {
+        return false;
+    };
+    let user_agent = user_agent.to_lowercase();
+    config
+        .bots
+        .iter()
+        .any(|bot| user_agent.contains(&bot.to_lowercase()))
+}
+
+/// What a destination says about itself
+#[derive(Debug, Clone, Default)]
+struct Tags {
+    title: Option<String>,
+    description: Option<String>,
+    image: Option<String>,
+    site_name: Option<String>,
+}
+
+/// The tags of destinations that bots have asked about recently
+#[derive(Debug, Default)]
+pub struct Unfurls {
+    tags: Mutex<HashMap<String, (Instant, Tags)>>,
+}
+
+impl Unfurls {
+    async fn tags(&self, url: &str, config: &UnfurlConfig) -> Tags {
+        let max_age = Duration::from_secs(config.max_age);
+        if let Some((at, tags)) = self.tags.lock().unwrap().get(url) {
+            if at.elapsed() < max_age {
+                return tags.clone();
+            }
+        }
+
+        let tags = match fetch(url, config).await {
+            Ok(tags) => {
+                debug!("Read the tags of {}", url);
+                tags
+            }
+            Err(err) => {
+                // kept anyway, so that every bot doesn't wait on a site that is down
+                warn!("Unable to read the tags of {}: {}", url, err);
+                Tags::default()
+            }
+        };
+        let mut cached = self.tags.lock().unwrap();
+        if cached.len() >= config.capacity {
+            cached.clear();
+        }
+        cached.insert(url.to_string(), (Instant::now(), tags.clone()));
+        tags
+    }
+}
+
+/// Addresses that a destination mustn't resolve to, so that bots can't be used to read the
+/// server's own network
+pub(crate) fn is_public(ip: IpAddr) -> bool {
+    match ip::canonical(ip) {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            // "this network", which some systems connect to as the host itself
+            let this_network = a == 0;
+            let shared = a == 100 && (64..128).contains(&b);
+            let benchmarking = a == 198 && b & 0xfe == 18;
+            // reserved for future use, along with the broadcast address
+            let reserved = a >= 240;
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || this_network
+                || shared
+                || benchmarking
+                || reserved)
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            let unique_local = segments[0] & 0xfe00 == 0xfc00;
+            let link_local = segments[0] & 0xffc0 == 0xfe80;
+            let documentation = segments[0] == 0x2001 && segments[1] == 0x0db8;
+            // gateways reach the IPv4 address inside these, so it has to be public too
+            let embedded = match segments {
+                // NAT64, the local-use prefix after it is never public
+                [0x64, 0xff9b, 0, 0, 0, 0, high, low] => Some((high, low)),
+                [0x64, 0xff9b, 1, ..] => return false,
+                // 6to4
+                [0x2002, high, low, ..] => Some((high, low)),
+                _ => None,
+            };
+            if let Some((high, low)) = embedded {
+                let v4 = Ipv4Addr::from((u32::from(high) << 16) | u32::from(low));
+                return is_public(v4.into());
+            }
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || unique_local
+                || link_local
+                || documentation)
+        }
+    }
+}
+
+/// Where to connect for `url`, if every address it resolves to is public
+pub(crate) async fn public_addr(url: &url::Url) -> Result<SocketAddr, String> {
+    let port = url.port_or_known_default().ok_or("the url has no port")?;
+    let addrs = match url.host() {
+        Some(Host::Ipv4(ip)) => vec![SocketAddr::new(ip.into(), port)],
+        Some(Host::Ipv6(ip)) => vec![SocketAddr::new(ip.into(), port)],
+        Some(Host::Domain(domain)) => tokio::net::lookup_host((domain, port))
+            .await
+            .map_err(|e| e.to_string())?
+            .collect(),
+        None => return Err("the url has no host".into()),
+    };
+    if addrs.iter().any(|addr| !is_public(addr.ip())) {
+        return Err("the url points at a private address".into());
+    }
+    addrs
+        .into_iter()
+        .next()
+        .ok_or_else(|| "the host has no addresses".into())
+}
+
+/// Read the start of the page at `url`, following redirects to other public pages
+async fn fetch(url: &str, config: &UnfurlConfig) -> Result<Tags, String> {
+    let mut url = url::Url::parse(url).map_err(|e| e.to_string())?;
+    for _ in 0..=MAX_REDIRECTS {
+        let addr = public_addr(&url).await?;
+        let host = url.host_str().ok_or("the url has no host")?;
+        // connect to the address that was checked, rather than resolving it again
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .redirect(reqwest::redirect::Policy::none())
+            .resolve(host, addr)
+            .user_agent(USER_AGENT)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let mut res = client
+            .get(url.clone())
+            .header(header::ACCEPT, "text/html")
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if res.status().is_redirection() {
+            let location = res
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .ok_or("a redirect without a location")?;
+            url = url.join(location).map_err(|e| e.to_string())?;
+            if !destination::is_web(&url) {
+                return Err("a redirect away from the web".into());
+            }
+            continue;
+        }
+        if !res.status().is_success() {
+            return Err(format!("the destination answered {}", res.status()));
+        }
+        let is_html = res
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|ct| ct.starts_with("text/html"));
+        if !is_html {
+            // images and the like have nothing to read, the defaults will do
+            return Ok(Tags::default());
+        }
+
+        let mut page = Vec::new();
+        while let Some(chunk) = res.chunk().await.map_err(|e| e.to_string())? {
+            page.extend_from_slice(&chunk);
+            if page.len() >= config.max_size {
+                page.truncate(config.max_size);
+                break;
+            }
+        }
+        return Ok(parse(&String::from_utf8_lossy(&page), &url));
+    }
+    Err("too many redirects".into())
+}
+
+/// The tags in the `<head>` of `page`, which was found at `url`
+fn parse(page: &str, url: &url::Url) -> Tags {
+    // only ascii is lowered, so offsets into it are offsets into `page`
+    let lower = page.to_ascii_lowercase();
+    let head = &lower[..lower.find("</head").unwrap_or(lower.len())];
+
+    let mut meta = HashMap::new();
+    let mut rest = 0;
+    while let Some(start) = head[rest..].find("<meta").map(|i| rest + i) {
+        let end = tag_end(&head[start..]).map_or(head.len(), |i| start + i);
+        let attributes = attributes(&page[start + "<meta".len()..end]);
+        let key = attributes
+            .iter()
+            .find(|(name, _)| name == "property" || name == "name")
+            .map(|(_, value)| value.to_lowercase());
+        let content = attributes.iter().find(|(name, _)| name == "content");
+        if let (Some(key), Some((_, content))) = (key, content) {
+            meta.entry(key).or_insert_with(|| content.clone());
+        }
+        rest = end;
+    }
+    let first = |keys: &[&str]| {
+        keys.iter()
+            .find_map(|key| meta.get(*key))
+            .map(|value| clean(value))
+            .filter(|value| !value.is_empty())
+    };
+
+    let title_tag = head.find("<title").and_then(|start| {
+        let text = start + head[start..].find('>')? + 1;
+        let end = text + head[text..].find("</title")?;
+        Some(clean(&unescape(&page[text..end])))
+    });
+    let image = first(&["og:image", "og:image:url", "twitter:image"])
+        .and_then(|image| url.join(&image).ok())
+        .filter(destination::is_web);
+
+    Tags {
+        title: first(&["og:title", "twitter:title"])
+            .or(title_tag.filter(|t| !t.is_empty()))
+            .map(|t| truncate(&t, MAX_TITLE)),
+        description: first(&["og:description", "twitter:description", "description"])
+            .map(|d| truncate(&d, MAX_DESCRIPTION)),
+        image: image.map(String::from),
+        site_name: first(&["og:site_name"]).map(|s| truncate(&s, MAX_TITLE)),
+    }
+}
+
+/// Where the tag at the start of `tag` ends (its `>`), which may also be in a quoted value
+fn tag_end(tag: &str) -> Option<usize> {
+    let mut chars = tag.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '>' => return Some(i),
+            // quotes only start a value straight after the `=`
+            '=' => {
+                while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+                if let Some((_, quote @ ('"' | '\''))) = chars.peek().copied() {
+                    chars.next();
+                    chars.find(|&(_, c)| c == quote)?;
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The attributes of a tag, from just after its name up to (not including) the `>`
+fn attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = tag.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+    while !rest.is_empty() {
+        let name_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '/')
+            .unwrap_or(rest.len());
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+
+        let mut value = String::new();
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (raw, remaining) = match after.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let inner = &after[1..];
+                    let end = inner.find(quote).unwrap_or(inner.len());
+                    (&inner[..end], inner.get(end + 1..).unwrap_or(""))
+                }
+                _ => {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            value = unescape(raw);
+            rest = remaining;
+        }
+        if !name.is_empty() {
+            attributes.push((name, value));
+        }
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+    }
+    attributes
+}
+
+/// Decode the character references in `text`, leaving anything unknown as it was
+fn unescape(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let reference = rest
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((reference(&rest[1..end])?, end)));
+        match reference {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// The character for a reference like `amp` or `#39`, without the `&` and `;`
+fn reference(name: &str) -> Option<char> {
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        _ => {
+            let number = name.strip_prefix('#')?;
+            let code = match number.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => number.parse().ok()?,
+            };
+            char::from_u32(code)?
+        }
+    };
+    Some(c)
+}
+
+/// Collapse the whitespace in `text`, which is often spread over lines in the page
+fn clean(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn truncate(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// The page for a bot asking about `slug`, which goes to `url`.  Only web urls have tags, so
+/// bots asking about anything else are sent on like everyone else.
+pub async fn page(unfurls: &Unfurls, slug: &str, url: &str, config: &Config) -> Option<Response> {
+    let parsed = url::Url::parse(url).ok()?;
+    if !destination::is_web(&parsed) {
+        return None;
+    }
+    UNFURL_COUNT.fetch_add(1, Ordering::Relaxed);
+    let tags = unfurls.tags(url, &config.unfurl).await;
+
+    let title = tags
+        .title
+        .or_else(|| parsed.host_str().map(String::from))
+        .unwrap_or_else(|| url.to_string());
+    // the screenshot of the page is the next best thing, though bots need a full url for it
+    let preview = (config.preview.service_url.is_some()
+        && config.base_url.is_some()
+        && template::arg_count(url) == 0)
+        .then(|| format!("{}/{}/preview.png", config.base_url(), slug));
+    let embeddable = preview.is_some();
+    let image = tags.image.or(preview);
+
+    let mut extra = Vec::new();
+    // OpenGraph uses `property`, Twitter's own tags `name`
+    let mut tag = |attribute: &str, key: &str, content: &str| {
+        extra.push(format!(
+            "<meta {}=\"{}\" content=\"{}\">",
+            attribute,
+            key,
+            escape(content)
+        ));
+    };
+    if let Some(description) = &tags.description {
+        tag("property", "og:description", description);
+    }
+    if let Some(site_name) = &tags.site_name {
+        tag("property", "og:site_name", site_name);
+    }
+    match &image {
+        Some(image) => {
+            tag("property", "og:image", image);
+            tag("name", "twitter:card", "summary_large_image");
+        }
+        None => tag("name", "twitter:card", "summary"),
+    }
+    if embeddable {
+        let link = format!("{}/{}", config.base_url(), slug);
+        let link = url::form_urlencoded::byte_serialize(link.as_bytes()).collect::<String>();
+        extra.push(format!(
+            "<link rel=\"alternate\" type=\"application/json+oembed\" href=\"{}\">",
+            escape(&format!("{}/oembed?url={}", config.base_url(), link))
+        ));
+    }
+
+    let page = TEMPLATE
+        .replace("{title}", &escape(&title))
+        .replace("{tags}", &extra.join("\n"))
+        .replace("{url}", &escape(url));
+    // the page is only for bots, so it mustn't be cached for anyone else
+    let headers = [(header::CACHE_CONTROL, "no-store")];
+    Some((headers, Html(page)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_at(page: &str) -> Tags {
+        parse(
+            page,
+            &url::Url::parse("https://example.com/blog/post").unwrap(),
+        )
+    }
+
+    fn public(ip: &str) -> bool {
+        is_public(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn opengraph_tags() {
+        let tags = parse_at(
+            r#"<html><head>
+            <title>Fallback</title>
+            <meta property="og:title" content="The title">
+            <meta name="description" content="Plain description">
+            <meta property="og:description" content="OpenGraph description">
+            <meta property="og:image" content="/images/cover.png">
+            <meta property="og:site_name" content="Example">
+            </head></html>"#,
+        );
+        assert_eq!(tags.title.as_deref(), Some("The title"));
+        assert_eq!(tags.description.as_deref(), Some("OpenGraph description"));
+        assert_eq!(
+            tags.image.as_deref(),
+            Some("https://example.com/images/cover.png")
+        );
+        assert_eq!(tags.site_name.as_deref(), Some("Example"));
+    }
+
+    #[test]
+    fn title_fallback() {
+        let tags = parse_at("<head><TITLE>\n  Just   a\n title </TITLE></head>");
+        assert_eq!(tags.title.as_deref(), Some("Just a title"));
+
+        // an empty og:title doesn't hide the title
+        let tags = parse_at(r#"<head><meta property="og:title" content=" "><title>T</title>"#);
+        assert_eq!(tags.title.as_deref(), Some("T"));
+    }
+
+    #[test]
+    fn only_the_head() {
+        let tags = parse_at(
+            r#"<head><meta property="og:title" content="Head"></HEAD>
+            <body><meta property="og:description" content="Body"><title>Body</title>"#,
+        );
+        assert_eq!(tags.title.as_deref(), Some("Head"));
+        assert_eq!(tags.description, None);
+
+        // a page cut off before its head ends is read as far as it goes
+        let tags = parse_at(r#"<head><meta property="og:title" content="Cut"#);
+        assert_eq!(tags.title.as_deref(), Some("Cut"));
+    }
+
+    #[test]
+    fn first_tag_wins() {
+        let tags = parse_at(
+            r#"<meta property="og:title" content="First">
+            <meta property="og:title" content="Second">"#,
+        );
+        assert_eq!(tags.title.as_deref(), Some("First"));
+    }
+
+    #[test]
+    fn case_and_quoting() {
+        let tags = parse_at(
+            r#"<META PROPERTY='OG:TITLE' CONTENT='Single "quoted"'>
+            <meta name=description content=unquoted />
+            <meta property = "og:site_name" content = "Spaced" />"#,
+        );
+        assert_eq!(tags.title.as_deref(), Some(r#"Single "quoted""#));
+        assert_eq!(tags.description.as_deref(), Some("unquoted"));
+        assert_eq!(tags.site_name.as_deref(), Some("Spaced"));
+    }
+
+    #[test]
+    fn quotes_in_tags() {
+        let tags = parse_at(
+            r#"<meta content="a > b" property="og:title">
+            <meta name=description content='it's'>"#,
+        );
+        assert_eq!(tags.title.as_deref(), Some("a > b"));
+        // a value ends at the first matching quote, as it does for a browser
+        assert_eq!(tags.description.as_deref(), Some("it"));
+
+        // an apostrophe in an unquoted value doesn't start a quote
+        let tags = parse_at(
+            r#"<meta property=og:title content=it's>
+            <meta property="og:description" content="next">"#,
+        );
+        assert_eq!(tags.title.as_deref(), Some("it's"));
+        assert_eq!(tags.description.as_deref(), Some("next"));
+    }
+
+    #[test]
+    fn entities() {
+        let tags = parse_at(
+            r#"<meta property="og:title" content="Tom &amp; Jerry&#39;s &quot;show&quot;">
+            <title>&lt;b&gt; &#x1F600;</title>"#,
+        );
+        assert_eq!(tags.title.as_deref(), Some(r#"Tom & Jerry's "show""#));
+
+        let tags = parse_at("<title>&lt;b&gt; &#x1F600;</title>");
+        assert_eq!(tags.title.as_deref(), Some("<b> 😀"));
+    }
+
+    #[test]
+    fn images() {
+        let image = |content: &str| {
+            let page = format!(r#"<meta property="og:image" content="{}">"#, content);
+            parse_at(&page).image
+        };
+        assert_eq!(
+            image("cover.png").as_deref(),
+            Some("https://example.com/blog/cover.png")
+        );
+        assert_eq!(
+            image("//cdn.example.net/a.png").as_deref(),
+            Some("https://cdn.example.net/a.png")
+        );
+        assert_eq!(image("javascript:alert(1)"), None);
+        assert_eq!(image("data:image/png;base64,AAAA"), None);
+    }
+
+    #[test]
+    fn long_values_are_cut() {
+        let title = "a".repeat(MAX_TITLE + 10);
+        let page = format!(r#"<meta property="og:title" content="{}">"#, title);
+        let cut = parse_at(&page).title.unwrap();
+        assert_eq!(cut.chars().count(), MAX_TITLE + 1);
+        assert!(cut.ends_with('…'));
+    }
+
+    #[test]
+    fn attribute_forms() {
+        let pairs = |tag: &str| {
+            attributes(tag)
+                .into_iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(pairs(r#" a="1" B='2' c=3 d"#), ["a=1", "b=2", "c=3", "d="]);
+        assert_eq!(pairs(" a = \"x y\" /"), ["a=x y"]);
+        assert_eq!(pairs(r#" a="&amp;&#x41;""#), ["a=&A"]);
+        // an unterminated quote runs to the end
+        assert_eq!(pairs(r#" a="open b=2"#), ["a=open b=2"]);
+        assert!(pairs("").is_empty());
+    }
+
+    #[test]
+    fn unescaping() {
+        assert_eq!(unescape("a &amp; b"), "a & b");
+        assert_eq!(unescape("&#65;&#x42;&#X43;"), "ABC");
+        assert_eq!(unescape("&nbsp;&apos;"), " '");
+        // only decoded once
+        assert_eq!(unescape("&amp;lt;"), "&lt;");
+        // anything unknown is left as it was
+        assert_eq!(unescape("&unknown; & &;"), "&unknown; & &;");
+        assert_eq!(unescape("AT&T"), "AT&T");
+        assert_eq!(unescape("&#xD800; &#99999999;"), "&#xD800; &#99999999;");
+        assert_eq!(unescape("&averyverylongname;"), "&averyverylongname;");
+        assert_eq!(unescape("trailing &"), "trailing &");
+    }
+
+    #[test]
+    fn public_addresses() {
+        for ip in [
+            "93.184.216.34",
+            "8.8.8.8",
+            "2606:2800:220:1:248:1893:25c8:1946",
+            "::ffff:93.184.216.34",
+            "64:ff9b::5db8:d822",
+            "2002:5db8:d822::1",
+        ] {
+            assert!(public(ip), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn private_addresses() {
+        for ip in [
+            // this network
+            "0.0.0.0",
+            "0.1.2.3",
+            "10.0.0.1",
+            "100.64.0.1",
+            "100.127.255.254",
+            "127.0.0.1",
+            "169.254.169.254",
+            "172.16.0.1",
+            "172.31.255.255",
+            "192.168.1.1",
+            // documentation
+            "192.0.2.1",
+            "198.51.100.1",
+            "203.0.113.1",
+            // benchmarking
+            "198.18.0.1",
+            "198.19.255.255",
+            "224.0.0.1",
+            // reserved, and broadcast
+            "240.0.0.1",
+            "255.255.255.255",
+            "::",
+            "::1",
+            "fc00::1",
+            "fd12:3456::1",
+            "fe80::1",
+            "ff02::1",
+            "2001:db8::1",
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+            // NAT64 of private addresses, and the local-use prefix
+            "64:ff9b::7f00:1",
+            "64:ff9b::a9fe:a9fe",
+            "64:ff9b:1::5db8:d822",
+            // 6to4 of private addresses
+            "2002:7f00:1::1",
+            "2002:c0a8:101::1",
+        ] {
+            assert!(!public(ip), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn edges_of_ranges() {
+        assert!(public("100.63.255.255"));
+        assert!(public("100.128.0.0"));
+        assert!(public("198.17.255.255"));
+        assert!(public("198.20.0.0"));
+        assert!(!public("239.255.255.255"));
+        assert!(public("1.0.0.0"));
+        assert!(public("223.255.255.255"));
+    }
+}
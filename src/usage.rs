@@ -0,0 +1,643 @@
+//! Usage counts are tallied in memory and written in batches, so that a link getting thousands
+//! of clicks a second costs one `UPDATE` per flush rather than one per click.  The clicks recorded
+//! for [`crate::analytics`] are held and written with them.
+//!
+//! Redirects only put an event on a bounded queue, a single task takes them off it into the
+//! tally and does all the writing, so a slow database never holds up a redirect.  Corrections
+//! to a usage count go through the same queue, so that they can't race a flush.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use rand::Rng;
+use serde::Deserialize;
+use tokio::{
+    sync::{
+        mpsc::{self, error::TrySendError},
+        oneshot,
+    },
+    time::MissedTickBehavior,
+};
+use tracing::warn;
+
+use crate::{
+    audit::{self, AuditAction},
+    config::LiveConfig,
+    db,
+    error::UrlErr,
+    mail::{Notification, Notifier},
+    models::{NewClick, Url},
+    schema::{clicks, urls},
+    slug,
+};
+
+/// How many clicks have been dropped because the queue was full, or too many were held in
+/// read-only mode, for `/metrics`
+pub static DROPPED_COUNT: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct UsageConfig {
+    /// How often (in milliseconds) the tally is written to the database, `0` writes clicks as
+    /// soon as they are taken off the queue
+    pub flush_interval: u64,
+    /// Write the tally early once it holds this many clicks
+    pub batch_size: usize,
+    /// How many clicks may wait on the queue for the writer
+    pub queue_size: usize,
+    /// What a redirect does when the queue is full
+    pub when_full: WhenFull,
+    /// How many independently locked maps the tally is split over, so that clicks on the same
+    /// link don't all wait on one lock
+    pub shards: usize,
+    /// How many clicks are held in memory while read-only mode stops them being written, any
+    /// more are dropped
+    pub max_held: usize,
+}
+
+/// What happens to a click when the queue is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WhenFull {
+    /// Don't count it, so redirects are never slowed down
+    #[default]
+    Drop,
+    /// Hold the redirect until there's room, so every click is counted
+    Wait,
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        Self {
+            flush_interval: 1000,
+            batch_size: 1000,
+            queue_size: 10_000,
+            when_full: WhenFull::default(),
+            shards: 16,
+            max_held: 100_000,
+        }
+    }
+}
+
+/// Something waiting on the queue
+#[derive(Debug)]
+pub enum UsageEvent {
+    Click {
+        slug: String,
+        click: Option<NewClick>,
+    },
+    Adjust(Adjust),
+}
+
+/// How to correct a link's usage count
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum UsageAdjustment {
+    /// Start counting from zero again
+    Reset,
+    /// Take off clicks that shouldn't have counted, such as a scraper's
+    Subtract { amount: u32 },
+}
+
+/// A correction waiting on the queue, answered with the link once it is made
+#[derive(Debug)]
+pub struct Adjust {
+    slug: String,
+    case_insensitive: bool,
+    adjustment: UsageAdjustment,
+    reply: oneshot::Sender<Result<Url, UrlErr>>,
+}
+
+/// Where redirects put their clicks for [`flush_every`] to write
+#[derive(Debug, Clone)]
+pub struct UsageQueue {
+    sender: mpsc::Sender<UsageEvent>,
+    when_full: WhenFull,
+}
+
+impl UsageQueue {
+    pub fn new(config: &UsageConfig) -> (Self, mpsc::Receiver<UsageEvent>) {
+        let (sender, receiver) = mpsc::channel(config.queue_size.max(1));
+        let queue = Self {
+            sender,
+            when_full: config.when_full,
+        };
+        (queue, receiver)
+    }
+
+    /// Count a click on `slug`, along with the click itself if it is being recorded
+    pub async fn push(&self, slug: String, click: Option<NewClick>) {
+        let event = UsageEvent::Click { slug, click };
+        let sent = match self.when_full {
+            WhenFull::Drop => match self.sender.try_send(event) {
+                Err(TrySendError::Full(_)) => {
+                    DROPPED_COUNT.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                sent => sent.is_ok(),
+            },
+            WhenFull::Wait => self.sender.send(event).await.is_ok(),
+        };
+        if !sent {
+            warn!("The usage writer has stopped, a click was lost");
+        }
+    }
+
+    /// Correct the usage count of `slug` once every click queued before now has been counted,
+    /// returning the link.  This waits for room on the queue however full it is.
+    pub async fn adjust(
+        &self,
+        slug: String,
+        case_insensitive: bool,
+        adjustment: UsageAdjustment,
+    ) -> Result<Url, UrlErr> {
+        let (reply, answer) = oneshot::channel();
+        let event = UsageEvent::Adjust(Adjust {
+            slug,
+            case_insensitive,
+            adjustment,
+            reply,
+        });
+        if self.sender.send(event).await.is_err() {
+            warn!("The usage writer has stopped, a usage count can't be corrected");
+            return Err(UrlErr::DBError);
+        }
+        answer.await.map_err(|_| UrlErr::DBError)?
+    }
+
+    /// How many clicks are waiting to be taken off the queue
+    pub fn len(&self) -> usize {
+        self.sender.max_capacity() - self.sender.capacity()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Clicks that haven't been written to the database yet
+#[derive(Debug)]
+pub struct UsageTally {
+    shards: Vec<Mutex<HashMap<String, i32>>>,
+    clicks: Mutex<Vec<NewClick>>,
+    /// Goes up with every click, so that responses including pending counts can tell whether
+    /// they changed without adding them all up
+    version: AtomicU64,
+}
+
+impl UsageTally {
+    pub fn new(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1)).map(|_| Mutex::default()).collect(),
+            clicks: Mutex::default(),
+            version: AtomicU64::new(0),
+        }
+    }
+
+    /// Count a click, on a random shard so that a hot slug is spread across all of them
+    pub fn add(&self, slug: &str, count: i32) {
+        self.version.fetch_add(1, Ordering::Relaxed);
+        let shard = rand::thread_rng().gen_range(0..self.shards.len());
+        *self.shards[shard]
+            .lock()
+            .unwrap()
+            .entry(slug.to_string())
+            .or_default() += count;
+    }
+
+    /// Hold a click to be written with the next flush
+    pub fn record(&self, click: NewClick) {
+        self.clicks.lock().unwrap().push(click);
+    }
+
+    fn push(&self, slug: &str, click: Option<NewClick>) {
+        self.add(slug, 1);
+        if let Some(click) = click {
+            self.record(click);
+        }
+    }
+
+    pub fn version(&self) -> u64 {
+        AtomicU64::load(&self.version, Ordering::Relaxed)
+    }
+
+    /// Clicks on `slug` that are still waiting to be written
+    pub fn pending(&self, slug: &str) -> i32 {
+        self.shards
+            .iter()
+            .filter_map(|shard| shard.lock().unwrap().get(slug).copied())
+            .sum()
+    }
+
+    /// Drop the clicks on `slug` that are waiting to be written, returning how many there were
+    fn discard(&self, slug: &str) -> i32 {
+        self.shards
+            .iter()
+            .filter_map(|shard| shard.lock().unwrap().remove(slug))
+            .sum()
+    }
+
+    /// Empty every shard, returning the total for each slug
+    fn take(&self) -> HashMap<String, i32> {
+        let mut totals = HashMap::new();
+        for shard in &self.shards {
+            for (slug, count) in shard.lock().unwrap().drain() {
+                *totals.entry(slug).or_default() += count;
+            }
+        }
+        totals
+    }
+}
+
+/// An email to send once the counts are written
+type Pending = (String, Notification);
+
+/// Add `count` to the usage count of `slug`, returning the email to send if this took it past a
+/// milestone
+async fn bump(
+    conn: &mut db::Connection,
+    slug: &str,
+    count: i32,
+    notifier: Option<&Notifier>,
+) -> QueryResult<Option<Pending>> {
+    // only worth reading when there's a way to tell the owner
+    let before = match notifier {
+        Some(_) => urls::table
+            .find(slug)
+            .select((urls::usage_count, urls::url, urls::owner_email))
+            .first::<(i32, String, Option<String>)>(conn)
+            .await
+            .optional()?,
+        None => None,
+    };
+
+    diesel::update(urls::table.find(slug))
+        .set(urls::usage_count.eq(urls::usage_count + count))
+        .execute(conn)
+        .await?;
+
+    Ok(before.and_then(|(old, url, owner)| {
+        let clicks = notifier?.milestone(old, old.saturating_add(count))?;
+        let notification = Notification::Milestone {
+            slug: slug.to_string(),
+            url,
+            clicks,
+        };
+        Some((owner?, notification))
+    }))
+}
+
+fn notify(notifier: Option<&Arc<Notifier>>, pending: Vec<Pending>) {
+    if let Some(notifier) = notifier {
+        for (to, notification) in pending {
+            notifier.send(to, notification);
+        }
+    }
+}
+
+/// Write the tally to the database, putting it back if that fails so that the next flush
+/// retries it
+async fn flush(tally: &UsageTally, pool: &db::Pool, notifier: Option<&Arc<Notifier>>) {
+    let totals = tally.take();
+    let clicks = std::mem::take(&mut *tally.clicks.lock().unwrap());
+    if totals.is_empty() && clicks.is_empty() {
+        return;
+    }
+
+    let result = async {
+        let mut conn = db::get(pool).await?;
+        conn.transaction(|conn| {
+            async {
+                let mut pending = Vec::new();
+                for (slug, count) in &totals {
+                    pending.extend(bump(conn, slug, *count, notifier.map(Arc::as_ref)).await?);
+                }
+                // one at a time, diesel only batches inserts into SQLite on its own connection
+                for click in &clicks {
+                    diesel::insert_into(clicks::table)
+                        .values(click)
+                        .execute(conn)
+                        .await?;
+                }
+                Ok::<_, UrlErr>(pending)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+    .await;
+
+    if let Ok(pending) = result {
+        notify(notifier, pending);
+    } else {
+        warn!("Unable to write usage counts, retrying next flush");
+        for (slug, count) in totals {
+            tally.add(&slug, count);
+        }
+        tally.clicks.lock().unwrap().extend(clicks);
+    }
+}
+
+/// Make a correction to a usage count, with the tally just flushed.  Clicks that couldn't be
+/// written are part of what is being corrected, so they are dropped once it has been.
+async fn adjust(tally: &UsageTally, pool: &db::Pool, adjust: Adjust) {
+    let Adjust {
+        slug,
+        case_insensitive,
+        adjustment,
+        reply,
+    } = adjust;
+
+    let result = async {
+        let mut conn = db::get(pool).await?;
+        conn.immediate_transaction(|conn| {
+            async move {
+                let url = urls::table
+                    .filter(slug::eq(slug, case_insensitive))
+                    .first::<Url>(conn)
+                    .await
+                    .optional()?
+                    .ok_or(UrlErr::NotFound)?;
+
+                let old = url.usage_count.saturating_add(tally.pending(&url.slug));
+                let (new, detail) = match adjustment {
+                    UsageAdjustment::Reset => (0, format!("reset from {}", old)),
+                    UsageAdjustment::Subtract { amount } => {
+                        let new = i64::from(old) - i64::from(amount);
+                        let new = i32::try_from(new.max(0)).unwrap_or(i32::MAX);
+                        (new, format!("subtracted {} ({} → {})", amount, old, new))
+                    }
+                };
+
+                diesel::update(urls::table.find(&url.slug))
+                    .set(urls::usage_count.eq(new))
+                    .execute(conn)
+                    .await?;
+                audit::record(
+                    conn,
+                    &url.slug,
+                    AuditAction::AdjustUsage,
+                    audit::ADMIN,
+                    Some(&detail),
+                )
+                .await?;
+                Ok::<_, UrlErr>(urls::table.find(url.slug).first::<Url>(conn).await?)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+    .await;
+
+    // only this task adds to the tally, so nothing has joined what was counted above
+    if let Ok(url) = &result {
+        tally.discard(&url.slug);
+    }
+    // the admin may have given up waiting
+    let _ = reply.send(result);
+}
+
+/// Take clicks off the queue into the tally, and write it to the database every
+/// `flush_interval` or once it holds `batch_size` clicks, until every [`UsageQueue`] is gone.
+/// Anything in the tally when the process stops is lost, as is anything past `max_held` while
+/// read-only mode keeps it from being written.
+pub async fn flush_every(
+    mut events: mpsc::Receiver<UsageEvent>,
+    tally: Arc<UsageTally>,
+    pool: db::Pool,
+    usage: UsageConfig,
+    notifier: Option<Arc<Notifier>>,
+    config: Arc<LiveConfig>,
+) {
+    let interval = Duration::from_millis(usage.flush_interval);
+    let mut ticker = tokio::time::interval(interval.max(Duration::from_millis(1)));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut since_flush = 0;
+    let mut warned = false;
+
+    loop {
+        // a correction stops the batch, so that it is made after the clicks queued before it
+        let mut adjustment = None;
+        let open = tokio::select! {
+            event = events.recv() => match event {
+                Some(event) => {
+                    let mut event = Some(event);
+                    // whatever else is already waiting goes in the same batch
+                    while let Some(next) = event.take() {
+                        match next {
+                            UsageEvent::Click { .. }
+                                if since_flush >= usage.max_held && config.is_read_only() =>
+                            {
+                                DROPPED_COUNT.fetch_add(1, Ordering::Relaxed);
+                                if !warned {
+                                    warn!(
+                                        "Holding {} clicks in read-only mode, dropping any more until it ends",
+                                        since_flush
+                                    );
+                                    warned = true;
+                                }
+                            }
+                            UsageEvent::Click { slug, click } => {
+                                tally.push(&slug, click);
+                                since_flush += 1;
+                            }
+                            UsageEvent::Adjust(adjust) => {
+                                adjustment = Some(adjust);
+                                break;
+                            }
+                        }
+                        if since_flush < usage.batch_size {
+                            event = events.try_recv().ok();
+                        }
+                    }
+                    let full = since_flush >= usage.batch_size;
+                    if adjustment.is_none() && !interval.is_zero() && !full {
+                        continue;
+                    }
+                    true
+                }
+                None => false,
+            },
+            _ = ticker.tick(), if !interval.is_zero() => true,
+        };
+
+        // held in memory until the database can be written to again
+        if !config.is_read_only() {
+            flush(&tally, &pool, notifier.as_ref()).await;
+            since_flush = 0;
+            warned = false;
+        }
+        if let Some(adjustment) = adjustment {
+            if config.is_read_only() {
+                let _ = adjustment.reply.send(Err(UrlErr::ReadOnly));
+            } else {
+                adjust(&tally, &pool, adjustment).await;
+            }
+        }
+        if !open {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::sql_query;
+
+    use super::*;
+    use crate::db::PoolConfig;
+
+    fn click(slug: &str) -> NewClick {
+        NewClick {
+            slug: slug.into(),
+            clicked_at: 0,
+            referrer: None,
+            user_agent: None,
+            ip: None,
+            country: None,
+        }
+    }
+
+    /// A fresh database holding `abc`, which has been used 10 times
+    async fn pool() -> db::Pool {
+        let pool = db::write_pool(":memory:", &PoolConfig::default());
+        db::run_migrations(&pool).await.unwrap();
+        let mut conn = db::get(&pool).await.unwrap();
+        sql_query(
+            "INSERT INTO urls (slug, url, author_ip, usage_count) \
+             VALUES ('abc', 'https://example.com', '::1', 10)",
+        )
+        .execute(&mut conn)
+        .await
+        .unwrap();
+        pool
+    }
+
+    async fn usage_count(pool: &db::Pool, slug: &str) -> i32 {
+        let mut conn = db::get(pool).await.unwrap();
+        urls::table
+            .find(slug)
+            .select(urls::usage_count)
+            .first(&mut conn)
+            .await
+            .unwrap()
+    }
+
+    async fn correct(
+        tally: &UsageTally,
+        pool: &db::Pool,
+        slug: &str,
+        adjustment: UsageAdjustment,
+    ) -> Result<Url, UrlErr> {
+        let (reply, answer) = oneshot::channel();
+        let event = Adjust {
+            slug: slug.into(),
+            case_insensitive: false,
+            adjustment,
+            reply,
+        };
+        adjust(tally, pool, event).await;
+        answer.await.unwrap()
+    }
+
+    #[test]
+    fn tally() {
+        let tally = UsageTally::new(4);
+        assert_eq!(tally.pending("abc"), 0);
+
+        // spread over the shards at random, but always adding up
+        for _ in 0..100 {
+            tally.push("abc", None);
+        }
+        tally.push("xyz", Some(click("xyz")));
+        tally.add("xyz", 2);
+        assert_eq!(tally.pending("abc"), 100);
+        assert_eq!(tally.pending("xyz"), 3);
+        assert_eq!(tally.version(), 102);
+        assert_eq!(tally.clicks.lock().unwrap().len(), 1);
+
+        assert_eq!(tally.discard("xyz"), 3);
+        assert_eq!(tally.pending("xyz"), 0);
+
+        let totals = tally.take();
+        assert_eq!(totals, HashMap::from([("abc".to_string(), 100)]));
+        assert_eq!(tally.pending("abc"), 0);
+        assert!(tally.take().is_empty());
+    }
+
+    #[test]
+    fn at_least_one_shard() {
+        let tally = UsageTally::new(0);
+        tally.add("abc", 1);
+        assert_eq!(tally.pending("abc"), 1);
+    }
+
+    #[tokio::test]
+    async fn flushes() {
+        let pool = pool().await;
+        let tally = UsageTally::new(4);
+        for _ in 0..5 {
+            tally.push("abc", Some(click("abc")));
+        }
+        flush(&tally, &pool, None).await;
+
+        assert_eq!(tally.pending("abc"), 0);
+        assert!(tally.clicks.lock().unwrap().is_empty());
+        assert_eq!(usage_count(&pool, "abc").await, 15);
+        let mut conn = db::get(&pool).await.unwrap();
+        let clicks = clicks::table.count().get_result::<i64>(&mut conn).await;
+        assert_eq!(clicks.unwrap(), 5);
+    }
+
+    #[tokio::test]
+    async fn adjusts_with_pending_clicks() {
+        let pool = pool().await;
+        let tally = UsageTally::new(4);
+        tally.add("abc", 5);
+
+        // the pending clicks are part of what is subtracted from, and are gone after
+        let url = correct(
+            &tally,
+            &pool,
+            "abc",
+            UsageAdjustment::Subtract { amount: 3 },
+        )
+        .await;
+        assert_eq!(url.unwrap().usage_count, 12);
+        assert_eq!(tally.pending("abc"), 0);
+
+        // never below zero
+        let url = correct(
+            &tally,
+            &pool,
+            "abc",
+            UsageAdjustment::Subtract { amount: 99 },
+        )
+        .await;
+        assert_eq!(url.unwrap().usage_count, 0);
+
+        tally.add("abc", 4);
+        let url = correct(&tally, &pool, "abc", UsageAdjustment::Reset).await;
+        assert_eq!(url.unwrap().usage_count, 0);
+        assert_eq!(tally.pending("abc"), 0);
+        assert_eq!(usage_count(&pool, "abc").await, 0);
+    }
+
+    #[tokio::test]
+    async fn adjusts_missing() {
+        let pool = pool().await;
+        let tally = UsageTally::new(4);
+        tally.add("abc", 5);
+        let url = correct(&tally, &pool, "nope", UsageAdjustment::Reset).await;
+        assert!(matches!(url, Err(UrlErr::NotFound)));
+        assert_eq!(tally.pending("abc"), 5);
+    }
+}